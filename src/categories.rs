@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use crate::definitions::ImplicitLayer;
+
+/// What kind of software an implicit layer belongs to
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerCategory {
+    Overlay,
+    Capture,
+    PerformanceHud,
+    /// Layers that are installed by the graphics driver
+    Driver,
+    /// Layers that launchers use to cache shaders, like Steam Fossilize
+    LauncherCache,
+    AntiCheat,
+    /// Layers for developers, like the validation layers
+    DeveloperTool,
+    Unknown
+}
+
+impl LayerCategory {
+    /// Disabling or breaking layers of these categories can stop games from launching, or get
+    /// accounts flagged by anti-cheat software
+    pub fn is_high_risk(self) -> bool {
+        matches!(self, Self::Driver | Self::LauncherCache | Self::AntiCheat)
+    }
+
+    /// Explains why disabling a layer of this category is risky
+    fn risk(self) -> &'static str {
+        match self {
+            Self::Driver => "it was installed by your graphics driver, which may need it",
+            Self::LauncherCache => "a game launcher may need it to launch games",
+            Self::AntiCheat => "anti-cheat software may refuse to launch games, or flag your account, without it",
+            _ => "it may be needed"
+        }
+    }
+}
+
+impl fmt::Display for LayerCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Overlay => "overlay",
+            Self::Capture => "capture",
+            Self::PerformanceHud => "performance HUD",
+            Self::Driver => "driver",
+            Self::LauncherCache => "launcher cache",
+            Self::AntiCheat => "anti-cheat",
+            Self::DeveloperTool => "developer tool",
+            Self::Unknown => "unknown"
+        })
+    }
+}
+
+/// The keywords of each category, in the order in which they are checked. They are matched
+/// against the lowercase name and description of a layer.
+const CATEGORY_KEYWORDS: [(LayerCategory, &[&str]); 6] = [
+    (LayerCategory::AntiCheat, &["anticheat", "anti-cheat", "anti_cheat", "battleye", "vanguard", "faceit"]),
+    (LayerCategory::LauncherCache, &["fossilize", "shader cache", "pipeline cache", "shader_cache"]),
+    (LayerCategory::PerformanceHud, &["rtss", "rivatuner", "afterburner", "mangohud", "hud", "fps", "frame rate", "statistics"]),
+    (LayerCategory::Capture, &["capture", "record", "_obs_", "obs studio", "obs-studio", "broadcast", "bandicam", "stream"]),
+    (LayerCategory::Overlay, &["overlay"]),
+    (LayerCategory::DeveloperTool, &["validation", "renderdoc", "api_dump", "nsight", "profiler", "debug", "trace", "lunarg", "khronos"])
+];
+
+/// The layer name prefixes of GPU vendors, whose layers are usually installed by the driver
+const DRIVER_PREFIXES: [&str; 3] = ["vk_layer_nv_", "vk_layer_amd_", "vk_layer_intel_"];
+
+/// The GPU vendors and brands whose layers are usually installed by the driver. They are matched
+/// against the words of the lowercase name and description of a layer.
+const DRIVER_VENDORS: [&str; 5] = ["nvidia", "geforce", "amd", "radeon", "intel"];
+
+/// Guesses the category of the layer from its name, description, vendor, and manifest path. The
+/// `overrides` map layer names (ignoring case) to categories, and take precedence. Driver layers
+/// are recognized first, since their names and descriptions often contain the keywords of other
+/// categories (like "hud" or "debug"), and misclassifying them would skip the risk warning.
+pub fn classify(layer: &ImplicitLayer, overrides: &HashMap<String, LayerCategory>) -> LayerCategory {
+    if let Some((_, category)) = overrides.iter().find(|(name, _)| name.eq_ignore_ascii_case(&layer.name)) {
+        return *category;
+    }
+    let name = layer.name.to_lowercase();
+    let text = format!("{} {}", name, layer.description.to_lowercase());
+    let mentions_driver_vendor = text.split(|character: char| !character.is_ascii_alphanumeric()).any(
+        |word| DRIVER_VENDORS.contains(&word)
+    );
+    if DRIVER_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) || mentions_driver_vendor ||
+        layer.settings_path.to_lowercase().contains("driverstore") {
+        return LayerCategory::Driver;
+    }
+    for (category, keywords) in CATEGORY_KEYWORDS {
+        if keywords.iter().any(|keyword| text.contains(keyword)) {
+            return category;
+        }
+    }
+    LayerCategory::Unknown
+}
+
+/// Describes the high-risk layers among `layers`, or returns `None` when none of them is risky.
+/// The GUI asks for confirmation before it disables or breaks them.
+pub fn risk_warning(layers: &[&ImplicitLayer], overrides: &HashMap<String, LayerCategory>) -> Option<String> {
+    let risky_layers: Vec<String> = layers.iter().filter_map(|layer| {
+        let category = classify(layer, overrides);
+        if category.is_high_risk() {
+            Some(format!("{} is a {} layer: {}", layer.name, category, category.risk()))
+        } else { None }
+    }).collect();
+    if risky_layers.is_empty() {
+        None
+    } else { Some(risky_layers.join("\n")) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definitions::ImplicitRegistry;
+
+    fn layer(name: &str, description: &str) -> ImplicitLayer {
+        ImplicitLayer {
+            settings_path: format!("C:\\Program Files\\{}.json", name),
+            registry: ImplicitRegistry::LocalMachine,
+            description: description.to_string(),
+            ..ImplicitLayer::test(name)
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let no_overrides = HashMap::new();
+        for (name, description, category) in [
+            ("VK_LAYER_VALVE_steam_fossilize_64", "Steam Pipeline Caching Layer", LayerCategory::LauncherCache),
+            ("VK_LAYER_VALVE_steam_overlay_64", "Steam Overlay Layer", LayerCategory::Overlay),
+            ("VK_LAYER_OBS_HOOK", "Open Broadcaster Software hook", LayerCategory::Capture),
+            ("VK_LAYER_RTSS", "RTSS overlay hook bootstrap", LayerCategory::PerformanceHud),
+            ("VK_LAYER_NV_optimus", "NVIDIA Optimus layer", LayerCategory::Driver),
+            ("VK_LAYER_KHRONOS_validation", "LunarG Validation Layer", LayerCategory::DeveloperTool),
+            ("VK_LAYER_EOS_anticheat", "Game protection", LayerCategory::AntiCheat),
+            ("VK_LAYER_mystery", "Does things", LayerCategory::Unknown),
+            // Driver layers often contain the keywords of other categories
+            ("VK_LAYER_NV_present", "NVIDIA GR2608 layer for FPS overlay", LayerCategory::Driver),
+            ("VK_LAYER_AMD_switchable_graphics_64", "AMD switchable graphics debug layer", LayerCategory::Driver),
+            ("VK_LAYER_INTEL_trace", "Stream tracing", LayerCategory::Driver),
+            ("VK_LAYER_helper", "Radeon Software hud helper", LayerCategory::Driver),
+            ("VK_LAYER_stamd", "Does things", LayerCategory::Unknown)
+        ] {
+            assert_eq!(category, classify(&layer(name, description), &no_overrides), "{}", name);
+        }
+
+        // Only the DriverStore part of the manifest path is used, not its keywords
+        let mut in_driver_store = layer("VK_LAYER_mystery", "Overlay");
+        in_driver_store.settings_path = "C:\\Windows\\System32\\DriverStore\\FileRepository\\mystery.json".to_string();
+        assert_eq!(LayerCategory::Driver, classify(&in_driver_store, &no_overrides));
+        let mut in_hud_directory = layer("VK_LAYER_mystery", "Does things");
+        in_hud_directory.settings_path = "C:\\Program Files\\Debug HUD\\mystery.json".to_string();
+        assert_eq!(LayerCategory::Unknown, classify(&in_hud_directory, &no_overrides));
+
+        let overrides = HashMap::from([("vk_layer_mystery".to_string(), LayerCategory::AntiCheat)]);
+        assert_eq!(LayerCategory::AntiCheat, classify(&layer("VK_LAYER_mystery", "Does things"), &overrides));
+    }
+
+    #[test]
+    fn test_risk_warning() {
+        let overlay = layer("VK_LAYER_VALVE_steam_overlay_64", "Steam Overlay Layer");
+        let driver = layer("VK_LAYER_AMD_switchable_graphics", "AMD switchable graphics layer");
+        let no_overrides = HashMap::new();
+        assert_eq!(None, risk_warning(&[&overlay], &no_overrides));
+        assert_eq!(
+            Some("VK_LAYER_AMD_switchable_graphics is a driver layer: it was installed by your graphics driver, which may need it".to_string()),
+            risk_warning(&[&overlay, &driver], &no_overrides)
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env::var_os;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use crate::categories::LayerCategory;
+use crate::definitions::{ImplicitLayer, Requirements};
+use crate::test_app::is_known_feature;
+
+const REQUIRE_INSTANCE_EXTENSION: &str = "--require-instance-extension";
+const REQUIRE_DEVICE_EXTENSION: &str = "--require-device-extension";
+const REQUIRE_FEATURE: &str = "--require-feature";
+
+/// The directory where vk-fixer stores its configuration and profiles: `%APPDATA%\vk-fixer` on
+/// Windows and `~/.config/vk-fixer` (or `$XDG_CONFIG_HOME/vk-fixer`) elsewhere
+pub fn config_directory() -> Option<PathBuf> {
+    let config_directory = var_os("APPDATA").map(PathBuf::from)
+        .or_else(|| var_os("XDG_CONFIG_HOME").map(PathBuf::from))
+        .or_else(|| var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_directory.join("vk-fixer"))
+}
+
+/// The settings of vk-fixer, which are stored in `config.json` in the config directory
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Layers that make instance and device creation slower than this (in milliseconds) are
+    /// flagged in the trial results
+    pub overhead_threshold_ms: u64,
+    /// The extensions and features that the trials need, in addition to the ones that are given
+    /// on the command line
+    pub requirements: Requirements,
+    /// The names of the layers that the user needs, like the OBS capture layer. The fix solver
+    /// never proposes to disable them.
+    pub kept_layers: Vec<String>,
+    /// Overrides the category of layers (by name, ignoring case) when vk-fixer guesses it wrong
+    pub layer_categories: HashMap<String, LayerCategory>
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { overhead_threshold_ms: 20, requirements: Requirements::default(), kept_layers: Vec::new(), layer_categories: HashMap::new() }
+    }
+}
+
+impl Config {
+    /// Loads the config from the given file, or returns the default config when it doesn't exist
+    pub fn load_from(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(
+                |parse_error| format!("Failed to parse {}: {}", path.display(), parse_error)
+            ),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(format!("Failed to read {}: {}", path.display(), error))
+        }
+    }
+
+    /// Loads the config from the config directory. When that fails, the default config is used,
+    /// and the error is returned as well.
+    pub fn load() -> (Self, Option<String>) {
+        match config_directory() {
+            Some(directory) => match Self::load_from(&directory.join("config.json")) {
+                Ok(config) => (config, None),
+                Err(error) => (Self::default(), Some(error))
+            },
+            None => (Self::default(), None)
+        }
+    }
+
+    /// Whether `layer` is one of the `kept_layers` (ignoring case)
+    pub fn is_kept(&self, layer: &ImplicitLayer) -> bool {
+        self.kept_layers.iter().any(|name| name.eq_ignore_ascii_case(&layer.name))
+    }
+
+    /// The requirements of the config, combined with the `--require-...` arguments that were
+    /// passed to vk-fixer. Other arguments are ignored. Problems with either are returned as
+    /// errors, and invalid names are left out.
+    pub fn trial_requirements(&self) -> (Requirements, Vec<String>) {
+        let mut requirements = self.requirements.clone();
+        let mut errors = Vec::new();
+        match Requirements::parse_known_args(&std::env::args().skip(1).collect::<Vec<_>>()) {
+            Ok(from_args) => requirements.extend(from_args),
+            Err(error) => errors.push(format!("Ignoring the required extensions and features of the command line: {}", error))
+        }
+        errors.extend(requirements.remove_invalid_names());
+        (requirements, errors)
+    }
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        self.instance_extensions.is_empty() && self.device_extensions.is_empty() && self.features.is_empty()
+    }
+
+    /// Parses `--require-instance-extension <name>`, `--require-device-extension <name>`, and
+    /// `--require-feature <name>` arguments
+    pub fn parse_args(args: &[String]) -> Result<Self, String> {
+        Self::parse(args, false)
+    }
+
+    /// Like `parse_args`, but skips all other arguments
+    pub fn parse_known_args(args: &[String]) -> Result<Self, String> {
+        Self::parse(args, true)
+    }
+
+    fn parse(args: &[String], skip_unknown: bool) -> Result<Self, String> {
+        let mut requirements = Self::default();
+        let mut remaining = args.iter();
+        while let Some(flag) = remaining.next() {
+            let list = match flag.as_str() {
+                REQUIRE_INSTANCE_EXTENSION => &mut requirements.instance_extensions,
+                REQUIRE_DEVICE_EXTENSION => &mut requirements.device_extensions,
+                REQUIRE_FEATURE => &mut requirements.features,
+                _ if skip_unknown => continue,
+                unknown => return Err(format!("Unexpected argument {}", unknown))
+            };
+            list.push(remaining.next().ok_or(format!("Missing name after {}", flag))?.clone());
+        }
+        Ok(requirements)
+    }
+
+    /// Removes the features that the test app doesn't know, and the extension names that don't
+    /// look like `VK_<name>`, and returns an error for each of them. Every trial would fail
+    /// because of them, which would look like Vulkan is broken.
+    pub fn remove_invalid_names(&mut self) -> Vec<String> {
+        let is_extension_name = |name: &str| name.len() > 3 && name.starts_with("VK_") && name.chars().all(
+            |character| character.is_ascii_alphanumeric() || character == '_'
+        );
+        let mut errors = Vec::new();
+        let mut check = |kind: &str, list: &mut Vec<String>, is_valid: &dyn Fn(&str) -> bool| list.retain(|name| {
+            if !is_valid(name) {
+                errors.push(format!("Ignoring the required {} {}, since it doesn't exist", kind, name));
+            }
+            is_valid(name)
+        });
+        check("instance extension", &mut self.instance_extensions, &is_extension_name);
+        check("device extension", &mut self.device_extensions, &is_extension_name);
+        check("feature", &mut self.features, &is_known_feature);
+        errors
+    }
+
+    /// The inverse of `parse_args`
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (flag, names) in [
+            (REQUIRE_INSTANCE_EXTENSION, &self.instance_extensions),
+            (REQUIRE_DEVICE_EXTENSION, &self.device_extensions),
+            (REQUIRE_FEATURE, &self.features)
+        ] {
+            for name in names {
+                args.push(flag.to_string());
+                args.push(name.clone());
+            }
+        }
+        args
+    }
+
+    /// Adds all requirements of `other` that this doesn't have yet
+    pub fn extend(&mut self, other: Requirements) {
+        for (list, names) in [
+            (&mut self.instance_extensions, other.instance_extensions),
+            (&mut self.device_extensions, other.device_extensions),
+            (&mut self.features, other.features)
+        ] {
+            for name in names {
+                if !list.contains(&name) {
+                    list.push(name);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_config() {
+        let directory = std::env::temp_dir().join(format!("vk-fixer-config-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.json");
+
+        assert_eq!(Ok(Config::default()), Config::load_from(&path));
+        fs::write(&path, r#"{ "overhead_threshold_ms": 5 }"#).unwrap();
+        assert_eq!(Ok(Config { overhead_threshold_ms: 5, ..Default::default() }), Config::load_from(&path));
+        fs::write(&path, r#"{ "kept_layers": ["VK_LAYER_OBS_HOOK"] }"#).unwrap();
+        assert_eq!(vec!["VK_LAYER_OBS_HOOK".to_string()], Config::load_from(&path).unwrap().kept_layers);
+        fs::write(&path, r#"{ "layer_categories": { "VK_LAYER_mine": "anti_cheat" } }"#).unwrap();
+        assert_eq!(
+            HashMap::from([("VK_LAYER_mine".to_string(), LayerCategory::AntiCheat)]),
+            Config::load_from(&path).unwrap().layer_categories
+        );
+        fs::write(&path, r#"{ "requirements": { "device_extensions": ["VK_KHR_dynamic_rendering"] } }"#).unwrap();
+        assert_eq!(
+            vec!["VK_KHR_dynamic_rendering".to_string()],
+            Config::load_from(&path).unwrap().requirements.device_extensions
+        );
+        fs::write(&path, "{}").unwrap();
+        assert_eq!(Ok(Config::default()), Config::load_from(&path));
+        fs::write(&path, "[").unwrap();
+        assert!(Config::load_from(&path).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_requirement_args() {
+        let args: Vec<String> = [
+            "--require-device-extension", "VK_KHR_dynamic_rendering", "--require-feature", "samplerAnisotropy",
+            "--require-device-extension", "VK_EXT_descriptor_indexing"
+        ].iter().map(|arg| arg.to_string()).collect();
+        let requirements = Requirements::parse_args(&args).unwrap();
+        assert_eq!(Requirements {
+            instance_extensions: vec![],
+            device_extensions: vec!["VK_KHR_dynamic_rendering".to_string(), "VK_EXT_descriptor_indexing".to_string()],
+            features: vec!["samplerAnisotropy".to_string()]
+        }, requirements);
+        assert_eq!(Ok(requirements.clone()), Requirements::parse_args(&requirements.to_args()));
+
+        let mut extended = requirements.clone();
+        extended.extend(Requirements { features: vec!["samplerAnisotropy".to_string(), "dynamicRendering".to_string()], ..Default::default() });
+        assert_eq!(vec!["samplerAnisotropy".to_string(), "dynamicRendering".to_string()], extended.features);
+
+        assert!(Requirements::parse_args(&["--require-feature".to_string()]).is_err());
+        assert!(Requirements::parse_args(&["--whatever".to_string()]).is_err());
+        assert!(Requirements::parse_args(&[]).unwrap().is_empty());
+
+        let mixed_args: Vec<String> = ["--whatever", "--require-feature", "samplerAnisotropy", "game.exe"].iter().map(
+            |arg| arg.to_string()
+        ).collect();
+        assert_eq!(vec!["samplerAnisotropy".to_string()], Requirements::parse_known_args(&mixed_args).unwrap().features);
+        assert!(Requirements::parse_known_args(&["--require-feature".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_remove_invalid_names() {
+        let mut requirements = Requirements {
+            instance_extensions: vec!["VK_KHR_surface".to_string(), "surface".to_string()],
+            device_extensions: vec!["VK_KHR_swapchain".to_string(), "VK_KHR swapchain".to_string()],
+            features: vec!["textureCompressionBC".to_string(), "textureCompressionBc".to_string()]
+        };
+        assert_eq!(vec![
+            "Ignoring the required instance extension surface, since it doesn't exist".to_string(),
+            "Ignoring the required device extension VK_KHR swapchain, since it doesn't exist".to_string(),
+            "Ignoring the required feature textureCompressionBc, since it doesn't exist".to_string()
+        ], requirements.remove_invalid_names());
+        assert_eq!(Requirements {
+            instance_extensions: vec!["VK_KHR_surface".to_string()],
+            device_extensions: vec!["VK_KHR_swapchain".to_string()],
+            features: vec!["textureCompressionBC".to_string()]
+        }, requirements);
+    }
+}
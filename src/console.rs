@@ -0,0 +1,26 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the output of `println!` and `eprintln!` ends up somewhere
+static HAS_CONSOLE: AtomicBool = AtomicBool::new(!cfg!(windows));
+
+/// vk-fixer is a GUI application on Windows, so it doesn't get a console of its own. This attaches
+/// it to the console of the terminal that started it (if any), so that the output of the command
+/// line modes (like `vk-fixer diagnose`) is visible.
+pub fn attach_parent_console() {
+    #[cfg(windows)]
+    {
+        use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        if unsafe { AttachConsole(ATTACH_PARENT_PROCESS) }.is_ok() {
+            HAS_CONSOLE.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Prints the error to stderr, and also shows it in a message box when vk-fixer has no console,
+/// for instance when Steam runs it as launch option
+pub fn report_error(error: &str) {
+    eprintln!("{}", error);
+    if !HAS_CONSOLE.load(Ordering::Relaxed) {
+        nwg::error_message("vk-fixer", error);
+    }
+}
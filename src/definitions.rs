@@ -1,22 +1,176 @@
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum ImplicitRegistry {
     CurrentUser, LocalMachine
 }
 
+/// Determines which environment variables are modified when a layer is disabled or enabled
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EnvironmentScope {
+    /// Only affects the current user (`HKCU\Environment`)
+    User,
+    /// Affects all users of the machine, which requires administrator privileges
+    System
+}
+
+/// Determines how layers are disabled, both during the trials and when the user disables them
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
+pub enum DisableMethod {
+    /// Set the `disable_environment` variable of each layer
+    #[default]
+    DisableEnvironment,
+    /// Add the layer names to `VK_LOADER_LAYERS_DISABLE`, which requires Vulkan loader 1.3.234 or later
+    LoaderFilter
+}
+
+/// The instance extensions, device extensions, and device features that the test app must enable.
+/// Trials fail when any of them is unavailable.
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Requirements {
+    pub instance_extensions: Vec<String>,
+    pub device_extensions: Vec<String>,
+    /// The names of the device features, like `samplerAnisotropy` or `dynamicRendering`
+    pub features: Vec<String>
+}
+
+/// Determines how the trials are run
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct TrialOptions {
+    pub method: DisableMethod,
+    /// Whether `VK_INSTANCE_LAYERS` and the other layer variables are cleared during the trials
+    pub clear_layer_variables: bool,
+    pub requirements: Requirements,
+    /// Whether all trials are run, even the ones that can't change the conclusion. This is
+    /// slower, but shows the overhead and extensions of every layer.
+    pub exhaustive: bool
+}
+
+/// Uniquely identifies an implicit layer, even when multiple layers have the same name
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct LayerId {
+    pub registry: ImplicitRegistry,
+    pub settings_path: String,
+    /// The index of the layer in the `layers` array of its manifest, or 0 when the manifest uses
+    /// `layer` instead
+    pub manifest_index: usize
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ImplicitLayer {
     pub settings_path: String,
     pub registry: ImplicitRegistry,
+    /// The index of the layer in the `layers` array of its manifest, or 0 when the manifest uses
+    /// `layer` instead
+    pub manifest_index: usize,
+    /// The DWORD value of the layer in the registry: the loader ignores the layer unless it is 0
+    pub registry_value: u32,
     pub name: String,
     pub description: String,
     pub disable_environment: String,
-    pub enable_environment: Option<String>
+    /// The key and value of the `enable_environment` of the layer. When present, the loader only
+    /// loads the layer when this environment variable has exactly this value.
+    pub enable_environment: Option<(String, String)>,
+    /// When another manifest declares a layer with the same name, and the loader picks that other
+    /// manifest, this is the settings path of that other manifest
+    pub shadowed_by: Option<String>,
+    /// The `library_path` of the manifest. When it contains a path separator, it is relative to
+    /// the manifest. Meta-layers don't have a library.
+    pub library_path: Option<String>,
+    /// The `implementation_version` of the manifest, which is the version of the layer itself
+    pub implementation_version: Option<String>,
+    /// The `api_version` of the manifest, which is the Vulkan version that the layer was written
+    /// for, like "1.3.250"
+    pub api_version: Option<String>,
+    /// When this layer is a meta-layer, this contains the layers it enables and disables
+    pub meta_layer: Option<MetaLayer>
+}
+
+/// A layer that is only loaded when an application (or `VK_INSTANCE_LAYERS`) explicitly enables it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExplicitLayer {
+    pub settings_path: String,
+    pub registry: ImplicitRegistry,
+    pub name: String,
+    pub description: String
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct MetaLayer {
+    /// The names of the layers that are enabled whenever this meta-layer is enabled
+    pub component_layers: Vec<String>,
+    /// The names of the layers that are disabled whenever this meta-layer is enabled, which is
+    /// used by the override layer of Vulkan Configurator
+    pub blacklisted_layers: Vec<String>
+}
+
+impl ImplicitLayer {
+    pub fn id(&self) -> LayerId {
+        LayerId {
+            registry: self.registry,
+            settings_path: self.settings_path.clone(),
+            manifest_index: self.manifest_index
+        }
+    }
+}
+
+#[cfg(test)]
+impl ImplicitLayer {
+    /// A layer for tests, which override the fields they care about with `..ImplicitLayer::test(name)`.
+    /// Its manifest is `<name>.json` in the user registry, and its `disable_environment` is
+    /// `DISABLE_<name>`.
+    pub fn test(name: &str) -> Self {
+        Self {
+            settings_path: format!("{}.json", name),
+            registry: ImplicitRegistry::CurrentUser,
+            manifest_index: 0,
+            registry_value: 0,
+            name: name.to_string(),
+            description: "".to_string(),
+            disable_environment: format!("DISABLE_{}", name),
+            enable_environment: None,
+            shadowed_by: None,
+            library_path: None,
+            implementation_version: None,
+            api_version: None,
+            meta_layer: None
+        }
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 pub struct TrialResult {
     pub exit_code: i32,
-    pub output: String
+    pub output: String,
+    pub timings: TrialTimings,
+    pub capabilities: TrialCapabilities
+}
+
+/// The layers and extensions that the test app saw during a trial. A list is empty when the test
+/// app failed before it could enumerate it.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct TrialCapabilities {
+    pub instance_layers: Vec<String>,
+    pub instance_extensions: Vec<String>,
+    pub device_extensions: Vec<String>
+}
+
+/// The time that each stage of the test app took. A stage is `None` when it wasn't reached.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct TrialTimings {
+    pub load_loader: Option<Duration>,
+    pub create_instance: Option<Duration>,
+    pub create_device: Option<Duration>
+}
+
+impl TrialTimings {
+    /// The time spent in `vkCreateInstance` and `vkCreateDevice`, which is where layers add overhead
+    pub fn creation_time(&self) -> Option<Duration> {
+        Some(self.create_instance? + self.create_device?)
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
@@ -71,18 +225,207 @@ pub struct TestResults {
     pub clean_result: VersionedTrialResults,
     /// The results of running the test app, where a different implicit layer is blocked in
     /// each trial
-    pub exclude_results: Vec<(String, VersionedTrialResults)>,
+    pub exclude_results: Vec<(LayerId, VersionedTrialResults)>,
     /// The result of running the test app, where all implicit layers are blocked, except 1 in
     /// each trial
-    pub isolation_results: Vec<(String, VersionedTrialResults)>
+    pub isolation_results: Vec<(LayerId, VersionedTrialResults)>,
+    /// The trials that were not run, because their results couldn't change the conclusion. They
+    /// are not in `exclude_results` and `isolation_results`.
+    pub skipped_trials: Vec<TrialKind>
 }
 
 pub struct EnvironmentVariables {
-    pub user: Vec<String>,
-    pub system: Vec<String>,
+    /// The environment variables of this process, which may be outdated
+    pub process: HashMap<String, String>,
+    /// The persistent environment variables of the current user
+    pub user: HashMap<String, String>,
+    /// The persistent environment variables of the system
+    pub system: HashMap<String, String>,
+    /// The active loader settings file (if any), which can override the environment variables
+    pub loader_settings: Option<LoaderSettings>,
     pub errors: Vec<String>
 }
 
+/// How a loader settings file controls a layer
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LayerControl {
+    /// The layer is always enabled
+    On,
+    /// The layer is always disabled
+    Off,
+    /// The layer is enabled or disabled by the usual rules (like its `disable_environment`)
+    Auto
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LoaderSettingsLayer {
+    pub name: String,
+    /// The path to the manifest of the layer, if the settings file specifies it
+    pub path: Option<String>,
+    pub control: LayerControl
+}
+
+/// The global layer configuration of a `vk_loader_settings.json` file, which is normally written
+/// by Vulkan Configurator
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LoaderSettings {
+    pub path: String,
+    pub layers: Vec<LoaderSettingsLayer>,
+    /// When this is false, the loader won't load any layer that is not listed in `layers`
+    pub has_unordered_layer_location: bool,
+    /// Whether this settings file was written by vk-fixer
+    pub managed_by_vk_fixer: bool
+}
+
+impl EnvironmentVariables {
+    /// Finds the value of the given environment variable, and where it comes from. The persistent
+    /// variables take precedence because they will be used by the next game that is launched.
+    pub fn lookup(&self, key: &str) -> Option<(VariableSource, &str)> {
+        [VariableSource::User, VariableSource::System, VariableSource::Process].into_iter().find_map(
+            |source| self.lookup_in(source, key).map(|value| (source, value))
+        )
+    }
+
+    /// Finds the value of the given environment variable in only the given source
+    pub fn lookup_in(&self, source: VariableSource, key: &str) -> Option<&str> {
+        find_ignoring_case(match source {
+            VariableSource::Process => &self.process,
+            VariableSource::User => &self.user,
+            VariableSource::System => &self.system
+        }, key)
+    }
+}
+
+// Environment variable names are case-insensitive on Windows
+fn find_ignoring_case<'a>(variables: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    variables.iter().find(|(candidate, _)| candidate.eq_ignore_ascii_case(key)).map(|(_, value)| value.as_str())
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum VariableSource {
+    /// The environment variable is only set in the environment of this process
+    Process,
+    /// The environment variable is persisted for the current user
+    User,
+    /// The environment variable is persisted for all users
+    System
+}
+
+/// Whether the loader will load an implicit layer, and why
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EffectiveState {
+    pub enabled: bool,
+    pub reason: StateReason
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StateReason {
+    /// The layer doesn't have an `enable_environment`, and nothing disables it
+    EnabledByDefault,
+    /// The registry value of the layer is not 0, so the loader ignores it
+    RegistryValue(u32),
+    /// Another manifest declares a layer with the same name, and the loader prefers that one
+    ShadowedBy { settings_path: String },
+    /// The `disable_environment` of the layer is set
+    DisableVariable { key: String, source: VariableSource },
+    /// The `enable_environment` of the layer is set to the right value
+    EnableVariable { key: String, source: VariableSource },
+    /// The layer has an `enable_environment` that is not set (to the right value)
+    MissingEnableVariable { key: String, value: String },
+    /// One of the loader filter variables (like `VK_LOADER_LAYERS_DISABLE`) matches the layer
+    LoaderFilter { variable: String, source: VariableSource },
+    /// The loader settings file forces the layer on or off
+    LoaderSettings { settings_path: String },
+    /// The loader settings file doesn't list the layer, and has no `unordered_layer_location`
+    MissingFromLoaderSettings { settings_path: String },
+    /// The layer is a component of an enabled meta-layer, so the loader enables it anyway
+    PulledInByMetaLayer { meta_layer: String },
+    /// The layer is blacklisted by an enabled meta-layer
+    BlacklistedByMetaLayer { meta_layer: String }
+}
+
+/// The Vulkan versions for which the trials are run
+pub const ALL_API_VERSIONS: [u32; 4] = [
+    ash::vk::API_VERSION_1_0, ash::vk::API_VERSION_1_1, ash::vk::API_VERSION_1_2, ash::vk::API_VERSION_1_3
+];
+
+/// Identifies one of the trials in `TestResults`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TrialKind {
+    Default,
+    Clean,
+    Exclude(LayerId),
+    Isolation(LayerId),
+    /// A trial that the fix solver planned, which disabled only these layers
+    Extra(Vec<LayerId>)
+}
+
+/// One step of the reasoning that led to a `Conclusion`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TraceStep {
+    /// A trial that was decisive for the conclusion, and the Vulkan versions for which it
+    /// succeeded and failed
+    Trial { trial: TrialKind, succeeded_versions: Vec<u32>, failed_versions: Vec<u32> },
+    /// No trial succeeded for these Vulkan versions, so they are ignored
+    UnsupportedVersions(Vec<u32>),
+    /// An observation about multiple trials, like "all isolation trials succeeded"
+    Note(String)
+}
+
+/// A way to resolve a finding by disabling layers
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Solution {
+    pub disabled_layers: Vec<LayerId>,
+    /// For conflicts, the layer that stays enabled while `disabled_layers` are disabled
+    pub kept_layer: Option<LayerId>
+}
+
+impl Solution {
+    pub fn disable(layer: &LayerId) -> Self {
+        Self { disabled_layers: vec![layer.clone()], kept_layer: None }
+    }
+}
+
+/// A trial that the fix solver planned, in which only `disabled_layers` (and the meta-layers
+/// that contain them) were disabled
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExtraTrial {
+    pub disabled_layers: Vec<LayerId>,
+    pub results: VersionedTrialResults
+}
+
+/// The outcome of the fix solver, which looks for the fewest layers to disable without disabling
+/// any of the layers that the user wants to keep
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FixPlan {
+    /// Any of these solutions makes the trials succeed. They all disable the same number of
+    /// layers, and no smaller solution exists.
+    Solved(Vec<Solution>),
+    /// The trials don't tell whether disabling any of these sets of layers would be enough, so
+    /// more trials are needed
+    NeedsTrials(Vec<Vec<LayerId>>),
+    /// Even disabling all other layers doesn't help, so the kept layers themselves are broken
+    Impossible
+}
+
+/// The layers that the user wants to keep, what the fix solver found for them, and the extra
+/// trials that it needed
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct KeptLayersFix {
+    pub kept_layers: Vec<LayerId>,
+    pub plan: FixPlan,
+    pub extra_trials: Vec<ExtraTrial>
+}
+
+/// One of the problems that the trials revealed, the reasoning that led to it, and the ways to
+/// resolve it. Any one of the `solutions` is enough to resolve the finding.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Finding {
+    pub conclusion: Conclusion,
+    pub trace: Vec<TraceStep>,
+    pub solutions: Vec<Solution>
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Default)]
 pub enum Conclusion {
     /// All trials succeeded, so the implicit layers are probably fine.
@@ -95,23 +438,59 @@ pub enum Conclusion {
     Hopeless,
     /// Both the default trial (with all layers) and the clean trial (without any layers) failed,
     /// but not all trials failed.
-    WeirdBroken { important_layer: String, exclude: bool },
+    WeirdBroken { important_layer: LayerId, exclude: bool },
     /// All trials for some Vulkan version(s) failed, but all others succeeded.
     /// This probably means that the graphics drivers don't support later versions.
     Partial { supported_versions: Vec<u32> },
     /// One of the implicit layers appears to be completely broken (even when all other layers
     /// are disabled).
-    BrokenLayer { layer: String },
+    BrokenLayer { layer: LayerId },
     /// It looks like one of the implicit layers only supports a subset of the Vulkan versions
     /// supported by the graphics drivers.
-    PartiallyBrokenLayer { layer: String, broken_versions: Vec<u32> },
+    PartiallyBrokenLayer { layer: LayerId, broken_versions: Vec<u32> },
     /// Multiple layers are conflicting: the trials succeed when at least 1 of them is disabled.
-    SymmetricConflict { layers: Vec<String> },
+    SymmetricConflict { layers: Vec<LayerId> },
     /// One layer conflicts with multiple other layers: all trials where both `main_offender` and
     /// another layer were enabled failed. All trials without `main_offender` and with only
     /// `main_offender` succeeded.
-    AsymmetricConflict { main_offender: String },
+    AsymmetricConflict { main_offender: LayerId },
     /// Multiple layers are conflicting with each other. All layers work fine in isolation, but
     /// all trials with more than 1 active layer failed.
     ComplexConflict,
+    /// All trials succeeded, but `layer` hides extensions that are available without it: the
+    /// trials without any layers and with only `layer` disabled saw these extensions, whereas
+    /// the trials with `layer` enabled didn't.
+    HiddenExtensions { layer: LayerId, instance_extensions: Vec<String>, device_extensions: Vec<String> },
+    /// The trial results don't match any known pattern, for instance because the default and
+    /// clean trials failed for different Vulkan versions. `failed_trials` contains each trial
+    /// that failed for a Vulkan version that some other trial supports, and those versions.
+    Inconclusive { failed_trials: Vec<(TrialKind, Vec<u32>)> },
+    /// The pre-flight check found a problem in the manifest or registration of `layer`, without
+    /// running any trial
+    MisconfiguredLayer { layer: LayerId, problem: LayerProblem },
+}
+
+/// A problem that the pre-flight check can find by looking at the manifest of a layer
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum LayerProblem {
+    /// The `library_path` of the manifest points to a file that doesn't exist
+    MissingLibrary { library: String },
+    /// The library was built for another architecture than the registry key that registers it,
+    /// like a 32-bit DLL in the 64-bit key
+    WrongArchitecture { library: String, library_bits: u32, expected_bits: u32 },
+    /// The manifest targets an older Vulkan version than the newest driver supports
+    OutdatedApiVersion { api_version: String, driver_version: String },
+    /// Other manifests declare a layer with the same name, and the loader ignores them
+    DuplicateName { ignored_manifests: Vec<String> },
+    /// The loader only enables the layer when the `enable_environment` variable has the given
+    /// value, but no environment variable can ever have that value
+    UnsatisfiableEnableEnvironment { key: String, value: String }
+}
+
+impl LayerProblem {
+    /// Whether the problem certainly prevents the loader from using the layer, so no trial is
+    /// needed to conclude that the layer should be disabled
+    pub fn is_certain(&self) -> bool {
+        matches!(self, Self::MissingLibrary { .. } | Self::WrongArchitecture { .. })
+    }
 }
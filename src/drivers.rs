@@ -0,0 +1,202 @@
+use serde::Deserialize;
+use std::env::var_os;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DRIVER_FILES: &str = "VK_DRIVER_FILES";
+pub const ICD_FILENAMES: &str = "VK_ICD_FILENAMES";
+pub const ADD_DRIVER_FILES: &str = "VK_ADD_DRIVER_FILES";
+
+/// Where the loader found a driver (ICD) manifest
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DriverSource {
+    /// The `SOFTWARE\Khronos\Vulkan\Drivers` registry key
+    Registry,
+    /// The `VulkanDriverName` value of a display adapter in the registry
+    DisplayAdapter,
+    /// An environment variable, like `VK_DRIVER_FILES`
+    Variable(String)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DriverManifest {
+    pub manifest_path: String,
+    pub source: DriverSource,
+    pub library_path: Option<String>,
+    pub api_version: Option<String>,
+    /// The problems that would prevent the loader from using this driver
+    pub problems: Vec<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct RootDriverSettings {
+    #[serde(rename = "ICD")]
+    icd: Option<DriverSettings>
+}
+
+#[derive(Debug, Deserialize)]
+struct DriverSettings {
+    library_path: Option<String>,
+    api_version: Option<String>
+}
+
+/// Resolves the `library_path` of a driver or layer manifest. A path with a separator is relative
+/// to the manifest, whereas the system finds a bare file name in its library search path, in which
+/// case `None` is returned.
+pub fn resolve_library_path(manifest_path: &str, library_path: &str) -> Option<PathBuf> {
+    if library_path.contains(['/', '\\']) {
+        Some(Path::new(manifest_path).parent().unwrap_or(Path::new("")).join(library_path))
+    } else { None }
+}
+
+/// Parses the given driver manifest, and checks whether its library exists. Relative library
+/// paths are relative to the manifest, and bare file names are found by the library search path
+/// of the system, so their existence can't be checked.
+pub fn validate_driver_manifest(manifest_path: &str, source: DriverSource, content: Option<&str>) -> DriverManifest {
+    let mut driver = DriverManifest {
+        manifest_path: manifest_path.to_string(),
+        source,
+        library_path: None,
+        api_version: None,
+        problems: vec![]
+    };
+    let content = match content {
+        Some(content) => content,
+        None => {
+            driver.problems.push("the manifest file doesn't exist or can't be read".to_string());
+            return driver;
+        }
+    };
+    let settings = match serde_json::from_str::<RootDriverSettings>(content) {
+        Ok(RootDriverSettings { icd: Some(settings) }) => settings,
+        Ok(_) => {
+            driver.problems.push("the manifest doesn't have an ICD section".to_string());
+            return driver;
+        },
+        Err(parse_error) => {
+            driver.problems.push(format!("the manifest is invalid: {}", parse_error));
+            return driver;
+        }
+    };
+
+    driver.api_version = settings.api_version;
+    if driver.api_version.is_none() {
+        driver.problems.push("the manifest doesn't specify an api_version".to_string());
+    }
+    match &settings.library_path {
+        None => driver.problems.push("the manifest doesn't specify a library_path".to_string()),
+        Some(library_path) => if let Some(library) = resolve_library_path(manifest_path, library_path) {
+            if !library.is_file() {
+                driver.problems.push(format!("its library {} doesn't exist", library.display()));
+            }
+        }
+    }
+    driver.library_path = settings.library_path;
+    driver
+}
+
+/// Finds the driver manifests that are registered in the registry, both in the `Drivers` key and
+/// in the display adapter keys (which is where most modern drivers register themselves).
+fn registered_driver_manifests(errors: &mut Vec<String>) -> Vec<(String, DriverSource)> {
+    crate::registry::get_driver_manifest_paths(errors)
+}
+
+fn split_variable(value: &std::ffi::OsStr) -> Vec<String> {
+    std::env::split_paths(value).map(|path| path.to_string_lossy().to_string()).filter(
+        |path| !path.is_empty()
+    ).collect()
+}
+
+/// Finds all driver manifests that the loader would consider, and validates them. Like the
+/// loader, `VK_DRIVER_FILES` (or the older `VK_ICD_FILENAMES`) replaces the registered drivers,
+/// and `VK_ADD_DRIVER_FILES` adds drivers.
+pub fn enumerate_drivers() -> (Vec<DriverManifest>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut manifests = Vec::new();
+
+    if let Some((variable, value)) = [DRIVER_FILES, ICD_FILENAMES].into_iter().find_map(
+        |variable| var_os(variable).map(|value| (variable, value))
+    ) {
+        for path in split_variable(&value) {
+            manifests.push((path, DriverSource::Variable(variable.to_string())));
+        }
+    } else {
+        manifests = registered_driver_manifests(&mut errors);
+    }
+    if let Some(value) = var_os(ADD_DRIVER_FILES) {
+        for path in split_variable(&value) {
+            manifests.push((path, DriverSource::Variable(ADD_DRIVER_FILES.to_string())));
+        }
+    }
+
+    let drivers = manifests.into_iter().map(|(path, source)| {
+        let content = fs::read_to_string(&path).ok();
+        validate_driver_manifest(&path, source, content.as_deref())
+    }).collect();
+    (drivers, errors)
+}
+
+/// Describes the problems with the given drivers, which is empty when all drivers look fine
+pub fn driver_problems(drivers: &[DriverManifest]) -> Vec<String> {
+    if drivers.is_empty() {
+        return vec!["No Vulkan drivers were found: you probably need to (re)install your graphics drivers.".to_string()];
+    }
+    let mut problems = Vec::new();
+    for driver in drivers {
+        for problem in &driver.problems {
+            problems.push(format!("Driver {}: {}", driver.manifest_path, problem));
+        }
+    }
+    if !problems.is_empty() && drivers.iter().all(|driver| !driver.problems.is_empty()) {
+        problems.push("None of the drivers looks usable: you probably need to reinstall your graphics drivers.".to_string());
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_driver_manifest() {
+        let driver = validate_driver_manifest("C:\\Drivers\\nv-vk64.json", DriverSource::Registry, Some(r#"{
+            "file_format_version": "1.0.1",
+            "ICD": { "library_path": "nvoglv64.dll", "api_version": "1.3.277" }
+        }"#));
+        assert_eq!(Some("nvoglv64.dll".to_string()), driver.library_path);
+        assert_eq!(Some("1.3.277".to_string()), driver.api_version);
+        assert!(driver.problems.is_empty());
+
+        let driver = validate_driver_manifest("/nonexistent/icd.d/intel.json", DriverSource::Registry, Some(r#"{
+            "ICD": { "library_path": "../lib/libvulkan_intel.so" }
+        }"#));
+        assert_eq!(2, driver.problems.len());
+        assert_eq!("the manifest doesn't specify an api_version", driver.problems[0]);
+        assert!(driver.problems[1].contains("libvulkan_intel.so doesn't exist"));
+
+        let driver = validate_driver_manifest("missing.json", DriverSource::Registry, None);
+        assert_eq!(vec!["the manifest file doesn't exist or can't be read".to_string()], driver.problems);
+
+        let driver = validate_driver_manifest("layer.json", DriverSource::Registry, Some(r#"{ "layer": {} }"#));
+        assert_eq!(vec!["the manifest doesn't have an ICD section".to_string()], driver.problems);
+
+        let driver = validate_driver_manifest("broken.json", DriverSource::Registry, Some("{"));
+        assert!(driver.problems[0].starts_with("the manifest is invalid"));
+    }
+
+    #[test]
+    fn test_driver_problems() {
+        assert_eq!(1, driver_problems(&[]).len());
+
+        let healthy = validate_driver_manifest("good.json", DriverSource::Registry, Some(
+            r#"{ "ICD": { "library_path": "good.dll", "api_version": "1.3.0" } }"#
+        ));
+        let broken = validate_driver_manifest("bad.json", DriverSource::Registry, None);
+        assert!(driver_problems(std::slice::from_ref(&healthy)).is_empty());
+        assert_eq!(
+            vec!["Driver bad.json: the manifest file doesn't exist or can't be read".to_string()],
+            driver_problems(&[healthy, broken.clone()])
+        );
+        assert_eq!(2, driver_problems(&[broken]).len());
+    }
+}
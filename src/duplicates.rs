@@ -0,0 +1,88 @@
+use crate::definitions::*;
+
+/// Finds the layers that the loader will skip because another manifest declares a layer with the
+/// same name, and stores the settings path of that other manifest in their `shadowed_by`.
+///
+/// The loader scans `HKEY_LOCAL_MACHINE` before `HKEY_CURRENT_USER`, and uses the first layer it
+/// finds with a given name. Within the same registry key, it follows the order of the values
+/// (which is the order of `layers`). Layers with a non-zero registry value are skipped before the
+/// loader even parses them, so they can't shadow anything.
+pub fn mark_shadowed_layers(layers: &mut [ImplicitLayer]) {
+    let mut precedence: Vec<usize> = (0 .. layers.len()).collect();
+    precedence.sort_by_key(|index| match layers[*index].registry {
+        ImplicitRegistry::LocalMachine => 0,
+        ImplicitRegistry::CurrentUser => 1
+    });
+
+    let mut winners: Vec<usize> = Vec::new();
+    for index in precedence {
+        layers[index].shadowed_by = None;
+        if layers[index].registry_value != 0 {
+            continue;
+        }
+
+        let winner = winners.iter().find(
+            |winner| layers[**winner].name.eq_ignore_ascii_case(&layers[index].name)
+        ).copied();
+        if let Some(winner) = winner {
+            layers[index].shadowed_by = Some(layers[winner].settings_path.clone());
+        } else {
+            winners.push(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str, settings_path: &str, registry: ImplicitRegistry) -> ImplicitLayer {
+        ImplicitLayer { settings_path: settings_path.to_string(), registry, ..ImplicitLayer::test(name) }
+    }
+
+    #[test]
+    fn test_no_duplicates() {
+        let mut layers = vec![
+            layer("VK_LAYER_a", "a.json", ImplicitRegistry::CurrentUser),
+            layer("VK_LAYER_b", "b.json", ImplicitRegistry::LocalMachine)
+        ];
+        mark_shadowed_layers(&mut layers);
+        assert!(layers.iter().all(|layer| layer.shadowed_by.is_none()));
+    }
+
+    #[test]
+    fn test_machine_shadows_user() {
+        let mut layers = vec![
+            layer("VK_LAYER_a", "user.json", ImplicitRegistry::CurrentUser),
+            layer("VK_LAYER_A", "machine.json", ImplicitRegistry::LocalMachine)
+        ];
+        mark_shadowed_layers(&mut layers);
+        assert_eq!(Some("machine.json".to_string()), layers[0].shadowed_by);
+        assert_eq!(None, layers[1].shadowed_by);
+    }
+
+    #[test]
+    fn test_first_version_wins_within_registry() {
+        let mut layers = vec![
+            layer("VK_LAYER_a", "v1/a.json", ImplicitRegistry::LocalMachine),
+            layer("VK_LAYER_a", "v2/a.json", ImplicitRegistry::LocalMachine),
+            layer("VK_LAYER_a", "v3/a.json", ImplicitRegistry::LocalMachine)
+        ];
+        mark_shadowed_layers(&mut layers);
+        assert_eq!(vec![None, Some("v1/a.json".to_string()), Some("v1/a.json".to_string())], layers.iter().map(
+            |layer| layer.shadowed_by.clone()
+        ).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ignored_layers_dont_shadow() {
+        let mut layers = vec![
+            layer("VK_LAYER_a", "user.json", ImplicitRegistry::CurrentUser),
+            layer("VK_LAYER_a", "machine.json", ImplicitRegistry::LocalMachine)
+        ];
+        layers[1].registry_value = 1;
+        mark_shadowed_layers(&mut layers);
+        assert_eq!(None, layers[0].shadowed_by);
+        assert_eq!(None, layers[1].shadowed_by);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::definitions::*;
+
+pub const INSTANCE_LAYERS: &str = "VK_INSTANCE_LAYERS";
+pub const LAYER_PATH: &str = "VK_LAYER_PATH";
+pub const ADD_LAYER_PATH: &str = "VK_ADD_LAYER_PATH";
+pub const IMPLICIT_LAYER_PATH: &str = "VK_IMPLICIT_LAYER_PATH";
+pub const ADD_IMPLICIT_LAYER_PATH: &str = "VK_ADD_IMPLICIT_LAYER_PATH";
+
+/// The loader variables that enable extra layers or change where the loader looks for them. The
+/// trials can clear these to test their effect.
+pub const LAYER_VARIABLES: [&str; 5] = [
+    INSTANCE_LAYERS, LAYER_PATH, ADD_LAYER_PATH, IMPLICIT_LAYER_PATH, ADD_IMPLICIT_LAYER_PATH
+];
+
+/// Splits the value of `VK_INSTANCE_LAYERS` into layer names. The loader uses `;` as separator on
+/// Windows and `:` elsewhere, but since layer names can't contain either, both are accepted.
+pub fn split_instance_layers(value: &str) -> Vec<&str> {
+    value.split([';', ':']).map(|name| name.trim()).filter(|name| !name.is_empty()).collect()
+}
+
+/// Finds the loader variables that affect which layers are loaded, and describes what they do.
+/// Variables that are set globally (for the user or system) affect every Vulkan application, so
+/// they are reported as warnings.
+pub fn layer_variable_warnings(env: &EnvironmentVariables, explicit_layers: &[ExplicitLayer]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for variable in LAYER_VARIABLES {
+        let (source, value) = match env.lookup(variable) {
+            Some(found) => found,
+            None => continue
+        };
+        let prefix = if source == VariableSource::Process { "Note" } else { "Warning" };
+        let effect = match variable {
+            INSTANCE_LAYERS => "which forces layers on for every Vulkan application",
+            LAYER_PATH => "which replaces the directories where the loader looks for explicit layers",
+            ADD_LAYER_PATH => "which adds directories where the loader looks for explicit layers",
+            IMPLICIT_LAYER_PATH => "which replaces the directories where the loader looks for implicit layers",
+            _ => "which adds directories where the loader looks for implicit layers"
+        };
+        warnings.push(format!("{}: the {} variable {} is set to {}, {}", prefix, source, variable, value, effect));
+
+        if variable == INSTANCE_LAYERS {
+            for name in split_instance_layers(value) {
+                if explicit_layers.iter().any(|layer| layer.name.eq_ignore_ascii_case(name)) {
+                    warnings.push(format!("- it forces explicit layer {} on", name));
+                } else {
+                    warnings.push(format!("- it asks for {}, but no explicit layer with that name was found", name));
+                }
+            }
+        }
+    }
+
+    if let Some(settings) = &env.loader_settings {
+        for entry in settings.layers.iter().filter(|entry| entry.control == LayerControl::On) {
+            if explicit_layers.iter().any(|layer| layer.name.eq_ignore_ascii_case(&entry.name)) {
+                warnings.push(format!(
+                    "Warning: the loader settings file {} forces explicit layer {} on", settings.path, entry.name
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn explicit_layer(name: &str) -> ExplicitLayer {
+        ExplicitLayer {
+            settings_path: format!("{}.json", name),
+            registry: ImplicitRegistry::LocalMachine,
+            name: name.to_string(),
+            description: "".to_string()
+        }
+    }
+
+    #[test]
+    fn test_split_instance_layers() {
+        assert_eq!(vec!["VK_LAYER_a", "VK_LAYER_b"], split_instance_layers("VK_LAYER_a;VK_LAYER_b;"));
+        assert_eq!(vec!["VK_LAYER_a", "VK_LAYER_b"], split_instance_layers("VK_LAYER_a: VK_LAYER_b"));
+        assert!(split_instance_layers("").is_empty());
+    }
+
+    #[test]
+    fn test_layer_variable_warnings() {
+        let mut env = EnvironmentVariables {
+            process: HashMap::from([(ADD_LAYER_PATH.to_string(), "C:\\Layers".to_string())]),
+            user: HashMap::from([(INSTANCE_LAYERS.to_string(), "VK_LAYER_KHRONOS_validation;VK_LAYER_gone".to_string())]),
+            system: HashMap::new(),
+            loader_settings: None,
+            errors: vec![]
+        };
+        let explicit_layers = [explicit_layer("VK_LAYER_KHRONOS_validation"), explicit_layer("VK_LAYER_other")];
+        assert_eq!(vec![
+            "Warning: the user variable VK_INSTANCE_LAYERS is set to VK_LAYER_KHRONOS_validation;VK_LAYER_gone, \
+            which forces layers on for every Vulkan application".to_string(),
+            "- it forces explicit layer VK_LAYER_KHRONOS_validation on".to_string(),
+            "- it asks for VK_LAYER_gone, but no explicit layer with that name was found".to_string(),
+            "Note: the process variable VK_ADD_LAYER_PATH is set to C:\\Layers, \
+            which adds directories where the loader looks for explicit layers".to_string()
+        ], layer_variable_warnings(&env, &explicit_layers));
+
+        env.user.clear();
+        env.process.clear();
+        env.loader_settings = Some(LoaderSettings {
+            path: "vk_loader_settings.json".to_string(),
+            layers: vec![LoaderSettingsLayer {
+                name: "VK_LAYER_other".to_string(), path: None, control: LayerControl::On
+            }],
+            has_unordered_layer_location: true,
+            managed_by_vk_fixer: false
+        });
+        assert_eq!(vec![
+            "Warning: the loader settings file vk_loader_settings.json forces explicit layer VK_LAYER_other on".to_string()
+        ], layer_variable_warnings(&env, &explicit_layers));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::definitions::*;
+
+/// The names in `before` that are not in `after`
+pub fn missing_names(before: &[String], after: &[String]) -> Vec<String> {
+    before.iter().filter(|name| !after.contains(name)).cloned().collect()
+}
+
+/// The capabilities of the first Vulkan version for which the trial succeeded. The capabilities of
+/// failed trials are not used, since the test app may have failed before it could enumerate them.
+fn succeeded_capabilities(results: &VersionedTrialResults) -> Option<&TrialCapabilities> {
+    [&results.vk10, &results.vk11, &results.vk12, &results.vk13].into_iter().find(
+        |trial| trial.exit_code == 0
+    ).map(|trial| &trial.capabilities)
+}
+
+/// The extensions that a layer adds or removes when it is the only enabled layer
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExtensionChanges {
+    pub layer: LayerId,
+    pub added_instance_extensions: Vec<String>,
+    pub removed_instance_extensions: Vec<String>,
+    pub added_device_extensions: Vec<String>,
+    pub removed_device_extensions: Vec<String>
+}
+
+impl ExtensionChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added_instance_extensions.is_empty() && self.removed_instance_extensions.is_empty() &&
+            self.added_device_extensions.is_empty() && self.removed_device_extensions.is_empty()
+    }
+
+    /// Describes the changes like "+VK_EXT_debug_utils, -VK_KHR_surface"
+    pub fn describe(added: &[String], removed: &[String]) -> String {
+        added.iter().map(|name| format!("+{}", name)).chain(
+            removed.iter().map(|name| format!("-{}", name))
+        ).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Compares the isolation trial of each layer with the clean trial, and returns the layers that
+/// add or remove extensions
+pub fn compute_extension_changes(results: &TestResults) -> Vec<ExtensionChanges> {
+    let clean = match succeeded_capabilities(&results.clean_result) {
+        Some(clean) => clean,
+        None => return vec![]
+    };
+    results.isolation_results.iter().filter_map(|(layer, isolation_results)| {
+        let isolated = succeeded_capabilities(isolation_results)?;
+        Some(ExtensionChanges {
+            layer: layer.clone(),
+            added_instance_extensions: missing_names(&isolated.instance_extensions, &clean.instance_extensions),
+            removed_instance_extensions: missing_names(&clean.instance_extensions, &isolated.instance_extensions),
+            added_device_extensions: missing_names(&isolated.device_extensions, &clean.device_extensions),
+            removed_device_extensions: missing_names(&clean.device_extensions, &isolated.device_extensions)
+        })
+    }).filter(|changes| !changes.is_empty()).collect()
+}
+
+/// The instance and device extensions that the clean trial has, but the default trial misses
+fn hidden_extensions(results: &TestResults) -> Option<(Vec<String>, Vec<String>)> {
+    let clean = succeeded_capabilities(&results.clean_result)?;
+    let default = succeeded_capabilities(&results.default_result)?;
+    let hidden_instance_extensions = missing_names(&clean.instance_extensions, &default.instance_extensions);
+    let hidden_device_extensions = missing_names(&clean.device_extensions, &default.device_extensions);
+    if hidden_instance_extensions.is_empty() && hidden_device_extensions.is_empty() {
+        None
+    } else { Some((hidden_instance_extensions, hidden_device_extensions)) }
+}
+
+/// Whether the default trial misses extensions that the clean trial has, in which case the
+/// exclude trials are needed to find the layer that hides them
+pub fn hides_extensions(results: &TestResults) -> bool {
+    hidden_extensions(results).is_some()
+}
+
+/// Checks whether the default trial misses extensions that the clean trial has, and tries to find
+/// the layer that hides them: the layer whose exclude trial gets most of them back.
+pub fn find_hidden_extensions(results: &TestResults) -> Option<Conclusion> {
+    let (hidden_instance_extensions, hidden_device_extensions) = hidden_extensions(results)?;
+
+    let restored_extensions = |layer_results: &VersionedTrialResults| {
+        let without_layer = succeeded_capabilities(layer_results)?;
+        let restore = |hidden: &[String], available: &[String]| hidden.iter().filter(
+            |name| available.contains(name)
+        ).cloned().collect::<Vec<_>>();
+        Some((
+            restore(&hidden_instance_extensions, &without_layer.instance_extensions),
+            restore(&hidden_device_extensions, &without_layer.device_extensions)
+        ))
+    };
+
+    let (layer, instance_extensions, device_extensions) = results.exclude_results.iter().filter_map(
+        |(layer, layer_results)| restored_extensions(layer_results).map(
+            |(instance_extensions, device_extensions)| (layer, instance_extensions, device_extensions)
+        )
+    ).filter(
+        |(_, instance_extensions, device_extensions)| !instance_extensions.is_empty() || !device_extensions.is_empty()
+    ).max_by_key(
+        |(_, instance_extensions, device_extensions)| instance_extensions.len() + device_extensions.len()
+    )?;
+    Some(Conclusion::HiddenExtensions { layer: layer.clone(), instance_extensions, device_extensions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(name: &str) -> LayerId {
+        LayerId {
+            registry: ImplicitRegistry::CurrentUser,
+            settings_path: format!("{}.json", name),
+            manifest_index: 0
+        }
+    }
+
+    fn to_names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn results(instance_extensions: &[&str], device_extensions: &[&str]) -> VersionedTrialResults {
+        let trial = TrialResult {
+            exit_code: 0,
+            output: "".to_string(),
+            timings: TrialTimings::default(),
+            capabilities: TrialCapabilities {
+                instance_layers: vec![],
+                instance_extensions: to_names(instance_extensions),
+                device_extensions: to_names(device_extensions)
+            }
+        };
+        VersionedTrialResults { vk10: trial.clone(), vk11: trial.clone(), vk12: trial.clone(), vk13: trial }
+    }
+
+    #[test]
+    fn test_extension_changes_and_hidden_extensions() {
+        let test_results = TestResults {
+            default_result: results(&["VK_KHR_surface", "VK_EXT_debug_utils"], &["VK_KHR_swapchain"]),
+            clean_result: results(&["VK_KHR_surface"], &["VK_KHR_swapchain", "VK_KHR_dynamic_rendering"]),
+            exclude_results: vec![
+                (id("debug"), results(&["VK_KHR_surface"], &["VK_KHR_swapchain"])),
+                (id("hider"), results(&["VK_KHR_surface", "VK_EXT_debug_utils"], &["VK_KHR_swapchain", "VK_KHR_dynamic_rendering"]))
+            ],
+            isolation_results: vec![
+                (id("debug"), results(&["VK_KHR_surface", "VK_EXT_debug_utils"], &["VK_KHR_swapchain", "VK_KHR_dynamic_rendering"])),
+                (id("hider"), results(&["VK_KHR_surface"], &["VK_KHR_swapchain"]))
+            ],
+            skipped_trials: vec![]
+        };
+
+        assert_eq!(vec![
+            ExtensionChanges {
+                layer: id("debug"),
+                added_instance_extensions: to_names(&["VK_EXT_debug_utils"]),
+                removed_instance_extensions: vec![],
+                added_device_extensions: vec![],
+                removed_device_extensions: vec![]
+            },
+            ExtensionChanges {
+                layer: id("hider"),
+                added_instance_extensions: vec![],
+                removed_instance_extensions: vec![],
+                added_device_extensions: vec![],
+                removed_device_extensions: to_names(&["VK_KHR_dynamic_rendering"])
+            }
+        ], compute_extension_changes(&test_results));
+        assert_eq!("+VK_EXT_debug_utils, -VK_KHR_surface", ExtensionChanges::describe(
+            &to_names(&["VK_EXT_debug_utils"]), &to_names(&["VK_KHR_surface"])
+        ));
+
+        assert_eq!(Some(Conclusion::HiddenExtensions {
+            layer: id("hider"),
+            instance_extensions: vec![],
+            device_extensions: to_names(&["VK_KHR_dynamic_rendering"])
+        }), find_hidden_extensions(&test_results));
+    }
+
+    #[test]
+    fn test_no_hidden_extensions() {
+        let test_results = TestResults {
+            default_result: results(&["VK_KHR_surface", "VK_EXT_debug_utils"], &["VK_KHR_swapchain"]),
+            clean_result: results(&["VK_KHR_surface"], &["VK_KHR_swapchain"]),
+            exclude_results: vec![],
+            isolation_results: vec![],
+            skipped_trials: vec![]
+        };
+        assert_eq!(None, find_hidden_extensions(&test_results));
+        assert!(!hides_extensions(&test_results));
+    }
+}
@@ -1,8 +1,25 @@
 use std::cell::RefCell;
-use std::io::ErrorKind;
+use std::collections::HashMap;
 use std::rc::Rc;
+use crate::categories::{classify, risk_warning, LayerCategory};
+use crate::config::Config;
 use crate::definitions::*;
-use crate::logic::{draw_conclusion, run_all_trials};
+use crate::drivers::{driver_problems, enumerate_drivers};
+use crate::explicit_layers::layer_variable_warnings;
+use crate::extensions::{compute_extension_changes, ExtensionChanges};
+use crate::known_issues::{failed_api_versions, KnownIssueDatabase};
+use crate::layer_state::*;
+use crate::logic::{diagnose, diagnose_per_version, run_all_trials, run_baseline_trials};
+use crate::meta_layers::describe_meta_layer;
+use crate::overhead::{compute_overheads, format_overhead};
+use crate::preflight::{combine_findings, preflight_check, preflight_is_conclusive};
+use crate::profiles::{Profile, ProfileStore};
+use crate::report::{
+    conclusion_layers, describe_fix, describe_layer_problem, describe_solution, describe_trace, describe_trial,
+    disables_kept_layer, display_api_version, display_api_versions, display_layer, merge_per_version, solution_layers, summarize_finding
+};
+use crate::shortcuts::{generate_shortcuts, own_executable_path, TargetPlatform};
+use crate::solver::find_fix;
 use crate::registry::*;
 
 #[derive(Eq, PartialEq, Clone, Default)]
@@ -11,16 +28,17 @@ pub enum GuiState {
     Initial,
     Manual(bool),
     AutoLayerList,
-    AutoResultsTable(TestResults, Vec<ImplicitLayer>),
-    AutoConclusion(Conclusion, Vec<ImplicitLayer>, bool),
+    AutoResultsTable(TestResults, Vec<ImplicitLayer>, DisableMethod, Option<KeptLayersFix>, Vec<Finding>),
+    AutoConclusion(Vec<Finding>, Vec<ImplicitLayer>, bool, DisableMethod, Option<KeptLayersFix>),
     AutoFinished(bool),
     AutoFailed(String, bool),
+    Profiles,
     Exit
 }
 
 #[derive(Default, nwd::NwgUi)]
 pub struct FixerApp {
-    #[nwg_control(size: (750, 400), center: true, title: "vk-fixer", flags: "MAIN_WINDOW|VISIBLE")]
+    #[nwg_control(size: (750, 460), center: true, title: "vk-fixer", flags: "MAIN_WINDOW|VISIBLE")]
     #[nwg_events( OnWindowClose: [FixerApp::say_goodbye] )]
     pub window: nwg::Window,
 
@@ -57,6 +75,10 @@ the culprit with trial and error, or just disable all of them and be done with i
     #[nwg_events( OnButtonClick: [FixerApp::start_manual_mode] )]
     pub manual_button: nwg::Button,
 
+    #[nwg_control(text: "Game profiles", size: (300, 40), position: (225, 395))]
+    #[nwg_events( OnButtonClick: [FixerApp::show_profiles] )]
+    pub profiles_button: nwg::Button,
+
     pub state: Rc<RefCell<GuiState>>
 }
 
@@ -73,11 +95,65 @@ impl FixerApp {
     }
 
     fn start_manual_mode(&self) {
-        *self.state.borrow_mut() = GuiState::Manual(true);
+        *self.state.borrow_mut() = GuiState::Manual(has_admin_privileges());
+        nwg::stop_thread_dispatch();
+    }
+
+    fn show_profiles(&self) {
+        *self.state.borrow_mut() = GuiState::Profiles;
         nwg::stop_thread_dispatch();
     }
 }
 
+/// Asks the user to confirm that they want to disable or break high-risk layers (see
+/// `risk_warning`). Returns true when there is no warning, or when the user confirms.
+fn confirm_risky_layers(parent: nwg::ControlHandle, warning: Option<&str>, action: &str) -> bool {
+    match warning {
+        Some(warning) => matches!(nwg::modal_message(parent, &nwg::MessageParams {
+            title: "Are you sure?",
+            content: &format!("{}\n\nDo you really want to {} anyway?", warning, action),
+            buttons: nwg::MessageButtons::YesNo,
+            icons: nwg::MessageIcons::Warning
+        }), nwg::MessageChoice::Yes),
+        None => true
+    }
+}
+
+/// Handles a click on a disable checkbox of the manual mode. The layer is identified by its
+/// manifest path and index, and `source` tells whether it's the user or the 'All users' checkbox.
+/// Afterwards, the window is rebuilt to show the new state of all layers.
+fn toggle_disable_variable(
+    parent: nwg::ControlHandle, layer_id: &(String, usize), source: VariableSource, risk: Option<&str>
+) {
+    let (layers, _) = get_implicit_layers();
+    let env = get_global_environment_keys();
+    let states = effective_states(&layers, &env);
+    let found = layers.iter().zip(&states).find(
+        |(layer, _)| layer.settings_path == layer_id.0 && layer.manifest_index == layer_id.1
+    );
+    if let Some((layer, state)) = found {
+        let key = &layer.disable_environment;
+        let succeeded = match (checkbox_action(layer, state, &env, source), source) {
+            (CheckboxAction::Enable, VariableSource::System) => remove_environment(EnvironmentScope::System, key),
+            (CheckboxAction::Enable, _) => remove_user_environment(key),
+            (CheckboxAction::Disable, VariableSource::System) => !confirm_risky_layers(
+                parent, risk, "disable it for all users"
+            ) || set_environment(EnvironmentScope::System, key),
+            (CheckboxAction::Disable, _) => !confirm_risky_layers(parent, risk, "disable it") || set_user_environment(key),
+            (CheckboxAction::Unchangeable, _) => {
+                nwg::modal_info_message(parent, &layer.name, &format!(
+                    "This checkbox can't change {}, because it is {}", layer.name, state
+                ));
+                true
+            }
+        };
+        if !succeeded {
+            nwg::modal_error_message(parent, "vk-fixer", &format!("Failed to change {}", key));
+        }
+    }
+    nwg::stop_thread_dispatch();
+}
+
 #[derive(Default, nwd::NwgUi)]
 pub struct ManualApp {
     #[nwg_events( OnWindowClose: [ManualApp::close], OnInit: [ManualApp::init_layers] )]
@@ -88,6 +164,7 @@ pub struct ManualApp {
     pub layout: nwg::GridLayout,
 
     pub layer_names: Rc<RefCell<Vec<nwg::CheckBox>>>,
+    pub system_boxes: Rc<RefCell<Vec<nwg::CheckBox>>>,
     pub layer_info: RefCell<Vec<nwg::Label>>,
     pub break_buttons: RefCell<Vec<nwg::Button>>,
     pub handlers: RefCell<Vec<nwg::EventHandler>>,
@@ -98,8 +175,13 @@ pub struct ManualApp {
 
 impl ManualApp {
     fn init_layers(&self) {
-        let (mut layers, errors) = get_implicit_layers();
+        let (mut layers, mut errors) = get_implicit_layers();
         let env = get_global_environment_keys();
+        let is_admin = has_admin_privileges();
+        let (known_issues, known_issues_error) = KnownIssueDatabase::load();
+        errors.extend(known_issues_error);
+        let (config, config_error) = Config::load();
+        errors.extend(config_error);
 
         layers.sort_by_key(|layer| {
             if layer.enable_environment.is_some() { 1 } else { 0 }
@@ -135,8 +217,35 @@ impl ManualApp {
             add_info("If you want to Break layers, you need to restart this application");
             add_info("with administrator privileges.");
         }
+        if is_admin {
+            add_info("");
+            add_info("Since you have administrator privileges, you can also disable layers for all users");
+            add_info("by checking the 'All users' box next to them.");
+        }
+        for filter in [LOADER_LAYERS_ENABLE, LOADER_LAYERS_DISABLE, LOADER_LAYERS_ALLOW] {
+            if let Some((source, value)) = env.lookup(filter) {
+                add_info(&format!("Note: the {} variable {} is set to {}", source, filter, value));
+            }
+        }
+        let (explicit_layers, _) = get_explicit_layers();
+        let warnings = layer_variable_warnings(&env, &explicit_layers);
+        if !warnings.is_empty() {
+            add_info("");
+            for warning in &warnings {
+                add_info(warning);
+            }
+        }
+        if let Some(settings) = &env.loader_settings {
+            add_info("");
+            for line in settings.describe_overrides() {
+                add_info(&line);
+            }
+            add_info("Disabling layers via environment variables has no effect on the layers that it forces on.");
+            add_info("Alternatively, use 'vk-fixer loader-settings disable <layer>...' to force layers off.");
+        }
         add_info("");
-        for layer in layers {
+        let states = effective_states(&layers, &env);
+        for (layer, state) in layers.iter().zip(states) {
 
             let mut break_button = Default::default();
             nwg::Button::builder()
@@ -147,24 +256,26 @@ impl ManualApp {
             let break_button_handle = break_button.handle;
 
             let mut layer_box = Default::default();
-            let is_disabled = env.user.contains(&layer.disable_environment);
             nwg::CheckBox::builder()
-                .text(&layer.name)
-                .check_state(if is_disabled { nwg::CheckBoxState::Checked } else { nwg::CheckBoxState::Unchecked })
+                .text(&format!("{} ({})", layer.name, state))
+                .check_state(if state.enabled { nwg::CheckBoxState::Unchecked } else { nwg::CheckBoxState::Checked })
                 .parent(&self.window)
                 .build(&mut layer_box)
                 .expect("Failed to add layer checkbox");
 
+            let risk = risk_warning(&[layer], &config.layer_categories);
+            let break_risk = risk.clone();
             let layer_path = layer.settings_path.clone();
-            let state_ref = Rc::clone(&self.state);
             let break_handler = nwg::bind_event_handler(
                 &break_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
                     if evt == nwg::Event::OnButtonClick && handle == break_button_handle {
-                        let delete_result = std::fs::remove_file(&layer_path);
-                        if let Err(failed_delete) = delete_result {
-                            if failed_delete.kind() == ErrorKind::PermissionDenied {
-                                *state_ref.borrow_mut() = GuiState::Manual(false);
-                            }
+                        if !confirm_risky_layers(handle, break_risk.as_deref(), "break it") {
+                            return;
+                        }
+                        if let Err(failed_delete) = std::fs::remove_file(&layer_path) {
+                            nwg::modal_error_message(
+                                handle, "Failed to break layer", &format!("Failed to delete {}: {}", layer_path, failed_delete)
+                            );
                         }
 
                         nwg::stop_thread_dispatch();
@@ -172,40 +283,53 @@ impl ManualApp {
                 }
             );
 
+            let system_risk = risk.clone();
             let toggle_handle = layer_box.handle;
-            let disable_env = layer.disable_environment.clone();
-            let layer_names_ref = Rc::clone(&self.layer_names);
+            let layer_id = (layer.settings_path.clone(), layer.manifest_index);
+            let system_layer_id = layer_id.clone();
             let toggle_handler = nwg::bind_event_handler(
                 &layer_box.handle, &self.window.handle, move |evt, _evt_data, handle| {
                     if evt == nwg::Event::OnButtonClick && handle == toggle_handle {
-                        let mut is_disabled = get_global_environment_keys().user.contains(&disable_env);
-                        if is_disabled {
-                            is_disabled = !remove_user_environment(&disable_env);
-                        } else {
-                            is_disabled = set_user_environment(&disable_env);
-                        }
-
-                        let layer_boxes = layer_names_ref.borrow_mut();
-                        for layer_box in &*layer_boxes {
-                            if layer_box.handle == toggle_handle {
-                                layer_box.set_check_state(if is_disabled { nwg::CheckBoxState::Checked } else { nwg::CheckBoxState::Unchecked });
-                            }
-                        }
+                        toggle_disable_variable(handle, &layer_id, VariableSource::User, risk.as_deref());
                     }
                 });
             self.handlers.borrow_mut().push(break_handler);
             self.handlers.borrow_mut().push(toggle_handler);
 
             let mut layer_names = self.layer_names.borrow_mut();
+            let row = (layer_names.len() + self.layer_info.borrow().len()) as u32;
             self.layout.add_child_item(nwg::GridLayoutItem::new(
                 &layer_box,
                 0,
-                (layer_names.len() + self.layer_info.borrow().len()) as u32,
-                7, 1
+                row,
+                if is_admin { 6 } else { 7 }, 1
             ));
             layer_names.push(layer_box);
             drop(layer_names);
 
+            if is_admin {
+                let mut system_box = Default::default();
+                let is_disabled_system_wide = env.lookup_in(VariableSource::System, &layer.disable_environment).is_some();
+                nwg::CheckBox::builder()
+                    .text("All users")
+                    .check_state(if is_disabled_system_wide { nwg::CheckBoxState::Checked } else { nwg::CheckBoxState::Unchecked })
+                    .parent(&self.window)
+                    .build(&mut system_box)
+                    .expect("Failed to add system checkbox");
+
+                let system_toggle_handle = system_box.handle;
+                let system_toggle_handler = nwg::bind_event_handler(
+                    &system_box.handle, &self.window.handle, move |evt, _evt_data, handle| {
+                        if evt == nwg::Event::OnButtonClick && handle == system_toggle_handle {
+                            toggle_disable_variable(handle, &system_layer_id, VariableSource::System, system_risk.as_deref());
+                        }
+                    });
+                self.handlers.borrow_mut().push(system_toggle_handler);
+
+                self.layout.add_child_item(nwg::GridLayoutItem::new(&system_box, 6, row, 1, 1));
+                self.system_boxes.borrow_mut().push(system_box);
+            }
+
             if self.show_break_buttons {
                 let mut break_buttons = self.break_buttons.borrow_mut();
                 self.layout.add_child_item(nwg::GridLayoutItem::new(
@@ -219,13 +343,26 @@ impl ManualApp {
             }
 
             add_info(&layer.description);
-            if let Some(enable_env) = layer.enable_environment {
-                if !env.user.contains(&enable_env) && !env.system.contains(&enable_env) {
-                    add_info("Note: this layer is NOT enabled by default, so disabling it has probably no effect");
-                }
+            add_info(&format!("Category: {}", classify(layer, &config.layer_categories)));
+            if let Some(meta_description) = describe_meta_layer(layer) {
+                add_info(&format!("This is a {}", meta_description));
             }
-            if env.system.contains(&layer.disable_environment) {
-                add_info("Note: this layer is already disabled system-wide, so disabling it has probably no effect");
+            for issue in known_issues.find_issues(layer, &layers, &ALL_API_VERSIONS) {
+                add_info(&issue.describe());
+            }
+            let disabled_by_checkbox = match &state.reason {
+                StateReason::DisableVariable { source: VariableSource::User, .. } => true,
+                StateReason::DisableVariable { source: VariableSource::System, .. } => is_admin,
+                _ => false
+            };
+            if !state.enabled && !disabled_by_checkbox {
+                add_info("Note: this layer is already inactive, so disabling it has probably no effect");
+            }
+            if let StateReason::PulledInByMetaLayer { meta_layer } = &state.reason {
+                add_info(&format!("Note: to disable this layer, you also need to disable {}", meta_layer));
+            }
+            if state.enabled && matches!(state.reason, StateReason::LoaderSettings { .. }) {
+                add_info("Note: the loader settings file keeps this layer enabled, even when you disable it here");
             }
             add_info("");
         }
@@ -247,28 +384,48 @@ impl ManualApp {
 #[derive(Default, nwd::NwgUi)]
 pub struct AutoLayerApp {
     #[nwg_events( OnWindowClose: [AutoLayerApp::close], OnInit: [AutoLayerApp::init_layers] )]
-    #[nwg_control(size: (650, 400), center: true, title: "Automatic: layer list", flags: "MAIN_WINDOW|VISIBLE")]
+    #[nwg_control(size: (650, 425), center: true, title: "Automatic: layer list", flags: "MAIN_WINDOW|VISIBLE")]
     pub window: nwg::Window,
 
-    #[nwg_control(text: "Run trials...", size: (300, 50), position: (150, 325))]
+    #[nwg_control(text: "Run trials...", size: (300, 50), position: (150, 350))]
     #[nwg_events( OnButtonClick: [AutoLayerApp::run_trials] )]
     pub trials_button: nwg::Button,
 
-    #[nwg_layout(parent: window, spacing: 0, margin: [0, 50, 100, 50])]
+    #[nwg_control(text: "Use VK_LOADER_LAYERS_DISABLE (requires a recent Vulkan loader)", size: (450, 25), position: (100, 300))]
+    pub filter_box: nwg::CheckBox,
+
+    #[nwg_control(text: "Clear VK_INSTANCE_LAYERS and the other layer variables during the trials", size: (450, 25), position: (100, 325))]
+    pub clear_box: nwg::CheckBox,
+
+    #[nwg_control(text: "Run all trials (slower, but shows the overhead and extensions of every layer)", size: (450, 25), position: (100, 275))]
+    pub exhaustive_box: nwg::CheckBox,
+
+    #[nwg_layout(parent: window, spacing: 0, margin: [0, 50, 150, 50])]
     pub layout: nwg::GridLayout,
 
     pub layer_info: RefCell<Vec<nwg::Label>>,
+    /// The check boxes that mark layers as must-keep, and the layers they belong to
+    pub keep_boxes: RefCell<Vec<(nwg::CheckBox, LayerId)>>,
 
     pub layer_list: RefCell<Vec<ImplicitLayer>>,
+    pub requirements: RefCell<Requirements>,
+    /// The problems that the pre-flight check found before running any trial
+    pub preflight_findings: RefCell<Vec<Finding>>,
 
     pub state: Rc<RefCell<GuiState>>
 }
 
 impl AutoLayerApp {
     fn init_layers(&self) {
-        let (mut layers, errors) = get_implicit_layers();
+        let (all_layers, errors) = get_implicit_layers();
+        let env = get_global_environment_keys();
 
-        layers.retain(|layer| is_enabled(layer));
+        let states = effective_states(&all_layers, &env);
+        let (drivers, driver_errors) = enumerate_drivers();
+        let preflight_findings = preflight_check(&all_layers, &states, &drivers);
+        let (layers, inactive_layers): (Vec<_>, Vec<_>) = all_layers.iter().cloned().zip(states).partition(
+            |(_, state)| state.enabled
+        );
 
         let add_info = |text: &str| {
             let mut label = Default::default();
@@ -283,6 +440,37 @@ impl AutoLayerApp {
             layer_info.push(label);
         };
 
+        let add_keep_box = |layer: &ImplicitLayer, checked: bool| {
+            let mut check_box = Default::default();
+            nwg::CheckBox::builder()
+                .text("I need this layer, so never propose to disable it")
+                .check_state(if checked { nwg::CheckBoxState::Checked } else { nwg::CheckBoxState::Unchecked })
+                .parent(&self.window)
+                .build(&mut check_box)
+                .expect("Failed to add keep box");
+
+            let mut layer_info = self.layer_info.borrow_mut();
+            self.layout.add_child(0, layer_info.len() as u32, &check_box);
+            // Reserve the row of the check box
+            layer_info.push(Default::default());
+            self.keep_boxes.borrow_mut().push((check_box, layer.id()));
+        };
+
+        let (config, config_error) = Config::load();
+        let (known_issues, known_issues_error) = KnownIssueDatabase::load();
+        if !preflight_findings.is_empty() {
+            add_info("Before running anything, I found the following problems in the layer manifests:");
+            for finding in &preflight_findings {
+                if let Conclusion::MisconfiguredLayer { layer, problem } = &finding.conclusion {
+                    add_info(&format!("{}: {}", display_layer(&all_layers, layer), describe_layer_problem(problem)));
+                }
+            }
+            let tested_layers: Vec<ImplicitLayer> = layers.iter().map(|(layer, _)| layer.clone()).collect();
+            if preflight_is_conclusive(&preflight_findings, &tested_layers) {
+                add_info("None of the layers can be loaded, so the trials are not needed: I will skip them.");
+            }
+            add_info("");
+        }
         if layers.is_empty() {
             add_info("No truly implicit layers were found on your system.");
             add_info("If you can't run any Vulkan game, you may have bad graphics drivers.");
@@ -290,15 +478,85 @@ impl AutoLayerApp {
         } else {
             add_info("The following implicit layers will be tested:");
         }
-        for layer in &layers {
+        for (layer, state) in &layers {
             add_info("");
             add_info(&format!("Name: {}", &layer.name));
             add_info(&format!("Description: {}", &layer.description));
+            add_info(&format!("Category: {}", classify(layer, &config.layer_categories)));
+            if let Some(meta_description) = describe_meta_layer(layer) {
+                add_info(&format!("This is a {}", meta_description));
+            }
+            if let StateReason::PulledInByMetaLayer { .. } = &state.reason {
+                add_info(&format!("Note: this layer is {}", state));
+            }
+            for issue in known_issues.find_issues(layer, &all_layers, &ALL_API_VERSIONS) {
+                add_info(&issue.describe());
+            }
+            add_keep_box(layer, config.is_kept(layer));
+        }
+
+        if !inactive_layers.is_empty() {
+            add_info("");
+            add_info("The following implicit layers are inactive, so they won't be tested:");
+            for (layer, state) in &inactive_layers {
+                add_info(&format!("{} ({})", &layer.name, state));
+            }
+        }
+
+        if let Some(settings) = &env.loader_settings {
+            add_info("");
+            for line in settings.describe_overrides() {
+                add_info(&line);
+            }
+        }
+
+        add_info("");
+        add_info("The following Vulkan drivers were found:");
+        for driver in &drivers {
+            add_info(&format!("{} (Vulkan {})", driver.manifest_path, driver.api_version.as_deref().unwrap_or("?")));
+        }
+        for problem in driver_problems(&drivers) {
+            add_info(&problem);
+        }
+
+        let (explicit_layers, explicit_errors) = get_explicit_layers();
+        if !explicit_layers.is_empty() {
+            add_info("");
+            add_info("The following explicit layers are installed (they only run when something asks for them):");
+            for layer in &explicit_layers {
+                add_info(&format!("{} ({})", &layer.name, &layer.description));
+            }
         }
+        let warnings = layer_variable_warnings(&env, &explicit_layers);
+        if !warnings.is_empty() {
+            add_info("");
+            for warning in &warnings {
+                add_info(warning);
+            }
+            add_info("You can test their effect by clearing them during the trials.");
+        }
+        let (requirements, requirement_errors) = config.trial_requirements();
+        if !requirements.is_empty() {
+            add_info("");
+            add_info("The trials will fail when any of the following is unavailable:");
+            for (kind, names) in [
+                ("Instance extension", &requirements.instance_extensions),
+                ("Device extension", &requirements.device_extensions),
+                ("Device feature", &requirements.features)
+            ] {
+                for name in names {
+                    add_info(&format!("{} {}", kind, name));
+                }
+            }
+        }
+        let errors = [
+            errors, explicit_errors, driver_errors, config_error.into_iter().collect(), requirement_errors,
+            known_issues_error.into_iter().collect()
+        ].concat();
 
         if errors.len() > 0 {
             add_info("");
-            add_info("Some errors occurred while enumerating layers:");
+            add_info("Some errors occurred while preparing the trials:");
             for error in errors {
                 add_info(&error);
             }
@@ -306,14 +564,39 @@ impl AutoLayerApp {
 
         add_info("");
         add_info("Note: running all trials can take several seconds.");
+        add_info("Trials that can't change the conclusion are skipped, unless you choose to run all trials.");
 
-        *self.layer_list.borrow_mut() = layers;
+        *self.layer_list.borrow_mut() = layers.into_iter().map(|(layer, _)| layer).collect();
+        *self.requirements.borrow_mut() = requirements;
+        *self.preflight_findings.borrow_mut() = preflight_findings;
     }
 
     fn run_trials(&self) {
         let layers = self.layer_list.borrow().clone();
-        let results = run_all_trials(&layers);
-        *self.state.borrow_mut() = GuiState::AutoResultsTable(results, layers);
+        let method = if self.filter_box.check_state() == nwg::CheckBoxState::Checked {
+            DisableMethod::LoaderFilter
+        } else { DisableMethod::DisableEnvironment };
+        let options = TrialOptions {
+            method,
+            clear_layer_variables: self.clear_box.check_state() == nwg::CheckBoxState::Checked,
+            requirements: self.requirements.borrow().clone(),
+            exhaustive: self.exhaustive_box.check_state() == nwg::CheckBoxState::Checked
+        };
+        let preflight_findings = self.preflight_findings.borrow().clone();
+        // When the pre-flight check already found the answer, the trials of the layers can't tell
+        // anything new
+        let layer_trials_skipped = preflight_is_conclusive(&preflight_findings, &layers);
+        let results = if layer_trials_skipped {
+            run_baseline_trials(&layers, &options)
+        } else { run_all_trials(&layers, &options) };
+        let kept_layers: Vec<LayerId> = self.keep_boxes.borrow().iter().filter(
+            |(check_box, _)| check_box.check_state() == nwg::CheckBoxState::Checked
+        ).map(|(_, layer)| layer.clone()).collect();
+        // The fix solver may need extra trials, so it runs before the results are shown
+        let fix = if kept_layers.is_empty() || layer_trials_skipped {
+            None
+        } else { Some(find_fix(&layers, &results, &kept_layers, &options)) };
+        *self.state.borrow_mut() = GuiState::AutoResultsTable(results, layers, method, fix, preflight_findings);
         nwg::stop_thread_dispatch();
     }
 
@@ -341,6 +624,11 @@ pub struct AutoResultsApp {
 
     pub results: TestResults,
     pub layers: Vec<ImplicitLayer>,
+    pub disable_method: DisableMethod,
+    /// The fix for the layers that the user wants to keep, if any
+    pub fix: Option<KeptLayersFix>,
+    /// The problems that the pre-flight check found, which come before the findings of the trials
+    pub preflight_findings: Vec<Finding>,
 
     pub results_table: RefCell<Vec<nwg::Label>>,
     pub info_labels: RefCell<Vec<nwg::Label>>,
@@ -399,16 +687,79 @@ impl AutoResultsApp {
         add_results_entry("with all layers", &self.results.default_result);
         add_results_entry("without any layers", &self.results.clean_result);
         for (layer, results) in &self.results.exclude_results {
-            add_results_entry(&format!("without {}", layer), results);
+            add_results_entry(&format!("without {}", display_layer(&self.layers, layer)), results);
         }
         for (layer, results) in &self.results.isolation_results {
-            add_results_entry(&format!("only {}", layer), results);
+            add_results_entry(&format!("only {}", display_layer(&self.layers, layer)), results);
+        }
+        for trial in &self.results.skipped_trials {
+            add_entry(&describe_trial(trial, &self.layers), "skipped", "skipped", "skipped", "skipped");
+        }
+        for extra_trial in self.fix.iter().flat_map(|fix| &fix.extra_trials) {
+            add_results_entry(
+                &describe_trial(&TrialKind::Extra(extra_trial.disabled_layers.clone()), &self.layers),
+                &extra_trial.results
+            );
+        }
+
+        let per_version = diagnose_per_version(&self.results);
+        let problems = merge_per_version(&per_version);
+        add_entry("", "", "", "", "");
+        add_entry("problems per Vulkan version", "vk1.0", "vk1.1", "vk1.2", "vk1.3");
+        if problems.is_empty() {
+            add_entry("no problems", "", "", "", "");
+        }
+        for (finding, affected_versions) in &problems {
+            let [vk10, vk11, vk12, vk13] = affected_versions.map(|affected| if affected { "affected" } else { "" });
+            let fixes = finding.solutions.iter().map(
+                |solution| describe_solution(solution, &self.layers)
+            ).collect::<Vec<_>>().join(" or ");
+            let description = if fixes.is_empty() {
+                summarize_finding(finding, &self.layers)
+            } else {
+                format!("{} (fix: {})", summarize_finding(finding, &self.layers), fixes)
+            };
+            add_entry(&description, vk10, vk11, vk12, vk13);
+        }
+
+        let extension_changes = compute_extension_changes(&self.results);
+        if !extension_changes.is_empty() {
+            add_entry("", "", "", "", "");
+            add_entry("extensions compared to no layers", "instance extensions", "device extensions", "", "");
+        }
+        for changes in &extension_changes {
+            add_entry(
+                &format!("only {}", display_layer(&self.layers, &changes.layer)),
+                &ExtensionChanges::describe(&changes.added_instance_extensions, &changes.removed_instance_extensions),
+                &ExtensionChanges::describe(&changes.added_device_extensions, &changes.removed_device_extensions),
+                "", ""
+            );
+        }
+
+        let (config, config_error) = Config::load();
+        if let Some(config_error) = config_error {
+            add_info(&config_error);
+        }
+        let overheads = compute_overheads(&self.results);
+        if !overheads.is_empty() {
+            add_entry("", "", "", "", "");
+            add_entry("overhead compared to no layers", "vk1.0", "vk1.1", "vk1.2", "vk1.3");
+        }
+        for overhead in &overheads {
+            let mut description = format!("only {}", display_layer(&self.layers, &overhead.layer));
+            if overhead.exceeds(config.overhead_threshold_ms) {
+                description += &format!(" (SLOW: more than {} ms)", config.overhead_threshold_ms);
+            }
+            let [vk10, vk11, vk12, vk13] = overhead.overhead_micros.map(format_overhead);
+            add_entry(&description, &vk10, &vk11, &vk12, &vk13);
         }
     }
 
     fn jump_to_conclusions(&self) {
-        let conclusion = draw_conclusion(&self.results);
-        *self.state.borrow_mut() = GuiState::AutoConclusion(conclusion, self.layers.clone(), true);
+        let findings = combine_findings(self.preflight_findings.clone(), diagnose(&self.results));
+        *self.state.borrow_mut() = GuiState::AutoConclusion(
+            findings, self.layers.clone(), has_admin_privileges(), self.disable_method, self.fix.clone()
+        );
         nwg::stop_thread_dispatch();
     }
 
@@ -421,19 +772,29 @@ impl AutoResultsApp {
 #[derive(Default, nwd::NwgUi)]
 pub struct AutoConclusionApp {
     #[nwg_events( OnWindowClose: [AutoConclusionApp::close], OnInit: [AutoConclusionApp::init_conclusion] )]
-    #[nwg_control(size: (900, 400), center: true, title: "Automatic: conclusion", flags: "MAIN_WINDOW|VISIBLE")]
+    #[nwg_control(size: (900, 550), center: true, title: "Automatic: conclusion", flags: "MAIN_WINDOW|VISIBLE")]
     pub window: nwg::Window,
 
     #[nwg_layout(parent: window, spacing: 0, margin: [20, 20, 20, 20])]
     pub table_layout: nwg::GridLayout,
 
-    pub conclusion: Conclusion,
+    /// The problems that the trials revealed, where the first one is the main conclusion
+    pub findings: Vec<Finding>,
 
     pub layers: Vec<ImplicitLayer>,
 
     pub show_break_buttons: bool,
 
+    pub disable_method: DisableMethod,
+
+    /// The fix for the layers that the user wants to keep, if any. Solutions of the findings that
+    /// would disable any of these layers are hidden.
+    pub fix: Option<KeptLayersFix>,
+
     pub lines: RefCell<Vec<nwg::Label>>,
+    pub game_input: Rc<RefCell<nwg::TextInput>>,
+    /// The check boxes of the solutions, and the layers that each of them would disable
+    pub selections: Rc<RefCell<Vec<(nwg::CheckBox, Vec<ImplicitLayer>)>>>,
     pub buttons: RefCell<Vec<nwg::Button>>,
     pub handlers: RefCell<Vec<nwg::EventHandler>>,
 
@@ -459,127 +820,385 @@ impl AutoConclusionApp {
             lines.push(label);
         };
 
-        fn display_api_version(api_version: u32) -> String {
-            format!(
-                "{}.{}",
-                ash::vk::api_version_major(api_version),
-                ash::vk::api_version_minor(api_version)
-            )
+        let is_admin = has_admin_privileges();
+        // Errors of the database were already shown in the layer list
+        let (known_issues, _) = KnownIssueDatabase::load();
+        let (config, _) = Config::load();
+        let kept_layers = self.fix.as_ref().map_or(&[][..], |fix| &fix.kept_layers[..]);
+        let usable_solutions = |finding: &Finding| finding.solutions.iter().filter(
+            |solution| !disables_kept_layer(solution, kept_layers, &self.layers)
+        ).cloned().collect::<Vec<_>>();
+        let fix_solutions = match self.fix.as_ref().map(|fix| &fix.plan) {
+            Some(FixPlan::Solved(solutions)) => solutions.iter().filter(
+                |solution| !solution.disabled_layers.is_empty()
+            ).cloned().collect(),
+            _ => vec![]
+        };
+        let has_solutions = !fix_solutions.is_empty() || self.findings.iter().any(
+            |finding| !usable_solutions(finding).is_empty()
+        );
+        // When there are multiple problems, the user can select a solution for each of them, and apply them at once
+        let selectable = self.findings.iter().filter(|finding| !usable_solutions(finding).is_empty()).count() > 1;
+
+        if has_solutions {
+            add_info("Instead of disabling layers for all games, you can also save them in a profile for 1 game.");
+            add_info("To do so, enter the game executable (or Steam ID) below, and click 'Profile'.");
+            add_info("Or click 'Shortcuts' to get a Steam launch option that disables them for just 1 game.");
+            let mut game_input = self.game_input.borrow_mut();
+            nwg::TextInput::builder()
+                .text("")
+                .parent(&self.window)
+                .build(&mut game_input)
+                .expect("Failed to add game input");
+            let mut lines = self.lines.borrow_mut();
+            self.table_layout.add_child_item(nwg::GridLayoutItem::new(
+                &*game_input,
+                0, lines.len() as u32,
+                9, 1
+            ));
+            // Reserve the row of the game input
+            lines.push(Default::default());
         }
-        
-        struct Solution {
-            layer: String,
-            exclude: bool
+        if self.findings.len() > 1 {
+            add_info("");
+            add_info(&format!("I found {} separate problems.", self.findings.len()));
         }
 
-        let mut solutions = Vec::with_capacity(self.layers.len());
+        for (index, finding) in self.findings.iter().enumerate() {
+            add_info("");
+            if self.findings.len() > 1 {
+                add_info(&format!("Problem {}:", index + 1));
+            }
 
-        if self.conclusion == Conclusion::Healthy {
-            add_info("Your computer seems to be perfectly capable of running Vulkan games,");
-            add_info("even when all implicit layers are enabled.");
-        }
-        if self.conclusion == Conclusion::WeirdHealthy {
-            add_info("Weird... when no implicit layers are disabled, Vulkan games seem to work fine.");
-            add_info("However, problems appear when some layers are disabled.");
-        }
-        if self.conclusion == Conclusion::Hopeless {
-            add_info("It looks like your computer can't run any Vulkan games,");
-            add_info("but this doesn't seem to have anything to do with implicit layers.");
-            add_info("Perhaps your graphics drivers are missing or outdated?");
-        }
-        if let Conclusion::WeirdBroken { important_layer, exclude } = &self.conclusion {
-            add_info("Your implicit layers are definitely causing problems,");
-            add_info("but I didn't find the exact culprit.");
-            add_info("The easiest solution seems to be the following:");
-            solutions.push(Solution{ layer: important_layer.clone(), exclude: *exclude });
-        }
-        if let Conclusion::Partial { supported_versions } = &self.conclusion {
-            add_info("Your graphics drivers don't seem to support all versions of Vulkan,");
-            add_info("but this doesn't seem to have anything to do with your implicit layers.");
-            let mut last_line = "The following Vulkan API versions are supported: ".to_string();
-            for version in supported_versions {
-                last_line += &format!("{}, ", display_api_version(*version));
+            if finding.conclusion == Conclusion::Healthy {
+                add_info("Your computer seems to be perfectly capable of running Vulkan games,");
+                add_info("even when all implicit layers are enabled.");
+            }
+            if finding.conclusion == Conclusion::WeirdHealthy {
+                add_info("Weird... when no implicit layers are disabled, Vulkan games seem to work fine.");
+                add_info("However, problems appear when some layers are disabled.");
+            }
+            if finding.conclusion == Conclusion::Hopeless {
+                add_info("It looks like your computer can't run any Vulkan games,");
+                add_info("but this doesn't seem to have anything to do with implicit layers.");
+                let (drivers, _) = enumerate_drivers();
+                let problems = driver_problems(&drivers);
+                if problems.is_empty() {
+                    add_info("Your Vulkan drivers look fine, but perhaps they are outdated?");
+                } else {
+                    add_info("I found the following problems with your Vulkan drivers:");
+                    for problem in &problems {
+                        add_info(problem);
+                    }
+                }
+            }
+            if let Conclusion::WeirdBroken { .. } = &finding.conclusion {
+                add_info("Your implicit layers are definitely causing problems,");
+                add_info("but I didn't find the exact culprit.");
+                add_info("The easiest solution seems to be the following:");
+            }
+            if let Conclusion::Partial { supported_versions } = &finding.conclusion {
+                add_info("Your graphics drivers don't seem to support all versions of Vulkan,");
+                add_info("but this doesn't seem to have anything to do with your implicit layers.");
+                let mut last_line = "The following Vulkan API versions are supported: ".to_string();
+                for version in supported_versions {
+                    last_line += &format!("{}, ", display_api_version(*version));
+                }
+            }
+            if let Conclusion::BrokenLayer { layer } = &finding.conclusion {
+                add_info(&format!(
+                    "{} seems to be completely broken, so you should disable it.", display_layer(&self.layers, layer)
+                ));
+            }
+            if let Conclusion::PartiallyBrokenLayer { layer, broken_versions } = &finding.conclusion {
+                add_info(&format!(
+                    "{} doesn't seem to support all Vulkan versions that your drivers support.",
+                    display_layer(&self.layers, layer)
+                ));
+                let mut next_line = "In particular, it doesn't support Vulkan ".to_string();
+                for version in broken_versions {
+                    next_line += &format!("{}, ", display_api_version(*version));
+                }
+                add_info("I recommend disabling it.");
+            }
+            if let Conclusion::SymmetricConflict { .. } = &finding.conclusion {
+                add_info("Some layers are conflicting with each other. I recommend disabling one of them.");
+            }
+            if let Conclusion::AsymmetricConflict { main_offender } = &finding.conclusion {
+                add_info(&format!(
+                    "{} conflicts with multiple other layers. I recommend disabling it.",
+                    display_layer(&self.layers, main_offender)
+                ));
+            }
+            if let Conclusion::HiddenExtensions { instance_extensions, device_extensions, .. } = &finding.conclusion {
+                add_info("All trials succeeded, but one of your implicit layers hides some extensions:");
+                if !instance_extensions.is_empty() {
+                    add_info(&format!("instance extensions: {}", instance_extensions.join(", ")));
+                }
+                if !device_extensions.is_empty() {
+                    add_info(&format!("device extensions: {}", device_extensions.join(", ")));
+                }
+                add_info("Games that need these extensions may not work. You can fix this with:");
+            }
+            if let Conclusion::Inconclusive { failed_trials } = &finding.conclusion {
+                add_info("The trial results don't match any pattern that I know, so I can't draw a conclusion.");
+                add_info("The following trials failed:");
+                for (trial, failed_versions) in failed_trials {
+                    add_info(&format!(
+                        "{} failed on Vulkan {}", describe_trial(trial, &self.layers), display_api_versions(failed_versions)
+                    ));
+                }
+                add_info("You can try Manual mode, or run the trials again to check whether the results are consistent.");
+            }
+            if finding.conclusion == Conclusion::ComplexConflict {
+                add_info("Multiple layers are conflicting with multiple other layers.");
+                add_info("I recommend disabling all layers except 1 (pick the one you want to have)");
+            }
+            if let Conclusion::MisconfiguredLayer { layer, problem } = &finding.conclusion {
+                add_info(&format!(
+                    "I found a problem in the manifest of {}: {}.", display_layer(&self.layers, layer), describe_layer_problem(problem)
+                ));
+                if !finding.solutions.is_empty() {
+                    add_info(if problem.is_certain() {
+                        "I recommend disabling it, or reinstalling the software that it belongs to."
+                    } else { "If it causes problems, you can try updating it or disabling it." });
+                }
+            }
+
+            add_info("");
+            add_info("Why I came to this conclusion:");
+            for line in describe_trace(finding, &self.layers) {
+                add_info(&line);
+            }
+
+            let mut involved_layers = conclusion_layers(&finding.conclusion);
+            if involved_layers.is_empty() {
+                involved_layers = finding.solutions.iter().flat_map(|solution| &solution.disabled_layers).cloned().collect();
+            }
+            let failed_versions = failed_api_versions(finding);
+            for layer in self.layers.iter().filter(|layer| involved_layers.contains(&layer.id())) {
+                for issue in known_issues.find_issues(layer, &self.layers, &failed_versions) {
+                    add_info(&format!("{}: {}", layer.name, issue.describe()));
+                }
+            }
+
+            let solutions = usable_solutions(finding);
+            if solutions.len() < finding.solutions.len() {
+                add_info(&format!(
+                    "I left out {} solution(s) that would disable a layer that you want to keep.",
+                    finding.solutions.len() - solutions.len()
+                ));
+            }
+            for (solution_index, solution) in solutions.iter().enumerate() {
+                self.add_solution_row(solution, selectable, solution_index == 0, is_admin, &config.layer_categories);
             }
         }
-        if let Conclusion::BrokenLayer { layer } = &self.conclusion {
-            add_info(&format!("{} seems to be completely broken, so you should disable it.", layer));
-            solutions.push(Solution{ layer: layer.clone(), exclude: true });
+
+        if selectable {
+            self.add_apply_selected_row(is_admin, &config.layer_categories);
         }
-        if let Conclusion::PartiallyBrokenLayer { layer, broken_versions } = &self.conclusion {
-            add_info(&format!("{} doesn't seem to support all Vulkan versions that your drivers support.", layer));
-            let mut next_line = "In particular, it doesn't support Vulkan ".to_string();
-            for version in broken_versions {
-                next_line += &format!("{}, ", display_api_version(*version));
+
+        if let Some(fix) = &self.fix {
+            add_info("");
+            add_info(&format!("You want to keep {}.", fix.kept_layers.iter().map(
+                |layer| display_layer(&self.layers, layer)
+            ).collect::<Vec<_>>().join(", ")));
+            match &fix.plan {
+                FixPlan::Solved(_) if fix_solutions.is_empty() => {
+                    add_info("No layers need to be disabled to keep them.");
+                },
+                FixPlan::Solved(_) => {
+                    add_info("The smallest fix that keeps them and solves all problems at once is any of the following:");
+                },
+                FixPlan::NeedsTrials(_) => {
+                    add_info("I couldn't find out which layers to disable to keep them, not even with extra trials.");
+                    add_info("You can try Manual mode, or keep fewer layers.");
+                },
+                FixPlan::Impossible => {
+                    add_info("Unfortunately, the trials fail even when all other layers are disabled,");
+                    add_info("so at least 1 of the layers that you want to keep seems to be broken.");
+                }
             }
-            add_info("I recommend disabling it.");
-            solutions.push(Solution{ layer: layer.clone(), exclude: true });
-        }
-        if let Conclusion::SymmetricConflict { layers } = &self.conclusion {
-            add_info("Some layers are conflicting with each other. I recommend disabling one of them.");
-            for layer in layers {
-                solutions.push(Solution{ layer: layer.clone(), exclude: true });
+            for line in describe_fix(fix, &self.layers) {
+                add_info(&line);
+            }
+            for solution in &fix_solutions {
+                self.add_solution_row(solution, false, false, is_admin, &config.layer_categories);
             }
         }
-        if let Conclusion::AsymmetricConflict { main_offender } = &self.conclusion {
-            add_info(&format!("{} conflicts with multiple other layers. I recommend disabling it.", main_offender));
-            solutions.push(Solution{ layer: main_offender.clone(), exclude: true });
-        }
-        if self.conclusion == Conclusion::ComplexConflict {
-            add_info("Multiple layers are conflicting with multiple other layers.");
-            add_info("I recommend disabling all layers except 1 (pick the one you want to have)");
-            for layer in &self.layers {
-                solutions.push(Solution{ layer: layer.name.clone(), exclude: false });
+
+        add_info("");
+        if !has_solutions {
+            add_info("Note that this automatic test catches most obviously broken layers,");
+            add_info("but NOT subtly broken layers.");
+            add_info("If you still have problems, you can always restart this application in Manual mode,");
+            add_info("and manually try to find the culprit (or just disable all layers).");
+        } else {
+            add_info("You can either break or disable layers.");
+            add_info("Disabling layers is easiest, but a computer restart may or may not be needed.");
+            if is_admin {
+                add_info("Use 'All users' to disable layers for every user of this computer.");
+            }
+            add_info("Breaking layers usually requires administrator privileges.");
+            add_info("When you break a layer, no restart is needed, but it's difficult to recover it later.");
+            if !self.show_break_buttons {
+                add_info("");
+                add_info("If you want to Break layers, you need to restart this application");
+                add_info("with administrator privileges.");
             }
         }
+    }
 
-        for solution in &solutions {
-            let layer = self.layers.iter().find(
-                |layer| layer.name == solution.layer
-            ).expect("Solution must have a valid layer");
+    /// Adds a row with buttons that disable the layers of all selected solutions at once
+    fn add_apply_selected_row(&self, is_admin: bool, categories: &HashMap<String, LayerCategory>) {
+        let mut label = Default::default();
+        nwg::Label::builder()
+            .text("Apply all checked solutions at once:")
+            .parent(&self.window)
+            .build(&mut label)
+            .expect("Failed to add line");
+
+        let mut lines = self.lines.borrow_mut();
+        let row = lines.len() as u32;
+        self.table_layout.add_child_item(nwg::GridLayoutItem::new(
+            &label,
+            0, row,
+            if is_admin { 6 } else { 7 }, 1
+        ));
+        lines.push(label);
+
+        let mut scopes = vec![(EnvironmentScope::User, "Disable", if is_admin { 6 } else { 7 })];
+        if is_admin {
+            scopes.push((EnvironmentScope::System, "All users", 7));
+        }
+        for (scope, text, column) in scopes {
+            let mut apply_button = Default::default();
+            nwg::Button::builder()
+                .text(text)
+                .parent(&self.window)
+                .build(&mut apply_button)
+                .expect("Failed to add apply button");
+            let apply_button_handle = apply_button.handle;
+
+            let selections = Rc::clone(&self.selections);
+            let categories = categories.clone();
+            let disable_method = self.disable_method;
+            let state_ref = Rc::clone(&self.state);
+            let apply_handler = nwg::bind_event_handler(
+                &apply_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
+                    if evt == nwg::Event::OnButtonClick && handle == apply_button_handle {
+                        let selections = selections.borrow();
+                        let mut disabled_layers: Vec<&ImplicitLayer> = Vec::new();
+                        for (check_box, layers) in selections.iter() {
+                            if check_box.check_state() == nwg::CheckBoxState::Checked {
+                                for layer in layers {
+                                    if !disabled_layers.contains(&layer) {
+                                        disabled_layers.push(layer);
+                                    }
+                                }
+                            }
+                        }
+                        if disabled_layers.is_empty() {
+                            nwg::modal_info_message(handle, "Nothing selected", "Please check at least 1 solution first");
+                            return;
+                        }
+                        let risk = risk_warning(&disabled_layers, &categories);
+                        if !confirm_risky_layers(handle, risk.as_deref(), "disable these layers") {
+                            return;
+                        }
 
-            let description = if solution.exclude {
-                format!("Disable {}", solution.layer)
-            } else { format!("Disable all layers except {}", solution.layer) };
+                        *state_ref.borrow_mut() = if disable_layers(scope, &disabled_layers, disable_method) {
+                            GuiState::AutoFinished(false)
+                        } else {
+                            GuiState::AutoFailed("Failed to disable 1 or more layers".to_string(), false)
+                        };
+                        nwg::stop_thread_dispatch();
+                    }
+                }
+            );
+            self.handlers.borrow_mut().push(apply_handler);
+            self.table_layout.add_child_item(nwg::GridLayoutItem::new(
+                &apply_button,
+                column, row,
+                1, 1
+            ));
+            self.buttons.borrow_mut().push(apply_button);
+        }
+    }
 
+    /// Adds a row with the description of the solution, and buttons to apply it. When `selectable`
+    /// is true, the description gets a check box, so the solution can be applied together with
+    /// the selected solutions of the other findings.
+    fn add_solution_row(
+        &self, solution: &Solution, selectable: bool, selected: bool, is_admin: bool,
+        categories: &HashMap<String, LayerCategory>
+    ) {
+        let description = describe_solution(solution, &self.layers);
+        let disabled_layers: Vec<ImplicitLayer> = solution_layers(solution, &self.layers).into_iter().cloned().collect();
+        let risk = risk_warning(&disabled_layers.iter().collect::<Vec<_>>(), categories);
+
+        let mut lines = self.lines.borrow_mut();
+        let mut buttons = self.buttons.borrow_mut();
+        let mut handlers = self.handlers.borrow_mut();
+        let row = lines.len() as u32;
+        let description_span = if is_admin { 6 } else { 7 };
+        if selectable {
+            let mut check_box = Default::default();
+            nwg::CheckBox::builder()
+                .text(&description)
+                .check_state(if selected { nwg::CheckBoxState::Checked } else { nwg::CheckBoxState::Unchecked })
+                .parent(&self.window)
+                .build(&mut check_box)
+                .expect("Failed to add check box");
+            self.table_layout.add_child_item(nwg::GridLayoutItem::new(
+                &check_box,
+                0, row,
+                description_span, 1
+            ));
+            self.selections.borrow_mut().push((check_box, disabled_layers.clone()));
+            // Reserve the row of the check box
+            lines.push(Default::default());
+        } else {
             let mut label = Default::default();
             nwg::Label::builder()
                 .text(&description)
                 .parent(&self.window)
                 .build(&mut label)
                 .expect("Failed to add line");
-
-            let mut lines = self.lines.borrow_mut();
-            let mut buttons = self.buttons.borrow_mut();
-            let mut handlers = self.handlers.borrow_mut();
-            let row = lines.len() as u32;
             self.table_layout.add_child_item(nwg::GridLayoutItem::new(
                 &label,
                 0, row,
-                7, 1
+                description_span, 1
             ));
             lines.push(label);
+        }
 
+        let mut scopes = vec![(EnvironmentScope::User, "Disable", if is_admin { 6 } else { 7 })];
+        if is_admin {
+            scopes.push((EnvironmentScope::System, "All users", 7));
+        }
+        for (scope, text, column) in scopes {
             let mut disable_button = Default::default();
             nwg::Button::builder()
-                .text("Disable")
+                .text(text)
                 .parent(&self.window)
                 .build(&mut disable_button)
                 .expect("Failed to add disable button");
             let disable_button_handle = disable_button.handle;
 
-            let disable_envs = if solution.exclude {
-                vec![layer.disable_environment.clone()]
-            } else {
-                self.layers.iter()
-                    .filter(|candidate| candidate.name != solution.layer)
-                    .map(|candidate| candidate.disable_environment.clone())
-                    .collect()
-            };
+            let disabled_layers = disabled_layers.clone();
+            let risk = risk.clone();
+            let disable_method = self.disable_method;
             let state_ref = Rc::clone(&self.state);
             let toggle_handler = nwg::bind_event_handler(
                 &disable_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
                     if evt == nwg::Event::OnButtonClick && handle == disable_button_handle {
-                        let succeeded = disable_envs.iter().all(|env| set_user_environment(env));
+                        if !confirm_risky_layers(handle, risk.as_deref(), "disable these layers") {
+                            return;
+                        }
+                        let succeeded = disable_layers(
+                            scope, &disabled_layers.iter().collect::<Vec<_>>(), disable_method
+                        );
                         if succeeded {
                             *state_ref.borrow_mut() = GuiState::AutoFinished(false);
                             nwg::stop_thread_dispatch();
@@ -593,92 +1212,146 @@ impl AutoConclusionApp {
             handlers.push(toggle_handler);
             self.table_layout.add_child_item(nwg::GridLayoutItem::new(
                 &disable_button,
-                7, row,
+                column, row,
                 1, 1
             ));
             buttons.push(disable_button);
-            if self.show_break_buttons {
-                
-                let mut break_button = Default::default();
-                nwg::Button::builder()
-                    .text("Break")
-                    .parent(&self.window)
-                    .build(&mut break_button)
-                    .expect("Failed to add break button");
-                
-                let break_button_handle = break_button.handle;
-                let state_ref = Rc::clone(&self.state);
-                let cloned_conclusion = self.conclusion.clone();
-                let cloned_layers = self.layers.clone();
-                let files_to_delete = if solution.exclude {
-                    vec![layer.settings_path.clone()]
-                } else {
-                    self.layers.iter()
-                        .filter(|candidate| candidate.name != solution.layer)
-                        .map(|candidate| candidate.settings_path.clone())
-                        .collect()
-                };
-                let break_handler = nwg::bind_event_handler(
-                    &break_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
-                        if evt == nwg::Event::OnButtonClick && handle == break_button_handle {
-                            let mut error: Option<std::io::Error> = None;
-                            for file in &files_to_delete {
-                                let delete_result = std::fs::remove_file(file);
-                                if let Err(failed_delete) = delete_result {
-                                    if failed_delete.kind() == ErrorKind::PermissionDenied {
-                                        *state_ref.borrow_mut() = GuiState::AutoConclusion(
-                                            cloned_conclusion.clone(),
-                                            cloned_layers.clone(),
-                                            false
-                                        );
-                                        nwg::stop_thread_dispatch();
-                                        return;
-                                    } else {
-                                        error = Some(failed_delete);
-                                    }
-                                }
-                            }
-                            
-                            if let Some(failed) = error {
-                                *state_ref.borrow_mut() = GuiState::AutoFailed(failed.to_string(), true);
-                                nwg::stop_thread_dispatch();
-                            } else {
-                                *state_ref.borrow_mut() = GuiState::AutoFinished(true);
-                                nwg::stop_thread_dispatch();
-                            }
-                        }
-                    }
-                );
-                handlers.push(break_handler);
-                
-                self.table_layout.add_child_item(nwg::GridLayoutItem::new(
-                    &break_button,
-                    8, row,
-                    1, 1
-                ));
-                buttons.push(break_button);
-            }
         }
+        let mut profile_button = Default::default();
+        nwg::Button::builder()
+            .text("Profile")
+            .parent(&self.window)
+            .build(&mut profile_button)
+            .expect("Failed to add profile button");
+        let profile_button_handle = profile_button.handle;
+
+        let profile_layers: Vec<String> = disabled_layers.iter().map(|layer| layer.name.clone()).collect();
+        let game_input_ref = Rc::clone(&self.game_input);
+        let disable_method = self.disable_method;
+        let state_ref = Rc::clone(&self.state);
+        let profile_handler = nwg::bind_event_handler(
+            &profile_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
+                if evt == nwg::Event::OnButtonClick && handle == profile_button_handle {
+                    let game = game_input_ref.borrow().text().trim().to_string();
+                    if game.is_empty() {
+                        nwg::modal_info_message(
+                            handle, "Missing game", "Please enter the game executable or ID first"
+                        );
+                        return;
+                    }
 
-        add_info("");
-        if solutions.is_empty() {
-            add_info("Note that this automatic test catches most obviously broken layers,");
-            add_info("but NOT subtly broken layers.");
-            add_info("If you still have problems, you can always restart this application in Manual mode,");
-            add_info("and manually try to find the culprit (or just disable all layers).");
-        } else {
-            add_info("You can either break or disable layers.");
-            add_info("Disabling layers is easiest, but a computer restart may or may not be needed.");
-            add_info("Breaking layers usually requires administrator privileges.");
-            add_info("When you break a layer, no restart is needed, but it's difficult to recover it later.");
-            if !self.show_break_buttons {
-                add_info("");
-                add_info("If you want to Break layers, you need to restart this application");
-                add_info("with administrator privileges.");
+                    let profile = Profile {
+                        name: game.rsplit(['/', '\\']).next().unwrap_or(&game).to_string(),
+                        game,
+                        disabled_layers: profile_layers.clone(),
+                        method: disable_method
+                    };
+                    let save_result = ProfileStore::default_location().ok_or(
+                        "Can't find the config directory".to_string()
+                    ).and_then(|store| store.save(profile).map_err(|error| error.to_string()));
+                    *state_ref.borrow_mut() = match save_result {
+                        Ok(()) => GuiState::Profiles,
+                        Err(error) => GuiState::AutoFailed(format!("Failed to save profile: {}", error), false)
+                    };
+                    nwg::stop_thread_dispatch();
+                }
             }
+        );
+        handlers.push(profile_handler);
+        self.table_layout.add_child_item(nwg::GridLayoutItem::new(
+            &profile_button,
+            9, row,
+            1, 1
+        ));
+        buttons.push(profile_button);
+
+        let mut shortcuts_button = Default::default();
+        nwg::Button::builder()
+            .text("Shortcuts")
+            .parent(&self.window)
+            .build(&mut shortcuts_button)
+            .expect("Failed to add shortcuts button");
+        let shortcuts_button_handle = shortcuts_button.handle;
+
+        let shortcut_layers = disabled_layers.clone();
+        let shortcut_method = self.disable_method;
+        let game_input_ref = Rc::clone(&self.game_input);
+        let shortcuts_handler = nwg::bind_event_handler(
+            &shortcuts_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
+                if evt == nwg::Event::OnButtonClick && handle == shortcuts_button_handle {
+                    let mut game = game_input_ref.borrow().text().trim().to_string();
+                    if game.is_empty() {
+                        game = "<game>".to_string();
+                    }
+                    let shortcuts = generate_shortcuts(
+                        &shortcut_layers.iter().collect::<Vec<_>>(), &game, TargetPlatform::current(),
+                        shortcut_method, &own_executable_path()
+                    );
+                    nwg::Clipboard::set_data_text(handle, &shortcuts.steam_launch_option);
+                    nwg::modal_info_message(handle, "Shortcuts", &format!(
+                        "The following Steam launch option was copied to your clipboard:\n{}\n\n\
+                        Alternatively, you can launch the game with this script:\n{}",
+                        shortcuts.steam_launch_option, shortcuts.launcher_script
+                    ));
+                }
+            }
+        );
+        handlers.push(shortcuts_handler);
+        self.table_layout.add_child_item(nwg::GridLayoutItem::new(
+            &shortcuts_button,
+            10, row,
+            1, 1
+        ));
+        buttons.push(shortcuts_button);
+
+        if self.show_break_buttons {
+            
+            let mut break_button = Default::default();
+            nwg::Button::builder()
+                .text("Break")
+                .parent(&self.window)
+                .build(&mut break_button)
+                .expect("Failed to add break button");
+            
+            let break_button_handle = break_button.handle;
+            let state_ref = Rc::clone(&self.state);
+            let files_to_delete: Vec<String> = disabled_layers.iter().map(
+                |disabled_layer| disabled_layer.settings_path.clone()
+            ).collect();
+            let break_handler = nwg::bind_event_handler(
+                &break_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
+                    if evt == nwg::Event::OnButtonClick && handle == break_button_handle {
+                        if !confirm_risky_layers(handle, risk.as_deref(), "break these layers") {
+                            return;
+                        }
+                        let mut error: Option<std::io::Error> = None;
+                        for file in &files_to_delete {
+                            let delete_result = std::fs::remove_file(file);
+                            if let Err(failed_delete) = delete_result {
+                                error = Some(failed_delete);
+                            }
+                        }
+                        
+                        if let Some(failed) = error {
+                            *state_ref.borrow_mut() = GuiState::AutoFailed(failed.to_string(), true);
+                            nwg::stop_thread_dispatch();
+                        } else {
+                            *state_ref.borrow_mut() = GuiState::AutoFinished(true);
+                            nwg::stop_thread_dispatch();
+                        }
+                    }
+                }
+            );
+            handlers.push(break_handler);
+            
+            self.table_layout.add_child_item(nwg::GridLayoutItem::new(
+                &break_button,
+                8, row,
+                1, 1
+            ));
+            buttons.push(break_button);
         }
     }
-
     fn close(&self) {
         *self.state.borrow_mut() = GuiState::Exit;
         nwg::stop_thread_dispatch();
@@ -752,7 +1425,7 @@ impl AutoFailedApp {
         if self.did_break {
             self.description.set_text(&format!("Failed to break 1 or more layers:\n{}", self.error));
         } else {
-            self.description.set_text("Disabling 1 or more layers failed for some reason");
+            self.description.set_text(&self.error);
         }
     }
 
@@ -761,3 +1434,148 @@ impl AutoFailedApp {
         nwg::stop_thread_dispatch();
     }
 }
+
+#[derive(Default, nwd::NwgUi)]
+pub struct ProfilesApp {
+    #[nwg_events( OnWindowClose: [ProfilesApp::close], OnInit: [ProfilesApp::init_profiles] )]
+    #[nwg_control(size: (750, 500), center: true, title: "Game profiles", flags: "MAIN_WINDOW|VISIBLE")]
+    pub window: nwg::Window,
+
+    #[nwg_layout(parent: window, spacing: 0, margin: [20, 20, 160, 20])]
+    pub layout: nwg::GridLayout,
+
+    #[nwg_control(text: "Name", size: (150, 25), position: (20, 360))]
+    pub name_label: nwg::Label,
+
+    #[nwg_control(text: "", size: (350, 25), position: (170, 360))]
+    pub name_input: nwg::TextInput,
+
+    #[nwg_control(text: "Game executable or ID", size: (150, 25), position: (20, 395))]
+    pub game_label: nwg::Label,
+
+    #[nwg_control(text: "", size: (350, 25), position: (170, 395))]
+    pub game_input: nwg::TextInput,
+
+    #[nwg_control(text: "Disabled layers", size: (150, 25), position: (20, 430))]
+    pub layers_label: nwg::Label,
+
+    #[nwg_control(text: "", size: (350, 25), position: (170, 430))]
+    pub layers_input: nwg::TextInput,
+
+    #[nwg_control(text: "Save profile", size: (150, 60), position: (560, 375))]
+    #[nwg_events( OnButtonClick: [ProfilesApp::save_profile] )]
+    pub save_button: nwg::Button,
+
+    pub lines: RefCell<Vec<nwg::Label>>,
+    pub buttons: RefCell<Vec<nwg::Button>>,
+    pub handlers: RefCell<Vec<nwg::EventHandler>>,
+
+    pub state: Rc<RefCell<GuiState>>
+}
+
+impl ProfilesApp {
+    fn init_profiles(&self) {
+        let add_info = |text: &str| {
+            let mut label = Default::default();
+            nwg::Label::builder()
+                .text(text)
+                .parent(&self.window)
+                .build(&mut label)
+                .expect("Failed to add line");
+
+            let mut lines = self.lines.borrow_mut();
+            self.layout.add_child_item(nwg::GridLayoutItem::new(
+                &label,
+                0, lines.len() as u32,
+                7, 1
+            ));
+            lines.push(label);
+        };
+
+        add_info("Profiles disable layers for only 1 game, rather than for all games.");
+        add_info("To use them, launch the game via 'vk-fixer run -- <game executable>',");
+        add_info("for instance by using '\"<path to vk-fixer>\" run -- %command%' as Steam launch option.");
+        add_info("To add or change a profile, fill in the fields below (separate layers with commas).");
+        add_info("");
+
+        let store = match ProfileStore::default_location() {
+            Some(store) => store,
+            None => {
+                add_info("Can't find the config directory, so profiles are unavailable.");
+                return;
+            }
+        };
+        let profiles = match store.list() {
+            Ok(profiles) => profiles,
+            Err(error) => {
+                add_info(&format!("Failed to load profiles: {}", error));
+                return;
+            }
+        };
+        if profiles.is_empty() {
+            add_info("You don't have any profiles yet.");
+        }
+
+        for profile in profiles {
+            add_info(&format!("{}: {} without {}", profile.name, profile.game, profile.disabled_layers.join(", ")));
+
+            let mut remove_button = Default::default();
+            nwg::Button::builder()
+                .text("Remove")
+                .parent(&self.window)
+                .build(&mut remove_button)
+                .expect("Failed to add remove button");
+            let remove_button_handle = remove_button.handle;
+
+            let store = store.clone();
+            let state_ref = Rc::clone(&self.state);
+            let remove_handler = nwg::bind_event_handler(
+                &remove_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
+                    if evt == nwg::Event::OnButtonClick && handle == remove_button_handle {
+                        if let Err(error) = store.remove(&profile.name) {
+                            nwg::modal_error_message(handle, "Failed to remove profile", &error.to_string());
+                        }
+                        *state_ref.borrow_mut() = GuiState::Profiles;
+                        nwg::stop_thread_dispatch();
+                    }
+                }
+            );
+            self.handlers.borrow_mut().push(remove_handler);
+            self.layout.add_child_item(nwg::GridLayoutItem::new(
+                &remove_button,
+                7, self.lines.borrow().len() as u32 - 1,
+                1, 1
+            ));
+            self.buttons.borrow_mut().push(remove_button);
+        }
+    }
+
+    fn save_profile(&self) {
+        let name = self.name_input.text().trim().to_string();
+        let game = self.game_input.text().trim().to_string();
+        let disabled_layers: Vec<String> = self.layers_input.text().split(',').map(
+            |layer| layer.trim().to_string()
+        ).filter(|layer| !layer.is_empty()).collect();
+        if name.is_empty() || game.is_empty() || disabled_layers.is_empty() {
+            nwg::modal_info_message(&self.window, "Incomplete profile", "Please fill in all fields");
+            return;
+        }
+
+        let profile = Profile { name, game, disabled_layers, method: DisableMethod::DisableEnvironment };
+        let save_result = ProfileStore::default_location().ok_or(
+            "Can't find the config directory".to_string()
+        ).and_then(|store| store.save(profile).map_err(|error| error.to_string()));
+        if let Err(error) = save_result {
+            nwg::modal_error_message(&self.window, "Failed to save profile", &error);
+            return;
+        }
+
+        *self.state.borrow_mut() = GuiState::Profiles;
+        nwg::stop_thread_dispatch();
+    }
+
+    fn close(&self) {
+        *self.state.borrow_mut() = GuiState::Exit;
+        nwg::stop_thread_dispatch();
+    }
+}
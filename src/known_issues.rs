@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+use std::env::args;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use crate::config::config_directory;
+use crate::definitions::{Finding, ImplicitLayer, TraceStep, ALL_API_VERSIONS};
+use crate::drivers::resolve_library_path;
+use crate::registry::get_implicit_layers;
+use crate::report::display_api_version;
+use crate::sha256::sha256_hex;
+
+/// The database that is bundled with vk-fixer. Users can add their own issues in
+/// `known-issues.json` in the config directory.
+const BUNDLED_DATABASE: &str = include_str!("../known-issues.json");
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The layer is known to be harmless
+    Harmless,
+    Info,
+    Warning,
+    /// The layer is known to break Vulkan games
+    Critical
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Keep,
+    Update,
+    Disable
+}
+
+/// An issue of a layer that is known to vk-fixer, and what to do about it
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct KnownIssue {
+    /// The name of the layer (ignoring case). A trailing `*` matches any suffix, like the `_32`
+    /// and `_64` variants of a layer.
+    pub layer: String,
+    /// When not empty, the issue only applies to these `implementation_version`s of the layer
+    #[serde(default)]
+    pub versions: Vec<String>,
+    /// When not empty, the issue only applies to layers whose library has one of these hashes
+    /// (see `library_hash`)
+    #[serde(default)]
+    pub library_hashes: Vec<String>,
+    /// When not empty, the issue only occurs in games that use one of these Vulkan versions, like
+    /// `"1.3"`
+    #[serde(default)]
+    pub api_versions: Vec<String>,
+    /// When not empty, the issue only applies when at least 1 of these layers is installed too
+    #[serde(default)]
+    pub conflicts_with: Vec<String>,
+    pub vendor: String,
+    pub severity: Severity,
+    pub action: Action,
+    pub summary: String
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+pub struct KnownIssueDatabase {
+    pub issues: Vec<KnownIssue>
+}
+
+fn matches_name(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.len() >= prefix.len() && name.is_char_boundary(prefix.len()) &&
+            name[.. prefix.len()].eq_ignore_ascii_case(prefix),
+        None => pattern.eq_ignore_ascii_case(name)
+    }
+}
+
+/// The path of a library, together with its size and modification time
+type LibraryVersion = (PathBuf, u64, Option<SystemTime>);
+
+/// The libraries that `library_hash` hashed, and their hash. The GUI looks up the known issues of
+/// every layer several times, which would otherwise read and hash every library each time.
+static LIBRARY_HASHES: Mutex<Vec<(LibraryVersion, String)>> = Mutex::new(Vec::new());
+
+/// The SHA-256 hash of the library of the layer, as 64 hexadecimal digits. Returns `None` when
+/// the layer doesn't have a library, when its library is a bare file name (which the system
+/// searches in its library path), or when the library can't be read. Each library is only hashed
+/// again when its size or modification time changes.
+pub fn library_hash(layer: &ImplicitLayer) -> Option<String> {
+    let library = resolve_library_path(&layer.settings_path, layer.library_path.as_ref()?)?;
+    let metadata = fs::metadata(&library).ok()?;
+    let key = (library, metadata.len(), metadata.modified().ok());
+    let mut hashes = LIBRARY_HASHES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some((_, hash)) = hashes.iter().find(|(candidate, _)| candidate == &key) {
+        return Some(hash.clone());
+    }
+    let hash = sha256_hex(&fs::read(&key.0).ok()?);
+    hashes.retain(|((path, _, _), _)| path != &key.0);
+    hashes.push((key, hash.clone()));
+    Some(hash)
+}
+
+/// The Vulkan versions for which the decisive trials of `finding` failed, or all versions when
+/// its trace doesn't say
+pub fn failed_api_versions(finding: &Finding) -> Vec<u32> {
+    let mut failed_versions: Vec<u32> = finding.trace.iter().flat_map(|step| match step {
+        TraceStep::Trial { failed_versions, .. } => failed_versions.clone(),
+        _ => vec![]
+    }).collect();
+    failed_versions.sort();
+    failed_versions.dedup();
+    if failed_versions.is_empty() {
+        ALL_API_VERSIONS.to_vec()
+    } else { failed_versions }
+}
+
+impl KnownIssueDatabase {
+    pub fn bundled() -> Self {
+        serde_json::from_str(BUNDLED_DATABASE).expect("The bundled known-issues.json should be valid")
+    }
+
+    /// Loads the issues of the user from the given file, followed by the bundled issues. When the
+    /// file doesn't exist, only the bundled issues are used.
+    pub fn load_from(path: &Path) -> Result<Self, String> {
+        let mut database = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(
+                |parse_error| format!("Failed to parse {}: {}", path.display(), parse_error)
+            )?,
+            Err(error) if error.kind() == ErrorKind::NotFound => Self::default(),
+            Err(error) => return Err(format!("Failed to read {}: {}", path.display(), error))
+        };
+        database.issues.extend(Self::bundled().issues);
+        Ok(database)
+    }
+
+    /// Loads the database from the config directory. When that fails, only the bundled issues are
+    /// used, and the error is returned as well.
+    pub fn load() -> (Self, Option<String>) {
+        match config_directory() {
+            Some(directory) => match Self::load_from(&directory.join("known-issues.json")) {
+                Ok(database) => (database, None),
+                Err(error) => (Self::bundled(), Some(error))
+            },
+            None => (Self::bundled(), None)
+        }
+    }
+
+    /// Finds the known issues of `layer`, where `layers` are all installed implicit layers, and
+    /// `api_versions` are the Vulkan versions that matter (like `ALL_API_VERSIONS`)
+    pub fn find_issues(&self, layer: &ImplicitLayer, layers: &[ImplicitLayer], api_versions: &[u32]) -> Vec<&KnownIssue> {
+        let mut hash = None;
+        self.issues.iter().filter(|issue| {
+            if !matches_name(&issue.layer, &layer.name) {
+                return false;
+            }
+            if !issue.api_versions.is_empty() && !api_versions.iter().any(
+                |api_version| issue.api_versions.iter().any(|candidate| candidate.trim() == display_api_version(*api_version))
+            ) {
+                return false;
+            }
+            if !issue.versions.is_empty() && !layer.implementation_version.as_ref().is_some_and(
+                |version| issue.versions.contains(version)
+            ) {
+                return false;
+            }
+            if !issue.conflicts_with.is_empty() && !layers.iter().any(|other| other != layer && issue.conflicts_with.iter().any(
+                |pattern| matches_name(pattern, &other.name)
+            )) {
+                return false;
+            }
+            // Hashing a library takes a while, so only do it when needed
+            issue.library_hashes.is_empty() || hash.get_or_insert_with(|| library_hash(layer)).as_ref().is_some_and(
+                |hash| issue.library_hashes.iter().any(|candidate| candidate.eq_ignore_ascii_case(hash))
+            )
+        }).collect()
+    }
+}
+
+impl KnownIssue {
+    /// Describes the issue like "Known issue (warning, by Guru3D): ... Recommended: update it"
+    pub fn describe(&self) -> String {
+        let severity = match self.severity {
+            Severity::Harmless => "harmless",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical"
+        };
+        let action = match self.action {
+            Action::Keep => "keep it",
+            Action::Update => "update it",
+            Action::Disable => "disable it"
+        };
+        let mut context = String::new();
+        if !self.conflicts_with.is_empty() {
+            context += &format!(" (together with {})", self.conflicts_with.join(", "));
+        }
+        if !self.api_versions.is_empty() {
+            context += &format!(" (on Vulkan {})", self.api_versions.join(", "));
+        }
+        format!("Known issue ({}, by {}){}: {} Recommended: {}", severity, self.vendor, context, self.summary, action)
+    }
+}
+
+/// Handles `vk-fixer known-issues`, which prints the version and library hash of each implicit
+/// layer, together with its known issues. This helps users to add their own issues.
+pub fn maybe_run_known_issues_command() {
+    let args = args().collect::<Vec<_>>();
+    if args.len() >= 2 && args[1] == "known-issues" {
+        let (database, database_error) = KnownIssueDatabase::load();
+        let (layers, errors) = get_implicit_layers();
+        for error in database_error.iter().chain(&errors) {
+            eprintln!("{}", error);
+        }
+        for layer in &layers {
+            println!(
+                "{} (version {}, library hash {})", layer.name,
+                layer.implementation_version.as_deref().unwrap_or("?"),
+                library_hash(layer).as_deref().unwrap_or("?")
+            );
+            for issue in database.find_issues(layer, &layers, &ALL_API_VERSIONS) {
+                println!("  {}", issue.describe());
+            }
+        }
+        exit(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definitions::TrialKind;
+
+    fn layer(name: &str, version: &str) -> ImplicitLayer {
+        ImplicitLayer { implementation_version: Some(version.to_string()), ..ImplicitLayer::test(name) }
+    }
+
+    fn issue(layer: &str) -> KnownIssue {
+        KnownIssue {
+            layer: layer.to_string(),
+            versions: vec![],
+            library_hashes: vec![],
+            api_versions: vec![],
+            conflicts_with: vec![],
+            vendor: "Vendor".to_string(),
+            severity: Severity::Warning,
+            action: Action::Disable,
+            summary: "It crashes.".to_string()
+        }
+    }
+
+    #[test]
+    fn test_bundled_database() {
+        let database = KnownIssueDatabase::bundled();
+        assert!(!database.issues.is_empty());
+        let fossilize = layer("VK_LAYER_VALVE_steam_fossilize_64", "1");
+        let issues = database.find_issues(&fossilize, std::slice::from_ref(&fossilize), &ALL_API_VERSIONS);
+        assert_eq!(1, issues.len());
+        assert_eq!(Severity::Harmless, issues[0].severity);
+
+        for keyed_by in [
+            |issue: &KnownIssue| !issue.library_hashes.is_empty(),
+            |issue: &KnownIssue| !issue.conflicts_with.is_empty()
+        ] {
+            assert!(database.issues.iter().any(keyed_by));
+        }
+    }
+
+    #[test]
+    fn test_find_issues() {
+        let old_overlay = issue("VK_LAYER_overlay");
+        let mut crashing_version = issue("vk_layer_overlay");
+        crashing_version.versions = vec!["7".to_string()];
+        let mut conflict = issue("VK_LAYER_capture*");
+        conflict.conflicts_with = vec!["VK_LAYER_overlay".to_string()];
+        let mut hashed = issue("VK_LAYER_overlay");
+        hashed.library_hashes = vec!["0123456789abcdef".to_string()];
+        let mut vulkan13 = issue("VK_LAYER_capture_64");
+        vulkan13.api_versions = vec!["1.3".to_string()];
+        let database = KnownIssueDatabase { issues: vec![
+            old_overlay.clone(), crashing_version.clone(), conflict.clone(), hashed, vulkan13.clone()
+        ] };
+
+        let overlay = layer("VK_LAYER_overlay", "7");
+        let capture = layer("VK_LAYER_capture_64", "1");
+        let layers = [overlay.clone(), capture.clone()];
+        let all = &ALL_API_VERSIONS;
+        assert_eq!(vec![&old_overlay, &crashing_version], database.find_issues(&overlay, &layers, all));
+        assert_eq!(vec![&old_overlay], database.find_issues(&layer("VK_LAYER_OVERLAY", "8"), &layers, all));
+        assert_eq!(vec![&conflict, &vulkan13], database.find_issues(&capture, &layers, all));
+        assert_eq!(vec![&conflict], database.find_issues(&capture, &layers, &[ash::vk::API_VERSION_1_2]));
+        assert_eq!(vec![&vulkan13], database.find_issues(&capture, std::slice::from_ref(&capture), all));
+        assert_eq!(
+            "Known issue (warning, by Vendor) (together with VK_LAYER_overlay): It crashes. Recommended: disable it",
+            conflict.describe()
+        );
+        assert_eq!(
+            "Known issue (warning, by Vendor) (on Vulkan 1.3): It crashes. Recommended: disable it",
+            vulkan13.describe()
+        );
+    }
+
+    #[test]
+    fn test_failed_api_versions() {
+        use ash::vk::{API_VERSION_1_2, API_VERSION_1_3};
+        let mut finding = Finding::default();
+        assert_eq!(ALL_API_VERSIONS.to_vec(), failed_api_versions(&finding));
+        finding.trace = vec![
+            TraceStep::Trial { trial: TrialKind::Default, succeeded_versions: vec![], failed_versions: vec![API_VERSION_1_3] },
+            TraceStep::Note("note".to_string()),
+            TraceStep::Trial { trial: TrialKind::Clean, succeeded_versions: vec![], failed_versions: vec![API_VERSION_1_2, API_VERSION_1_3] }
+        ];
+        assert_eq!(vec![API_VERSION_1_2, API_VERSION_1_3], failed_api_versions(&finding));
+    }
+
+    #[test]
+    fn test_library_hash() {
+        let directory = std::env::temp_dir().join(format!("vk-fixer-hash-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("layer.dll"), "a").unwrap();
+        let mut hashed = layer("VK_LAYER_hashed", "1");
+        hashed.settings_path = directory.join("layer.json").to_str().unwrap().to_string();
+        assert_eq!(None, library_hash(&hashed));
+        hashed.library_path = Some(".\\layer.dll".replace('\\', std::path::MAIN_SEPARATOR_STR));
+        // The SHA-256 hash of "a"
+        assert_eq!(
+            Some("ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb".to_string()),
+            library_hash(&hashed)
+        );
+        // The cached hash is only used while the library stays the same
+        fs::write(directory.join("layer.dll"), "abc").unwrap();
+        assert_eq!(
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string()),
+            library_hash(&hashed)
+        );
+        hashed.library_path = Some("layer.dll".to_string());
+        assert_eq!(None, library_hash(&hashed));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_load_user_issues() {
+        let directory = std::env::temp_dir().join(format!("vk-fixer-issues-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("known-issues.json");
+        assert_eq!(Ok(KnownIssueDatabase::bundled()), KnownIssueDatabase::load_from(&path));
+        fs::write(&path, r#"{ "issues": [{
+            "layer": "VK_LAYER_mine", "vendor": "Me", "severity": "critical", "action": "disable", "summary": "Broken."
+        }] }"#).unwrap();
+        let database = KnownIssueDatabase::load_from(&path).unwrap();
+        assert_eq!("VK_LAYER_mine", database.issues[0].layer);
+        assert_eq!(KnownIssueDatabase::bundled().issues.len() + 1, database.issues.len());
+        fs::write(&path, "[").unwrap();
+        assert!(KnownIssueDatabase::load_from(&path).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+use std::env::{args, var};
+use std::process::{exit, Command};
+use crate::console::report_error;
+use crate::definitions::*;
+use crate::layer_state::{disable_variables, LOADER_LAYERS_DISABLE};
+use crate::meta_layers::include_meta_layers;
+use crate::profiles::ProfileStore;
+use crate::registry::get_implicit_layers;
+
+const RUN_USAGE: &str = "Usage: vk-fixer run [--loader-filter] [--profile <name>] [--disable <layer>...] -- <program> [arguments...]
+When neither --profile nor --disable is given, the profile of the program (if any) is used.";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RunArguments {
+    /// The names of the layers that should be disabled for the program
+    pub disabled_layers: Vec<String>,
+    pub method: DisableMethod,
+    /// The name of the profile whose layers should also be disabled
+    pub profile: Option<String>,
+    pub program: String,
+    pub program_arguments: Vec<String>
+}
+
+/// Handles `vk-fixer run --disable <layer>... -- <program> [arguments...]`, which launches the
+/// program with the given layers disabled, without touching the global environment variables.
+/// This is useful as Steam launch option.
+pub fn maybe_run_launcher() {
+    let args = args().collect::<Vec<_>>();
+    if args.len() >= 2 && args[1] == "run" {
+        let run_arguments = match parse_run_arguments(&args[2 ..]) {
+            Ok(run_arguments) => run_arguments,
+            Err(error) => {
+                report_error(&format!("{}\n{}", error, RUN_USAGE));
+                exit(2);
+            }
+        };
+        exit(run_program(&run_arguments));
+    }
+}
+
+pub fn parse_run_arguments(args: &[String]) -> Result<RunArguments, String> {
+    let mut disabled_layers = Vec::new();
+    let mut method = DisableMethod::DisableEnvironment;
+    let mut profile = None;
+    let mut parsing_layers = false;
+    let mut parsing_profile = false;
+
+    for (index, arg) in args.iter().enumerate() {
+        if parsing_profile {
+            profile = Some(arg.clone());
+            parsing_profile = false;
+            continue;
+        }
+        match arg.as_str() {
+            "--" => {
+                let program = args.get(index + 1).ok_or("Missing program after --")?;
+                return Ok(RunArguments {
+                    disabled_layers,
+                    method,
+                    profile,
+                    program: program.clone(),
+                    program_arguments: args[index + 2 ..].to_vec()
+                });
+            },
+            "--disable" => parsing_layers = true,
+            "--profile" => {
+                parsing_profile = true;
+                parsing_layers = false;
+            },
+            "--loader-filter" => {
+                method = DisableMethod::LoaderFilter;
+                parsing_layers = false;
+            },
+            layer if parsing_layers && !layer.starts_with("--") => disabled_layers.push(layer.to_string()),
+            unknown => return Err(format!("Unexpected argument {}", unknown))
+        }
+    }
+    Err("Missing -- followed by the program to run".to_string())
+}
+
+/// Finds the implicit layers with the given names (case-insensitive). When there are multiple
+/// layers with the same name, the one that is not shadowed is preferred.
+pub fn resolve_layers<'a>(names: &[String], available: &'a [ImplicitLayer]) -> Result<Vec<&'a ImplicitLayer>, String> {
+    match resolve_installed_layers(names, available) {
+        (_, missing) if !missing.is_empty() => Err(format!("Can't find implicit layer {}", missing[0])),
+        (layers, _) => Ok(layers)
+    }
+}
+
+/// Like `resolve_layers`, but skips the names of layers that are not installed (anymore), and
+/// returns those names separately
+pub fn resolve_installed_layers<'a>(
+    names: &[String], available: &'a [ImplicitLayer]
+) -> (Vec<&'a ImplicitLayer>, Vec<String>) {
+    let mut layers = Vec::new();
+    let mut missing = Vec::new();
+    for name in names {
+        match available.iter().filter(
+            |layer| layer.name.eq_ignore_ascii_case(name)
+        ).min_by_key(|layer| layer.shadowed_by.is_some()) {
+            Some(layer) => layers.push(layer),
+            None => missing.push(name.clone())
+        }
+    }
+    (layers, missing)
+}
+
+/// Adds the layers of the requested profile to the run arguments. When no profile and no layers
+/// were given, the profile of the program (if any) is used.
+pub fn apply_profile(run_arguments: &mut RunArguments, store: &ProfileStore) -> Result<(), String> {
+    let profile = if let Some(name) = &run_arguments.profile {
+        Some(store.get(name).map_err(|error| error.to_string())?.ok_or(format!("There is no profile named {}", name))?)
+    } else if run_arguments.disabled_layers.is_empty() {
+        store.find_for_game(&run_arguments.program).map_err(|error| error.to_string())?
+    } else { None };
+
+    if let Some(profile) = profile {
+        for layer in profile.disabled_layers {
+            if !run_arguments.disabled_layers.contains(&layer) {
+                run_arguments.disabled_layers.push(layer);
+            }
+        }
+        if profile.method == DisableMethod::LoaderFilter {
+            run_arguments.method = DisableMethod::LoaderFilter;
+        }
+    }
+    Ok(())
+}
+
+fn run_program(run_arguments: &RunArguments) -> i32 {
+    let mut run_arguments = run_arguments.clone();
+    if let Some(store) = ProfileStore::default_location() {
+        if let Err(error) = apply_profile(&mut run_arguments, &store) {
+            report_error(&error);
+            return 2;
+        }
+    }
+
+    // A layer that was uninstalled doesn't need to be disabled, so it must not stop the game from
+    // launching. This is only a warning, since a message box would hold up the game.
+    let (layers, _) = get_implicit_layers();
+    let (disabled_layers, missing_layers) = resolve_installed_layers(&run_arguments.disabled_layers, &layers);
+    for name in missing_layers {
+        eprintln!("Warning: can't find implicit layer {}, so it won't be disabled", name);
+    }
+    let disabled_layers = include_meta_layers(disabled_layers, &layers);
+
+    let existing_filter = var(LOADER_LAYERS_DISABLE).ok();
+    let envs = disable_variables(&disabled_layers, run_arguments.method, existing_filter.as_deref());
+    let status = Command::new(&run_arguments.program)
+        .args(&run_arguments.program_arguments)
+        .envs(envs)
+        .status();
+    match status {
+        Ok(status) => status.code().unwrap_or(-21022),
+        Err(error) => {
+            report_error(&format!("Failed to launch {}: {}", run_arguments.program, error));
+            -21021
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profiles::Profile;
+    use super::*;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_run_arguments() {
+        assert_eq!(Ok(RunArguments {
+            disabled_layers: to_args(&["VK_LAYER_a", "VK_LAYER_b"]),
+            method: DisableMethod::DisableEnvironment,
+            profile: None,
+            program: "game.exe".to_string(),
+            program_arguments: to_args(&["--disable", "-fullscreen"])
+        }), parse_run_arguments(&to_args(&[
+            "--disable", "VK_LAYER_a", "VK_LAYER_b", "--", "game.exe", "--disable", "-fullscreen"
+        ])));
+
+        assert_eq!(Ok(RunArguments {
+            disabled_layers: to_args(&["VK_LAYER_a"]),
+            method: DisableMethod::LoaderFilter,
+            profile: None,
+            program: "game.exe".to_string(),
+            program_arguments: vec![]
+        }), parse_run_arguments(&to_args(&["--disable", "VK_LAYER_a", "--loader-filter", "--", "game.exe"])));
+
+        assert_eq!(Ok(RunArguments {
+            disabled_layers: vec![],
+            method: DisableMethod::DisableEnvironment,
+            profile: Some("--odd name".to_string()),
+            program: "game.exe".to_string(),
+            program_arguments: vec![]
+        }), parse_run_arguments(&to_args(&["--profile", "--odd name", "--", "game.exe"])));
+    }
+
+    #[test]
+    fn test_apply_profile() {
+        let directory = std::env::temp_dir().join(format!("vk-fixer-launcher-{}", std::process::id()));
+        let store = ProfileStore::new(directory.join("profiles.json"));
+        store.save(Profile {
+            name: "first".to_string(),
+            game: "C:\\Games\\first.exe".to_string(),
+            disabled_layers: to_args(&["VK_LAYER_a", "VK_LAYER_b"]),
+            method: DisableMethod::LoaderFilter
+        }).unwrap();
+
+        let mut implicit_profile = parse_run_arguments(&to_args(&["--", "first.exe"])).unwrap();
+        apply_profile(&mut implicit_profile, &store).unwrap();
+        assert_eq!(to_args(&["VK_LAYER_a", "VK_LAYER_b"]), implicit_profile.disabled_layers);
+        assert_eq!(DisableMethod::LoaderFilter, implicit_profile.method);
+
+        let mut explicit_layers = parse_run_arguments(&to_args(&["--disable", "VK_LAYER_c", "--", "first.exe"])).unwrap();
+        apply_profile(&mut explicit_layers, &store).unwrap();
+        assert_eq!(to_args(&["VK_LAYER_c"]), explicit_layers.disabled_layers);
+
+        let mut explicit_profile = parse_run_arguments(&to_args(&[
+            "--disable", "VK_LAYER_b", "--profile", "first", "--", "other.exe"
+        ])).unwrap();
+        apply_profile(&mut explicit_profile, &store).unwrap();
+        assert_eq!(to_args(&["VK_LAYER_b", "VK_LAYER_a"]), explicit_profile.disabled_layers);
+
+        let mut missing_profile = parse_run_arguments(&to_args(&["--profile", "second", "--", "first.exe"])).unwrap();
+        assert!(apply_profile(&mut missing_profile, &store).is_err());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_parse_invalid_run_arguments() {
+        assert!(parse_run_arguments(&to_args(&["--disable", "VK_LAYER_a"])).is_err());
+        assert!(parse_run_arguments(&to_args(&["--disable", "VK_LAYER_a", "--"])).is_err());
+        assert!(parse_run_arguments(&to_args(&["VK_LAYER_a", "--", "game.exe"])).is_err());
+        assert!(parse_run_arguments(&to_args(&["--whatever", "--", "game.exe"])).is_err());
+    }
+
+    #[test]
+    fn test_resolve_layers() {
+        let available = [ImplicitLayer::test("VK_LAYER_a"), ImplicitLayer::test("VK_LAYER_b")];
+
+        assert_eq!(Ok(vec![&available[1]]), resolve_layers(&to_args(&["vk_layer_B"]), &available));
+        assert!(resolve_layers(&to_args(&["VK_LAYER_a", "VK_LAYER_c"]), &available).is_err());
+        assert_eq!(
+            (vec![&available[0]], to_args(&["VK_LAYER_c"])),
+            resolve_installed_layers(&to_args(&["VK_LAYER_a", "VK_LAYER_c"]), &available)
+        );
+    }
+}
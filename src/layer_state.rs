@@ -0,0 +1,527 @@
+use std::fmt::{Display, Formatter};
+use crate::definitions::*;
+
+pub const LOADER_LAYERS_ENABLE: &str = "VK_LOADER_LAYERS_ENABLE";
+pub const LOADER_LAYERS_DISABLE: &str = "VK_LOADER_LAYERS_DISABLE";
+pub const LOADER_LAYERS_ALLOW: &str = "VK_LOADER_LAYERS_ALLOW";
+
+/// Computes whether the loader will load the given implicit layer, following the same precedence
+/// rules as the loader:
+/// 1. the loader ignores layers whose registry value is not 0, and layers that are shadowed
+/// 2. the loader settings file (if any) can force layers on or off, and hides unlisted layers when
+///    it has no `unordered_layer_location`
+/// 3. layers matching `VK_LOADER_LAYERS_ENABLE` are always enabled
+/// 4. layers matching `VK_LOADER_LAYERS_DISABLE` are disabled, unless they match `VK_LOADER_LAYERS_ALLOW`
+/// 5. layers whose `disable_environment` is set are disabled
+/// 6. layers with an `enable_environment` are only enabled when it has the right value
+pub fn effective_state(layer: &ImplicitLayer, env: &EnvironmentVariables) -> EffectiveState {
+    if layer.registry_value != 0 {
+        return disabled(StateReason::RegistryValue(layer.registry_value));
+    }
+    if let Some(settings_path) = &layer.shadowed_by {
+        return disabled(StateReason::ShadowedBy { settings_path: settings_path.clone() });
+    }
+
+    if let Some(settings) = &env.loader_settings {
+        let settings_path = settings.path.clone();
+        match settings.control_of(layer) {
+            Some(LayerControl::On) => return enabled(StateReason::LoaderSettings { settings_path }),
+            Some(LayerControl::Off) => return disabled(StateReason::LoaderSettings { settings_path }),
+            Some(LayerControl::Auto) => {},
+            None => if !settings.has_unordered_layer_location {
+                return disabled(StateReason::MissingFromLoaderSettings { settings_path });
+            }
+        }
+    }
+
+    if let Some((source, _)) = find_matching_filter(layer, env, LOADER_LAYERS_ENABLE) {
+        return enabled(StateReason::LoaderFilter { variable: LOADER_LAYERS_ENABLE.to_string(), source });
+    }
+
+    if let Some((source, _)) = find_matching_filter(layer, env, LOADER_LAYERS_DISABLE) {
+        if find_matching_filter(layer, env, LOADER_LAYERS_ALLOW).is_none() {
+            return disabled(StateReason::LoaderFilter { variable: LOADER_LAYERS_DISABLE.to_string(), source });
+        }
+    }
+
+    if let Some((source, _)) = env.lookup(&layer.disable_environment) {
+        return disabled(StateReason::DisableVariable { key: layer.disable_environment.clone(), source });
+    }
+
+    if let Some((key, value)) = &layer.enable_environment {
+        return match env.lookup(key) {
+            Some((source, actual_value)) if actual_value == value => enabled(
+                StateReason::EnableVariable { key: key.clone(), source }
+            ),
+            _ => disabled(StateReason::MissingEnableVariable { key: key.clone(), value: value.clone() })
+        };
+    }
+
+    enabled(StateReason::EnabledByDefault)
+}
+
+/// Computes the effective state of all given layers. Unlike `effective_state`, this takes
+/// meta-layers into account: an enabled meta-layer enables its component layers, and disables its
+/// blacklisted layers. Only the registry value, shadowing, the loader settings file, and the loader
+/// filters take precedence over meta-layers.
+pub fn effective_states(layers: &[ImplicitLayer], env: &EnvironmentVariables) -> Vec<EffectiveState> {
+    let base_states: Vec<EffectiveState> = layers.iter().map(|layer| effective_state(layer, env)).collect();
+    let mut states = base_states.clone();
+
+    // Meta-layers can contain (or blacklist) other meta-layers, so repeat until nothing changes.
+    // Each pass starts from the base states, so a layer is only pulled in (or blacklisted) while
+    // the meta-layer responsible for it is still enabled, regardless of the order of the layers.
+    // Meta-layers that blacklist each other could flip forever, so give up after enough passes.
+    for _ in 0 ..= layers.len() {
+        let mut next_states = base_states.clone();
+        for (meta_index, meta_layer) in layers.iter().enumerate() {
+            let meta = match &meta_layer.meta_layer {
+                Some(meta) if states[meta_index].enabled => meta,
+                _ => continue
+            };
+            for (index, layer) in layers.iter().enumerate() {
+                if index == meta_index || matches!(base_states[index].reason,
+                    StateReason::RegistryValue(_) | StateReason::ShadowedBy { .. } |
+                    StateReason::LoaderSettings { .. } | StateReason::MissingFromLoaderSettings { .. } |
+                    StateReason::LoaderFilter { .. }
+                ) || matches!(next_states[index].reason, StateReason::BlacklistedByMetaLayer { .. }) {
+                    continue;
+                }
+                let has_name = |names: &[String]| names.iter().any(|name| name.eq_ignore_ascii_case(&layer.name));
+                if has_name(&meta.blacklisted_layers) {
+                    next_states[index] = disabled(StateReason::BlacklistedByMetaLayer { meta_layer: meta_layer.name.clone() });
+                } else if has_name(&meta.component_layers) && !next_states[index].enabled {
+                    next_states[index] = enabled(StateReason::PulledInByMetaLayer { meta_layer: meta_layer.name.clone() });
+                }
+            }
+        }
+        if next_states == states {
+            break;
+        }
+        states = next_states;
+    }
+    states
+}
+
+/// What clicking a disable checkbox in the manual mode does
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CheckboxAction {
+    /// Set the `disable_environment` of the layer
+    Disable,
+    /// Remove the `disable_environment` of the layer
+    Enable,
+    /// The `disable_environment` has no effect on the layer, for instance because the loader
+    /// settings file forces it on
+    Unchangeable
+}
+
+/// Decides what clicking the checkbox that controls the `disable_environment` of `layer` in
+/// `source` (user or system) does, given its effective `state`
+pub fn checkbox_action(
+    layer: &ImplicitLayer, state: &EffectiveState, env: &EnvironmentVariables, source: VariableSource
+) -> CheckboxAction {
+    if env.lookup_in(source, &layer.disable_environment).is_some() {
+        return CheckboxAction::Enable;
+    }
+    match state.reason {
+        StateReason::EnabledByDefault | StateReason::EnableVariable { .. } |
+        StateReason::DisableVariable { .. } => CheckboxAction::Disable,
+        _ => CheckboxAction::Unchangeable
+    }
+}
+
+fn enabled(reason: StateReason) -> EffectiveState {
+    EffectiveState { enabled: true, reason }
+}
+
+fn disabled(reason: StateReason) -> EffectiveState {
+    EffectiveState { enabled: false, reason }
+}
+
+fn find_matching_filter<'a>(
+    layer: &ImplicitLayer, env: &'a EnvironmentVariables, variable: &str
+) -> Option<(VariableSource, &'a str)> {
+    env.lookup(variable).filter(|(_, filter)| filter_matches(filter, &layer.name))
+}
+
+/// Checks whether the comma-separated loader filter `filter` matches the implicit layer
+/// `layer_name`. Like the loader, this supports the `~all~`, `~implicit~` and `~explicit~`
+/// keywords, and `*` wildcards at the start and/or end of each entry.
+pub fn filter_matches(filter: &str, layer_name: &str) -> bool {
+    let layer_name = layer_name.to_ascii_lowercase();
+    filter.split(',').map(|entry| entry.trim().to_ascii_lowercase()).filter(|entry| !entry.is_empty()).any(
+        |entry| filter_entry_matches(&entry, &layer_name)
+    )
+}
+
+fn filter_entry_matches(entry: &str, layer_name: &str) -> bool {
+    match entry {
+        "~all~" | "~implicit~" => true,
+        "~explicit~" => false,
+        _ => {
+            let wildcard_start = entry.starts_with('*');
+            let wildcard_end = entry.len() > 1 && entry.ends_with('*');
+            let pattern = &entry[
+                if wildcard_start { 1 } else { 0 } .. entry.len() - if wildcard_end { 1 } else { 0 }
+            ];
+            match (wildcard_start, wildcard_end) {
+                (true, true) => layer_name.contains(pattern),
+                (true, false) => layer_name.ends_with(pattern),
+                (false, true) => layer_name.starts_with(pattern),
+                (false, false) => layer_name == pattern
+            }
+        }
+    }
+}
+
+/// Appends the given layer names to the existing loader filter (if any), skipping the layers that
+/// already match it.
+pub fn extend_filter(existing: Option<&str>, layer_names: &[&str]) -> String {
+    let mut entries: Vec<String> = existing.into_iter().flat_map(|filter| filter.split(',')).map(
+        |entry| entry.trim().to_string()
+    ).filter(|entry| !entry.is_empty()).collect();
+    for layer_name in layer_names {
+        if !filter_matches(&entries.join(","), layer_name) {
+            entries.push(layer_name.to_string());
+        }
+    }
+    entries.join(",")
+}
+
+/// Computes the environment variables that need to be set to disable all given layers. When the
+/// `LoaderFilter` method is used, `existing_filter` should be the current value of
+/// `VK_LOADER_LAYERS_DISABLE`, since it would be overwritten otherwise.
+pub fn disable_variables(
+    layers: &[&ImplicitLayer], method: DisableMethod, existing_filter: Option<&str>
+) -> Vec<(String, String)> {
+    if layers.is_empty() {
+        return vec![];
+    }
+    match method {
+        DisableMethod::DisableEnvironment => layers.iter().map(
+            |layer| (layer.disable_environment.clone(), "1".to_string())
+        ).collect(),
+        DisableMethod::LoaderFilter => vec![(LOADER_LAYERS_DISABLE.to_string(), extend_filter(
+            existing_filter, &layers.iter().map(|layer| layer.name.as_str()).collect::<Vec<_>>()
+        ))]
+    }
+}
+
+impl Display for VariableSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VariableSource::Process => write!(f, "process"),
+            VariableSource::User => write!(f, "user"),
+            VariableSource::System => write!(f, "system")
+        }
+    }
+}
+
+impl Display for EffectiveState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            StateReason::EnabledByDefault => write!(f, "enabled by default"),
+            StateReason::RegistryValue(value) => write!(
+                f, "ignored by the loader: its registry value is {} instead of 0", value
+            ),
+            StateReason::ShadowedBy { settings_path } => write!(
+                f, "ignored by the loader: shadowed by the layer with the same name in {}", settings_path
+            ),
+            StateReason::DisableVariable { key, source } => write!(f, "disabled by {} variable {}", source, key),
+            StateReason::EnableVariable { key, source } => write!(f, "enabled by {} variable {}", source, key),
+            StateReason::MissingEnableVariable { key, value } => write!(f, "not enabled: requires {}={}", key, value),
+            StateReason::LoaderFilter { variable, source } => if self.enabled {
+                write!(f, "forcibly enabled by {} variable {}", source, variable)
+            } else {
+                write!(f, "disabled by {} variable {}", source, variable)
+            },
+            StateReason::LoaderSettings { settings_path } => write!(
+                f, "forced {} by the loader settings file {}", if self.enabled { "on" } else { "off" }, settings_path
+            ),
+            StateReason::MissingFromLoaderSettings { settings_path } => write!(
+                f, "disabled because the loader settings file {} doesn't list it", settings_path
+            ),
+            StateReason::PulledInByMetaLayer { meta_layer } => write!(
+                f, "active because meta-layer {} pulls it in", meta_layer
+            ),
+            StateReason::BlacklistedByMetaLayer { meta_layer } => write!(
+                f, "disabled because meta-layer {} blacklists it", meta_layer
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn layer(enable_environment: Option<(&str, &str)>) -> ImplicitLayer {
+        ImplicitLayer {
+            disable_environment: "DISABLE_OVERLAY".to_string(),
+            enable_environment: enable_environment.map(|(key, value)| (key.to_string(), value.to_string())),
+            ..ImplicitLayer::test("VK_LAYER_test_overlay")
+        }
+    }
+
+    fn env(process: &[(&str, &str)], user: &[(&str, &str)], system: &[(&str, &str)]) -> EnvironmentVariables {
+        let to_map = |pairs: &[(&str, &str)]| pairs.iter().map(
+            |(key, value)| (key.to_string(), value.to_string())
+        ).collect::<HashMap<_, _>>();
+        EnvironmentVariables {
+            process: to_map(process),
+            user: to_map(user),
+            system: to_map(system),
+            loader_settings: None,
+            errors: vec![]
+        }
+    }
+
+    #[test]
+    fn test_enabled_by_default() {
+        let state = effective_state(&layer(None), &env(&[], &[], &[]));
+        assert_eq!(EffectiveState { enabled: true, reason: StateReason::EnabledByDefault }, state);
+        assert_eq!("enabled by default", state.to_string());
+    }
+
+    #[test]
+    fn test_registry_value() {
+        let mut ignored_layer = layer(None);
+        ignored_layer.registry_value = 1;
+        let state = effective_state(&ignored_layer, &env(&[], &[], &[]));
+        assert!(!state.enabled);
+        assert_eq!(StateReason::RegistryValue(1), state.reason);
+    }
+
+    #[test]
+    fn test_shadowed_layer() {
+        let mut shadowed_layer = layer(None);
+        shadowed_layer.shadowed_by = Some("other.json".to_string());
+        let state = effective_state(&shadowed_layer, &env(&[], &[], &[]));
+        assert!(!state.enabled);
+        assert_eq!("ignored by the loader: shadowed by the layer with the same name in other.json", state.to_string());
+    }
+
+    #[test]
+    fn test_disable_variable_prefers_persistent_source() {
+        let state = effective_state(&layer(None), &env(
+            &[("DISABLE_OVERLAY", "1")], &[("disable_overlay", "1")], &[]
+        ));
+        assert!(!state.enabled);
+        assert_eq!("disabled by user variable DISABLE_OVERLAY", state.to_string());
+
+        let state = effective_state(&layer(None), &env(&[], &[], &[("DISABLE_OVERLAY", "1")]));
+        assert_eq!("disabled by system variable DISABLE_OVERLAY", state.to_string());
+    }
+
+    #[test]
+    fn test_stale_process_environment() {
+        // After setx, the persistent variable exists, but the process environment is outdated
+        let state = effective_state(&layer(None), &env(&[], &[("DISABLE_OVERLAY", "1")], &[]));
+        assert!(!state.enabled);
+    }
+
+    #[test]
+    fn test_enable_variable() {
+        let enable_layer = layer(Some(("ENABLE_OVERLAY", "1")));
+
+        let state = effective_state(&enable_layer, &env(&[], &[], &[]));
+        assert!(!state.enabled);
+        assert_eq!("not enabled: requires ENABLE_OVERLAY=1", state.to_string());
+
+        let state = effective_state(&enable_layer, &env(&[("ENABLE_OVERLAY", "0")], &[], &[]));
+        assert!(!state.enabled);
+
+        let state = effective_state(&enable_layer, &env(&[("ENABLE_OVERLAY", "1")], &[], &[]));
+        assert!(state.enabled);
+        assert_eq!("enabled by process variable ENABLE_OVERLAY", state.to_string());
+
+        let state = effective_state(&enable_layer, &env(
+            &[("ENABLE_OVERLAY", "1")], &[], &[("DISABLE_OVERLAY", "1")]
+        ));
+        assert!(!state.enabled);
+    }
+
+    #[test]
+    fn test_loader_filters() {
+        let state = effective_state(&layer(None), &env(
+            &[], &[(LOADER_LAYERS_DISABLE, "VK_LAYER_other, VK_LAYER_test_overlay")], &[]
+        ));
+        assert_eq!("disabled by user variable VK_LOADER_LAYERS_DISABLE", state.to_string());
+
+        let state = effective_state(&layer(None), &env(
+            &[], &[(LOADER_LAYERS_DISABLE, "VK_LAYER_test_overlay")], &[(LOADER_LAYERS_ALLOW, "VK_LAYER_test_overlay")]
+        ));
+        assert_eq!(StateReason::EnabledByDefault, state.reason);
+
+        let state = effective_state(&layer(Some(("ENABLE_OVERLAY", "1"))), &env(
+            &[(LOADER_LAYERS_ENABLE, "VK_LAYER_test_overlay")], &[("DISABLE_OVERLAY", "1")], &[]
+        ));
+        assert!(state.enabled);
+        assert_eq!("forcibly enabled by process variable VK_LOADER_LAYERS_ENABLE", state.to_string());
+    }
+
+    #[test]
+    fn test_loader_settings() {
+        let settings_layer = |name: &str, control| LoaderSettingsLayer { name: name.to_string(), path: None, control };
+        let mut env = env(&[(LOADER_LAYERS_ENABLE, "VK_LAYER_test_overlay")], &[], &[]);
+        env.loader_settings = Some(LoaderSettings {
+            path: "vk_loader_settings.json".to_string(),
+            layers: vec![settings_layer("VK_LAYER_test_overlay", LayerControl::Off)],
+            has_unordered_layer_location: true,
+            managed_by_vk_fixer: false
+        });
+
+        let state = effective_state(&layer(None), &env);
+        assert!(!state.enabled);
+        assert_eq!("forced off by the loader settings file vk_loader_settings.json", state.to_string());
+
+        env.loader_settings.as_mut().unwrap().layers[0].control = LayerControl::Auto;
+        let state = effective_state(&layer(None), &env);
+        assert_eq!("forcibly enabled by process variable VK_LOADER_LAYERS_ENABLE", state.to_string());
+
+        env.loader_settings.as_mut().unwrap().layers[0].name = "VK_LAYER_other".to_string();
+        assert!(effective_state(&layer(None), &env).enabled);
+
+        env.loader_settings.as_mut().unwrap().has_unordered_layer_location = false;
+        let state = effective_state(&layer(None), &env);
+        assert!(!state.enabled);
+        assert_eq!("disabled because the loader settings file vk_loader_settings.json doesn't list it", state.to_string());
+    }
+
+    #[test]
+    fn test_meta_layers() {
+        let mut component = layer(None);
+        let mut blacklisted = layer(None);
+        blacklisted.name = "VK_LAYER_bad_overlay".to_string();
+        blacklisted.disable_environment = "DISABLE_BAD_OVERLAY".to_string();
+        let mut meta_layer = layer(None);
+        meta_layer.name = "VK_LAYER_LUNARG_override".to_string();
+        meta_layer.disable_environment = "DISABLE_OVERRIDE".to_string();
+        meta_layer.meta_layer = Some(MetaLayer {
+            component_layers: vec!["VK_LAYER_test_overlay".to_string()],
+            blacklisted_layers: vec!["VK_LAYER_bad_overlay".to_string()]
+        });
+        component.enable_environment = Some(("ENABLE_OVERLAY".to_string(), "1".to_string()));
+        let layers = [component, blacklisted, meta_layer];
+
+        let states = effective_states(&layers, &env(&[], &[("DISABLE_OVERLAY", "1")], &[]));
+        assert_eq!("active because meta-layer VK_LAYER_LUNARG_override pulls it in", states[0].to_string());
+        assert!(states[0].enabled);
+        assert_eq!("disabled because meta-layer VK_LAYER_LUNARG_override blacklists it", states[1].to_string());
+        assert!(!states[1].enabled);
+        assert_eq!(StateReason::EnabledByDefault, states[2].reason);
+
+        let states = effective_states(&layers, &env(&[], &[("DISABLE_OVERRIDE", "1")], &[]));
+        assert!(!states[0].enabled);
+        assert!(states[1].enabled);
+
+        let states = effective_states(&layers, &env(&[(LOADER_LAYERS_DISABLE, "VK_LAYER_test_overlay")], &[], &[]));
+        assert!(!states[0].enabled);
+    }
+
+    #[test]
+    fn test_blacklisted_meta_layer_does_not_pull_in() {
+        let component = ImplicitLayer {
+            enable_environment: Some(("ENABLE_OVERLAY".to_string(), "1".to_string())),
+            ..layer(None)
+        };
+        let inner_meta = ImplicitLayer {
+            meta_layer: Some(MetaLayer {
+                component_layers: vec!["VK_LAYER_test_overlay".to_string()],
+                blacklisted_layers: vec![]
+            }),
+            ..ImplicitLayer::test("VK_LAYER_inner")
+        };
+        let outer_meta = ImplicitLayer {
+            meta_layer: Some(MetaLayer {
+                component_layers: vec![],
+                blacklisted_layers: vec!["VK_LAYER_inner".to_string()]
+            }),
+            ..ImplicitLayer::test("VK_LAYER_outer")
+        };
+
+        // The result must not depend on whether the blacklisting meta-layer comes before or after
+        let orders = [
+            [component.clone(), inner_meta.clone(), outer_meta.clone()],
+            [outer_meta.clone(), inner_meta.clone(), component.clone()],
+            [inner_meta, component, outer_meta]
+        ];
+        for layers in orders {
+            let states = effective_states(&layers, &env(&[], &[], &[]));
+            for (layer, state) in layers.iter().zip(states) {
+                match layer.name.as_str() {
+                    "VK_LAYER_test_overlay" => assert_eq!(StateReason::MissingEnableVariable {
+                        key: "ENABLE_OVERLAY".to_string(), value: "1".to_string()
+                    }, state.reason),
+                    "VK_LAYER_inner" => assert_eq!(StateReason::BlacklistedByMetaLayer {
+                        meta_layer: "VK_LAYER_outer".to_string()
+                    }, state.reason),
+                    _ => assert_eq!(StateReason::EnabledByDefault, state.reason)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_checkbox_action() {
+        let action = |env: &EnvironmentVariables, source| {
+            checkbox_action(&layer(None), &effective_state(&layer(None), env), env, source)
+        };
+        assert_eq!(CheckboxAction::Disable, action(&env(&[], &[], &[]), VariableSource::User));
+        assert_eq!(CheckboxAction::Enable, action(&env(&[], &[("disable_overlay", "1")], &[]), VariableSource::User));
+
+        let system_disabled = env(&[], &[], &[("DISABLE_OVERLAY", "1")]);
+        assert_eq!(CheckboxAction::Disable, action(&system_disabled, VariableSource::User));
+        assert_eq!(CheckboxAction::Enable, action(&system_disabled, VariableSource::System));
+
+        let forced_on = env(&[(LOADER_LAYERS_ENABLE, "~implicit~")], &[], &[]);
+        assert_eq!(CheckboxAction::Unchangeable, action(&forced_on, VariableSource::User));
+        let forced_on = env(&[(LOADER_LAYERS_ENABLE, "~implicit~")], &[("DISABLE_OVERLAY", "1")], &[]);
+        assert_eq!(CheckboxAction::Enable, action(&forced_on, VariableSource::User));
+    }
+
+    #[test]
+    fn test_filter_wildcards() {
+        assert!(filter_matches("~implicit~", "VK_LAYER_test_overlay"));
+        assert!(filter_matches("~all~", "VK_LAYER_test_overlay"));
+        assert!(!filter_matches("~explicit~", "VK_LAYER_test_overlay"));
+        assert!(filter_matches("*overlay*", "VK_LAYER_test_overlay"));
+        assert!(filter_matches("*OVERLAY", "VK_LAYER_test_overlay"));
+        assert!(filter_matches("VK_LAYER_test*", "VK_LAYER_test_overlay"));
+        assert!(filter_matches("VK_LAYER_other,*test*", "VK_LAYER_test_overlay"));
+        assert!(!filter_matches("*capture*", "VK_LAYER_test_overlay"));
+        assert!(!filter_matches("VK_LAYER_test", "VK_LAYER_test_overlay"));
+        assert!(!filter_matches("", "VK_LAYER_test_overlay"));
+        assert!(filter_matches("*", "VK_LAYER_test_overlay"));
+
+        let state = effective_state(&layer(None), &env(&[(LOADER_LAYERS_DISABLE, "~implicit~")], &[], &[]));
+        assert!(!state.enabled);
+    }
+
+    #[test]
+    fn test_extend_filter() {
+        assert_eq!("VK_LAYER_a,VK_LAYER_b", extend_filter(None, &["VK_LAYER_a", "VK_LAYER_b"]));
+        assert_eq!("*overlay*,VK_LAYER_b", extend_filter(
+            Some(" *overlay* ,"), &["VK_LAYER_test_overlay", "VK_LAYER_b"]
+        ));
+        assert_eq!("VK_LAYER_a", extend_filter(Some("VK_LAYER_a"), &["VK_LAYER_a"]));
+    }
+
+    #[test]
+    fn test_disable_variables() {
+        let first = layer(None);
+        let mut second = layer(None);
+        second.name = "VK_LAYER_test_capture".to_string();
+        second.disable_environment = "DISABLE_CAPTURE".to_string();
+
+        assert_eq!(vec![
+            ("DISABLE_OVERLAY".to_string(), "1".to_string()),
+            ("DISABLE_CAPTURE".to_string(), "1".to_string())
+        ], disable_variables(&[&first, &second], DisableMethod::DisableEnvironment, None));
+        assert_eq!(vec![
+            (LOADER_LAYERS_DISABLE.to_string(), "VK_LAYER_other,VK_LAYER_test_overlay,VK_LAYER_test_capture".to_string())
+        ], disable_variables(&[&first, &second], DisableMethod::LoaderFilter, Some("VK_LAYER_other")));
+        assert!(disable_variables(&[], DisableMethod::LoaderFilter, Some("VK_LAYER_other")).is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+const BEGIN_MARKER: &str = "# BEGIN vk-fixer managed variables";
+const END_MARKER: &str = "# END vk-fixer managed variables";
+
+/// Linux counterpart of `setx`: persists environment variables for future sessions of the user.
+/// The variables are always written to `~/.config/environment.d/vk-fixer.conf` (which is picked
+/// up by systemd user sessions), and optionally to a managed block in `~/.profile` (which is
+/// picked up by most login shells).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LinuxEnvironmentStore {
+    pub home: PathBuf,
+    pub shell_profile: bool
+}
+
+impl LinuxEnvironmentStore {
+    pub fn new(home: impl Into<PathBuf>, shell_profile: bool) -> Self {
+        Self { home: home.into(), shell_profile }
+    }
+
+    pub fn environment_d_path(&self) -> PathBuf {
+        self.home.join(".config").join("environment.d").join("vk-fixer.conf")
+    }
+
+    pub fn profile_path(&self) -> PathBuf {
+        self.home.join(".profile")
+    }
+
+    /// Lists all (key, value) pairs that are currently managed by vk-fixer
+    pub fn list(&self) -> Result<Vec<(String, String)>> {
+        Ok(parse_block(&read_or_empty(&self.environment_d_path())?, false))
+    }
+
+    pub fn contains(&self, key: &str) -> Result<bool> {
+        Ok(self.list()?.iter().any(|(existing_key, _)| existing_key == key))
+    }
+
+    /// Sets `key` to `value`. Adding a key that is already managed will just overwrite its value.
+    pub fn add(&self, key: &str, value: &str) -> Result<()> {
+        validate_key(key)?;
+        if value.contains('\n') {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Value of {} contains a newline", key)));
+        }
+
+        let mut variables = self.list()?;
+        if let Some(existing) = variables.iter_mut().find(|(existing_key, _)| existing_key == key) {
+            existing.1 = value.to_string();
+        } else {
+            variables.push((key.to_string(), value.to_string()));
+        }
+        self.write_all(&variables)
+    }
+
+    /// Stops managing `key`. Removing a key that is not managed is a no-op.
+    pub fn remove(&self, key: &str) -> Result<()> {
+        let mut variables = self.list()?;
+        variables.retain(|(existing_key, _)| existing_key != key);
+        self.write_all(&variables)?;
+
+        // The profile may still contain the key when shell_profile was toggled in the meantime
+        let profile_path = self.profile_path();
+        let profile = read_or_empty(&profile_path)?;
+        let mut profile_variables = parse_block(&profile, true);
+        if profile_variables.iter().any(|(existing_key, _)| existing_key == key) {
+            profile_variables.retain(|(existing_key, _)| existing_key != key);
+            write_or_delete(&profile_path, &replace_block(&profile, &profile_variables, true), false)?;
+        }
+        Ok(())
+    }
+
+    fn write_all(&self, variables: &[(String, String)]) -> Result<()> {
+        let environment_d_path = self.environment_d_path();
+        let old_content = read_or_empty(&environment_d_path)?;
+        write_or_delete(&environment_d_path, &replace_block(&old_content, variables, false), true)?;
+
+        if self.shell_profile {
+            let profile_path = self.profile_path();
+            let old_profile = read_or_empty(&profile_path)?;
+            write_or_delete(&profile_path, &replace_block(&old_profile, variables, true), false)?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    let mut chars = key.chars();
+    let valid_start = chars.next().is_some_and(|first| first == '_' || first.is_ascii_alphabetic());
+    if valid_start && chars.all(|next| next == '_' || next.is_ascii_alphanumeric()) {
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::InvalidInput, format!("{} is not a valid environment variable name", key)))
+    }
+}
+
+fn read_or_empty(path: &Path) -> Result<String> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(error) => Err(error)
+    }
+}
+
+fn write_or_delete(path: &Path, content: &str, delete_when_empty: bool) -> Result<()> {
+    if content.trim().is_empty() && delete_when_empty {
+        return match fs::remove_file(path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(())
+        };
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+fn parse_block(content: &str, exported: bool) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    let mut inside = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line == BEGIN_MARKER {
+            inside = true;
+        } else if line == END_MARKER {
+            inside = false;
+        } else if inside {
+            let assignment = if exported { line.strip_prefix("export ") } else { Some(line) };
+            if let Some((key, value)) = assignment.and_then(|assignment| assignment.split_once('=')) {
+                result.push((key.to_string(), unquote(value)));
+            }
+        }
+    }
+    result
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('$', "\\$"))
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        value[1 .. value.len() - 1].replace("\\$", "$").replace("\\\"", "\"").replace("\\\\", "\\")
+    } else {
+        value.to_string()
+    }
+}
+
+/// Replaces the vk-fixer block in `content` (or appends one when it doesn't have one yet), while
+/// leaving all other lines alone. The block is omitted entirely when `variables` is empty, in which
+/// case the blank line that separated it from the other lines is removed as well.
+fn replace_block(content: &str, variables: &[(String, String)], exported: bool) -> String {
+    let mut block = String::new();
+    if !variables.is_empty() {
+        block += BEGIN_MARKER;
+        block += "\n";
+        for (key, value) in variables {
+            if exported {
+                block += "export ";
+            }
+            block += &format!("{}={}\n", key, quote(value));
+        }
+        block += END_MARKER;
+        block += "\n";
+    }
+
+    let mut result = String::with_capacity(content.len() + block.len());
+    let mut inside = false;
+    let mut placed_block = false;
+    for line in content.lines() {
+        if line.trim() == BEGIN_MARKER {
+            inside = true;
+            if block.is_empty() && (result.ends_with("\n\n") || result == "\n") {
+                result.pop();
+            }
+            if !placed_block {
+                result += &block;
+                placed_block = true;
+            }
+        } else if line.trim() == END_MARKER {
+            inside = false;
+        } else if !inside {
+            result += line;
+            result += "\n";
+        }
+    }
+
+    if !placed_block && !block.is_empty() {
+        if !result.is_empty() {
+            result += "\n";
+        }
+        result += &block;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_home(name: &str) -> PathBuf {
+        let home = std::env::temp_dir().join(format!("vk-fixer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(&home).unwrap();
+        home
+    }
+
+    #[test]
+    fn test_add_list_remove() {
+        let home = temporary_home("add-list-remove");
+        let store = LinuxEnvironmentStore::new(&home, false);
+
+        assert_eq!(Vec::<(String, String)>::new(), store.list().unwrap());
+        store.add("DISABLE_LAYER_OVERLAY_1", "1").unwrap();
+        store.add("DISABLE_OTHER", "1").unwrap();
+        assert_eq!(vec![
+            ("DISABLE_LAYER_OVERLAY_1".to_string(), "1".to_string()),
+            ("DISABLE_OTHER".to_string(), "1".to_string())
+        ], store.list().unwrap());
+        assert!(store.contains("DISABLE_OTHER").unwrap());
+        assert!(!home.join(".profile").exists());
+
+        store.remove("DISABLE_LAYER_OVERLAY_1").unwrap();
+        assert_eq!(vec![("DISABLE_OTHER".to_string(), "1".to_string())], store.list().unwrap());
+
+        store.remove("DISABLE_OTHER").unwrap();
+        assert!(!store.environment_d_path().exists());
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_add_and_remove_are_idempotent() {
+        let home = temporary_home("idempotent");
+        let store = LinuxEnvironmentStore::new(&home, true);
+
+        store.add("DISABLE_LAYER_OVERLAY_1", "1").unwrap();
+        let environment_d = fs::read_to_string(store.environment_d_path()).unwrap();
+        let profile = fs::read_to_string(store.profile_path()).unwrap();
+
+        store.add("DISABLE_LAYER_OVERLAY_1", "1").unwrap();
+        assert_eq!(environment_d, fs::read_to_string(store.environment_d_path()).unwrap());
+        assert_eq!(profile, fs::read_to_string(store.profile_path()).unwrap());
+
+        store.remove("NOT_MANAGED").unwrap();
+        assert_eq!(environment_d, fs::read_to_string(store.environment_d_path()).unwrap());
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_shell_profile_keeps_user_content() {
+        let home = temporary_home("profile");
+        fs::write(home.join(".profile"), "export PATH=\"$HOME/bin:$PATH\"\n").unwrap();
+        let store = LinuxEnvironmentStore::new(&home, true);
+
+        store.add("DISABLE_LAYER_OVERLAY_1", "1").unwrap();
+        assert_eq!(
+            format!("export PATH=\"$HOME/bin:$PATH\"\n\n{}\nexport DISABLE_LAYER_OVERLAY_1=\"1\"\n{}\n", BEGIN_MARKER, END_MARKER),
+            fs::read_to_string(store.profile_path()).unwrap()
+        );
+        assert_eq!(
+            format!("{}\nDISABLE_LAYER_OVERLAY_1=\"1\"\n{}\n", BEGIN_MARKER, END_MARKER),
+            fs::read_to_string(store.environment_d_path()).unwrap()
+        );
+
+        store.remove("DISABLE_LAYER_OVERLAY_1").unwrap();
+        assert_eq!("export PATH=\"$HOME/bin:$PATH\"\n", fs::read_to_string(store.profile_path()).unwrap());
+
+        let original = "export PATH=\"$HOME/bin:$PATH\"\n\n# more settings\n\n";
+        fs::write(home.join(".profile"), original).unwrap();
+        store.add("DISABLE_LAYER_OVERLAY_1", "1").unwrap();
+        store.add("DISABLE_OTHER", "1").unwrap();
+        store.remove("DISABLE_LAYER_OVERLAY_1").unwrap();
+        store.remove("DISABLE_OTHER").unwrap();
+        assert_eq!(original, fs::read_to_string(store.profile_path()).unwrap());
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_values_are_quoted() {
+        let home = temporary_home("quoting");
+        let store = LinuxEnvironmentStore::new(&home, false);
+
+        store.add("WEIRD", "a \"quoted\" $value").unwrap();
+        assert_eq!(vec![("WEIRD".to_string(), "a \"quoted\" $value".to_string())], store.list().unwrap());
+        assert!(store.add("NOT VALID", "1").is_err());
+        assert!(store.add("MULTI_LINE", "1\n2").is_err());
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
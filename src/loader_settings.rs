@@ -0,0 +1,360 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::env::{args, var_os};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use crate::definitions::*;
+use crate::launcher::resolve_layers;
+use crate::meta_layers::include_meta_layers;
+use crate::registry::{get_global_environment_keys, get_implicit_layers};
+
+const LOADER_SETTINGS_USAGE: &str = "Usage:
+vk-fixer loader-settings show
+vk-fixer loader-settings disable <layer>...
+vk-fixer loader-settings remove";
+
+const LOADER_SETTINGS_FILE: &str = "vk_loader_settings.json";
+
+#[derive(Debug, Deserialize)]
+struct RootLoaderSettings {
+    settings: Option<LoaderSettingsEntry>,
+    settings_array: Option<Vec<LoaderSettingsEntry>>,
+    /// This is not part of the format: the loader ignores it, but vk-fixer uses it to recognize
+    /// the files it wrote
+    #[serde(default)]
+    vk_fixer_managed: bool
+}
+
+#[derive(Debug, Deserialize)]
+struct LoaderSettingsEntry {
+    app_keys: Option<Vec<String>>,
+    layers: Option<Vec<LayerEntry>>
+}
+
+#[derive(Debug, Deserialize)]
+struct LayerEntry {
+    control: String,
+    name: Option<String>,
+    path: Option<String>
+}
+
+/// Parses the given loader settings file. This returns `None` when the file doesn't contain a
+/// global layer configuration (for instance when it only configures specific applications).
+pub fn parse_loader_settings(path: &str, content: &str) -> std::result::Result<Option<LoaderSettings>, String> {
+    let root: RootLoaderSettings = serde_json::from_str(content).map_err(
+        |parse_error| format!("Failed to parse {}: {}", path, parse_error)
+    )?;
+
+    let global_entry = root.settings.into_iter().chain(root.settings_array.into_iter().flatten()).find(
+        |entry| entry.app_keys.as_ref().is_none_or(|app_keys| app_keys.is_empty())
+    );
+    let layer_entries = match global_entry.and_then(|entry| entry.layers) {
+        Some(layer_entries) => layer_entries,
+        None => return Ok(None)
+    };
+
+    let mut layers = Vec::with_capacity(layer_entries.len());
+    let mut has_unordered_layer_location = false;
+    for entry in layer_entries {
+        let control = match entry.control.to_ascii_lowercase().as_str() {
+            "on" => LayerControl::On,
+            "off" => LayerControl::Off,
+            "auto" => LayerControl::Auto,
+            "unordered_layer_location" => {
+                has_unordered_layer_location = true;
+                continue;
+            },
+            unknown => return Err(format!("Unknown layer control {} in {}", unknown, path))
+        };
+        let name = entry.name.ok_or(format!("Missing layer name in {}", path))?;
+        layers.push(LoaderSettingsLayer { name, path: entry.path, control });
+    }
+
+    Ok(Some(LoaderSettings {
+        path: path.to_string(),
+        layers,
+        has_unordered_layer_location,
+        managed_by_vk_fixer: root.vk_fixer_managed
+    }))
+}
+
+impl LoaderSettings {
+    /// Finds how this settings file controls the given layer, or returns `None` when the layer is
+    /// not listed. Entries with a path only match the layer with that manifest.
+    pub fn control_of(&self, layer: &ImplicitLayer) -> Option<LayerControl> {
+        self.layers.iter().find(|entry| match &entry.path {
+            Some(path) => Path::new(path) == Path::new(&layer.settings_path) || path.eq_ignore_ascii_case(&layer.settings_path),
+            None => entry.name.eq_ignore_ascii_case(&layer.name)
+        }).map(|entry| entry.control)
+    }
+
+    /// Describes which layers this settings file forces on or off, regardless of the environment
+    /// variables
+    pub fn describe_overrides(&self) -> Vec<String> {
+        let mut description = vec![format!("The loader settings file {} is active.", self.path)];
+        for (control, text) in [(LayerControl::On, "forces on"), (LayerControl::Off, "forces off")] {
+            let names: Vec<&str> = self.layers.iter().filter(
+                |entry| entry.control == control
+            ).map(|entry| entry.name.as_str()).collect();
+            if !names.is_empty() {
+                description.push(format!("It {}: {}", text, names.join(", ")));
+            }
+        }
+        if !self.has_unordered_layer_location {
+            description.push("It disables all layers that it doesn't list.".to_string());
+        }
+        description
+    }
+}
+
+/// Gets the paths where the loader looks for its settings file, in the order that the loader
+/// checks them. The loader uses only the first one that exists.
+pub fn settings_search_paths() -> Vec<PathBuf> {
+    crate::registry::get_loader_settings_paths().into_iter().map(PathBuf::from).collect()
+}
+
+/// Finds and parses the loader settings file that the loader would use
+pub fn find_loader_settings(errors: &mut Vec<String>) -> Option<LoaderSettings> {
+    let path = settings_search_paths().into_iter().find(|path| path.is_file())?;
+    let path_string = path.to_string_lossy().to_string();
+    match fs::read_to_string(&path) {
+        Ok(content) => match parse_loader_settings(&path_string, &content) {
+            Ok(settings) => settings,
+            Err(error) => {
+                errors.push(error);
+                None
+            }
+        },
+        Err(error) => {
+            errors.push(format!("Failed to read {}: {}", path_string, error));
+            None
+        }
+    }
+}
+
+/// The path of the loader settings file that vk-fixer writes: `%APPDATA%\vk-fixer\vk_loader_settings.json`
+/// on Windows (which is registered in the registry), and the first search path of the loader elsewhere.
+pub fn managed_settings_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        var_os("APPDATA").map(|app_data| Path::new(&app_data).join("vk-fixer").join(LOADER_SETTINGS_FILE))
+    } else {
+        settings_search_paths().into_iter().next()
+    }
+}
+
+/// Generates a loader settings file that forces all given layers off, and keeps the layers that
+/// an earlier vk-fixer settings file forced off. All other layers are loaded as usual. The loader
+/// needs the manifest path of each layer, so earlier layers without one are looked up in
+/// `known_layers`, and dropped when they can't be found.
+pub fn build_managed_settings(
+    disabled_layers: &[&ImplicitLayer], existing: Option<&LoaderSettings>, known_layers: &[ImplicitLayer]
+) -> Value {
+    let mut layers: Vec<Value> = Vec::new();
+    let mut add_layer = |name: &str, path: &str| {
+        if layers.iter().any(|layer| layer["name"].as_str().is_some_and(|existing| existing.eq_ignore_ascii_case(name))) {
+            return;
+        }
+        layers.push(json!({ "control": "off", "name": name, "path": path }));
+    };
+
+    if let Some(existing) = existing.filter(|existing| existing.managed_by_vk_fixer) {
+        for layer in existing.layers.iter().filter(|layer| layer.control == LayerControl::Off) {
+            let path = layer.path.as_deref().or_else(|| known_layers.iter().find(
+                |known| known.shadowed_by.is_none() && known.name.eq_ignore_ascii_case(&layer.name)
+            ).map(|known| known.settings_path.as_str()));
+            if let Some(path) = path {
+                add_layer(&layer.name, path);
+            }
+        }
+    }
+    for layer in disabled_layers {
+        add_layer(&layer.name, &layer.settings_path);
+    }
+    layers.push(json!({ "control": "unordered_layer_location" }));
+
+    json!({
+        "file_format_version": "1.0.0",
+        "vk_fixer_managed": true,
+        "settings": { "layers": layers }
+    })
+}
+
+/// Writes (or extends) the vk-fixer loader settings file such that all given layers are forced
+/// off. This refuses to replace a settings file of Vulkan Configurator, since the loader would
+/// only use one of them.
+pub fn write_managed_settings(
+    disabled_layers: &[&ImplicitLayer], existing: Option<&LoaderSettings>, known_layers: &[ImplicitLayer]
+) -> Result<PathBuf> {
+    if let Some(existing) = existing.filter(|existing| !existing.managed_by_vk_fixer) {
+        return Err(Error::new(ErrorKind::AlreadyExists, format!(
+            "The loader settings file {} of another program (probably Vulkan Configurator) is active: \
+            use that program to turn off the layers instead", existing.path
+        )));
+    }
+    let path = managed_settings_path().ok_or(Error::new(ErrorKind::NotFound, "Can't find the config directory"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_string_pretty(&build_managed_settings(disabled_layers, existing, known_layers)).map_err(
+        |error| Error::new(ErrorKind::InvalidData, error)
+    )?;
+    fs::write(&path, content)?;
+
+    if !crate::registry::register_loader_settings(&path.to_string_lossy()) {
+        return Err(Error::other("Failed to register the loader settings file"));
+    }
+    Ok(path)
+}
+
+/// Removes the loader settings file of vk-fixer, and returns whether it existed
+pub fn remove_managed_settings(existing: Option<&LoaderSettings>) -> Result<bool> {
+    let existing = match existing.filter(|existing| existing.managed_by_vk_fixer) {
+        Some(existing) => existing,
+        None => return Ok(false)
+    };
+    fs::remove_file(&existing.path)?;
+
+    if !crate::registry::unregister_loader_settings(&existing.path) {
+        return Err(Error::other("Failed to unregister the loader settings file"));
+    }
+    Ok(true)
+}
+
+/// Handles the `vk-fixer loader-settings ...` commands
+pub fn maybe_run_loader_settings_command() {
+    let args = args().collect::<Vec<_>>();
+    if args.len() >= 2 && args[1] == "loader-settings" {
+        let existing = get_global_environment_keys().loader_settings;
+        let result = match args.get(2).map(|command| command.as_str()) {
+            Some("show") => Ok(match &existing {
+                Some(settings) => settings.describe_overrides().join("\n"),
+                None => "No loader settings file is active.".to_string()
+            }),
+            Some("disable") if args.len() > 3 => {
+                let (layers, _) = get_implicit_layers();
+                resolve_layers(&args[3 ..], &layers).and_then(|disabled_layers| write_managed_settings(
+                    &include_meta_layers(disabled_layers, &layers), existing.as_ref(), &layers
+                ).map_err(|error| error.to_string())).map(
+                    |path| format!("Wrote {}", path.display())
+                )
+            },
+            Some("remove") => match remove_managed_settings(existing.as_ref()) {
+                Ok(true) => Ok("Removed the loader settings file of vk-fixer".to_string()),
+                Ok(false) => Err("vk-fixer didn't write the active loader settings file".to_string()),
+                Err(error) => Err(error.to_string())
+            },
+            _ => Err("Unknown loader-settings command".to_string())
+        };
+        match result {
+            Ok(output) => {
+                println!("{}", output);
+                exit(0);
+            },
+            Err(error) => {
+                eprintln!("{}\n{}", error, LOADER_SETTINGS_USAGE);
+                exit(2);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str) -> ImplicitLayer {
+        ImplicitLayer { settings_path: format!("C:\\Layers\\{}.json", name), ..ImplicitLayer::test(name) }
+    }
+
+    #[test]
+    fn test_parse_vkconfig_settings() {
+        let content = r#"{
+            "file_format_version": "1.0.0",
+            "settings_array": [
+                { "app_keys": ["game.exe"], "layers": [{ "control": "off", "name": "VK_LAYER_a" }] },
+                { "layers": [
+                    { "control": "on", "name": "VK_LAYER_KHRONOS_validation", "path": "C:\\Layers\\validation.json" },
+                    { "control": "off", "name": "VK_LAYER_bad_overlay" },
+                    { "control": "auto", "name": "VK_LAYER_a" }
+                ] }
+            ]
+        }"#;
+        let settings = parse_loader_settings("vk_loader_settings.json", content).unwrap().unwrap();
+        assert_eq!(3, settings.layers.len());
+        assert!(!settings.has_unordered_layer_location);
+        assert!(!settings.managed_by_vk_fixer);
+
+        let mut validation = layer("VK_LAYER_KHRONOS_validation");
+        assert_eq!(None, settings.control_of(&validation));
+        validation.settings_path = "C:\\Layers\\validation.json".to_string();
+        assert_eq!(Some(LayerControl::On), settings.control_of(&validation));
+        assert_eq!(Some(LayerControl::Off), settings.control_of(&layer("vk_layer_bad_overlay")));
+        assert_eq!(Some(LayerControl::Auto), settings.control_of(&layer("VK_LAYER_a")));
+        assert_eq!(vec![
+            "The loader settings file vk_loader_settings.json is active.".to_string(),
+            "It forces on: VK_LAYER_KHRONOS_validation".to_string(),
+            "It forces off: VK_LAYER_bad_overlay".to_string(),
+            "It disables all layers that it doesn't list.".to_string()
+        ], settings.describe_overrides());
+    }
+
+    #[test]
+    fn test_parse_settings_without_global_layers() {
+        assert_eq!(Ok(None), parse_loader_settings("a.json", r#"{ "settings": { "stderr_log": ["all"] } }"#));
+        assert_eq!(Ok(None), parse_loader_settings("b.json", r#"{ "settings_array": [
+            { "app_keys": ["game.exe"], "layers": [] }
+        ] }"#));
+        assert!(parse_loader_settings("c.json", r#"{ "settings": { "layers": [{ "control": "maybe", "name": "a" }] } }"#).is_err());
+        assert!(parse_loader_settings("d.json", "not json").is_err());
+    }
+
+    #[test]
+    fn test_managed_settings_round_trip() {
+        let first = layer("VK_LAYER_a");
+        let second = layer("VK_LAYER_b");
+
+        let content = build_managed_settings(&[&first], None, &[]).to_string();
+        let settings = parse_loader_settings("managed.json", &content).unwrap().unwrap();
+        assert!(settings.managed_by_vk_fixer);
+        assert!(settings.has_unordered_layer_location);
+        assert_eq!(Some(LayerControl::Off), settings.control_of(&first));
+        assert_eq!(None, settings.control_of(&second));
+
+        let content = build_managed_settings(&[&second, &first], Some(&settings), &[]).to_string();
+        let mut settings = parse_loader_settings("managed.json", &content).unwrap().unwrap();
+        assert_eq!(
+            vec!["VK_LAYER_a", "VK_LAYER_b"],
+            settings.layers.iter().map(|layer| layer.name.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(LayerControl::Off), settings.control_of(&second));
+
+        // Earlier entries without a path are looked up in the known layers, or dropped
+        for earlier in &mut settings.layers {
+            earlier.path = None;
+        }
+        let content = build_managed_settings(&[], Some(&settings), std::slice::from_ref(&second)).to_string();
+        let settings = parse_loader_settings("managed.json", &content).unwrap().unwrap();
+        assert_eq!(vec![LoaderSettingsLayer {
+            name: "VK_LAYER_b".to_string(),
+            path: Some(second.settings_path.clone()),
+            control: LayerControl::Off
+        }], settings.layers);
+    }
+
+    #[test]
+    fn test_refuse_foreign_settings() {
+        let foreign = LoaderSettings {
+            path: "vkconfig.json".to_string(),
+            layers: vec![],
+            has_unordered_layer_location: true,
+            managed_by_vk_fixer: false
+        };
+        assert_eq!(
+            ErrorKind::AlreadyExists,
+            write_managed_settings(&[&layer("VK_LAYER_a")], Some(&foreign), &[]).unwrap_err().kind()
+        );
+        assert!(!remove_managed_settings(Some(&foreign)).unwrap());
+    }
+}
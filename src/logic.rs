@@ -1,28 +1,384 @@
+use std::env::var;
 use crate::definitions::*;
+use crate::explicit_layers::LAYER_VARIABLES;
+use crate::extensions::{find_hidden_extensions, hides_extensions};
+use crate::layer_state::{disable_variables, LOADER_LAYERS_DISABLE};
+use crate::meta_layers::{component_layers, include_meta_layers};
 use crate::test_app::{await_test_apps, spawn_test_apps};
 
-pub fn run_all_trials(layers: &[ImplicitLayer]) -> TestResults {
-    let default_trial = spawn_test_apps(&[]);
-    let clean_trial = spawn_test_apps(&layers.iter().map(
-        |layer| layer.disable_environment.as_str()
-    ).collect::<Vec<_>>());
-    let exclude_trials = layers.iter().map(
-        |layer| (layer.name.clone(), spawn_test_apps(&[layer.disable_environment.as_str()]))
+/// Runs the trials that `plan_next_trials` asks for, or all trials when `options.exhaustive` is
+/// true. The isolation trials of `group_isolation_trials` are never run. When `options.clear_layer_variables` is true, all trials run without
+/// `VK_INSTANCE_LAYERS` and the other loader variables that add layers (see `LAYER_VARIABLES`).
+/// Trials fail when the extensions or features in `options.requirements` are unavailable.
+pub fn run_all_trials(layers: &[ImplicitLayer], options: &TrialOptions) -> TestResults {
+    run_trials(layers, options, false)
+}
+
+/// Runs only the default and clean trials, and marks the trials of the individual layers as
+/// skipped. This is used when the pre-flight check already found a certain problem with every
+/// layer: the trials of the layers can't tell anything new, but the default and clean trials still
+/// show whether Vulkan works at all.
+pub fn run_baseline_trials(layers: &[ImplicitLayer], options: &TrialOptions) -> TestResults {
+    run_trials(layers, options, true)
+}
+
+fn run_trials(layers: &[ImplicitLayer], options: &TrialOptions, baseline_only: bool) -> TestResults {
+    let existing_filter = var(LOADER_LAYERS_DISABLE).ok();
+    let removed_envs: &[&str] = if options.clear_layer_variables { &LAYER_VARIABLES } else { &[] };
+    let requirements = &options.requirements;
+    let disable = |disabled_layers: &[&ImplicitLayer]| disable_variables(
+        disabled_layers, options.method, existing_filter.as_deref()
     );
-    let isolation_trials = layers.iter().map(
-        |only_layer| (only_layer.name.clone(), spawn_test_apps(&layers.iter().filter(
-            |other_layer| other_layer != &only_layer
-        ).map(|other_layer| other_layer.disable_environment.as_str()).collect::<Vec<_>>()))
+    let find_layer = |id: &LayerId| layers.iter().find(|layer| &layer.id() == id);
+    let spawn_trial = |trial: &TrialKind| match trial {
+        TrialKind::Default => spawn_test_apps(&[], removed_envs, requirements),
+        TrialKind::Clean => spawn_test_apps(&disable(&layers.iter().collect::<Vec<_>>()), removed_envs, requirements),
+        // Disabling a component of an enabled meta-layer is pointless, so the meta-layers need to go too
+        TrialKind::Exclude(id) => spawn_test_apps(
+            &disable(&include_meta_layers(find_layer(id).into_iter().collect(), layers)), removed_envs, requirements
+        ),
+        TrialKind::Isolation(id) => spawn_test_apps(&disable(&layers.iter().filter(
+            |other_layer| &other_layer.id() != id
+        ).collect::<Vec<_>>()), removed_envs, requirements),
+        TrialKind::Extra(disabled_layers) => spawn_test_apps(&disable(&include_meta_layers(layers.iter().filter(
+            |layer| disabled_layers.contains(&layer.id())
+        ).collect(), layers)), removed_envs, requirements)
+    };
+
+    let layer_ids: Vec<LayerId> = layers.iter().map(|layer| layer.id()).collect();
+    let never_run = group_isolation_trials(layers);
+    let mut completed: Vec<(TrialKind, VersionedTrialResults)> = Vec::new();
+    let mut results = collect_results(&layer_ids, &completed);
+    loop {
+        let next_trials = if baseline_only {
+            baseline_trials(&results)
+        } else if options.exhaustive {
+            results.skipped_trials.iter().filter(|trial| !never_run.contains(trial)).cloned().collect()
+        } else {
+            plan_next_trials(
+                &results, &completed.iter().map(|(trial, _)| trial.clone()).collect::<Vec<_>>(), &layer_ids, &never_run
+            )
+        };
+        if next_trials.is_empty() {
+            return results;
+        }
+        // Spawn all trials of the batch before awaiting any of them, so that they run in parallel
+        let spawned: Vec<_> = next_trials.into_iter().map(|trial| {
+            let children = spawn_trial(&trial);
+            (trial, children)
+        }).collect();
+        for (trial, children) in spawned {
+            completed.push((trial, await_test_apps(children)));
+        }
+        results = collect_results(&layer_ids, &completed);
+    }
+}
+
+/// Puts the results of the `completed` trials in `TestResults`, in the order of `layers`. All
+/// other trials are marked as skipped.
+fn collect_results(layers: &[LayerId], completed: &[(TrialKind, VersionedTrialResults)]) -> TestResults {
+    let find = |trial: &TrialKind| completed.iter().find(
+        |(candidate, _)| candidate == trial
+    ).map(|(_, trial_results)| trial_results.clone());
+    let mut results = TestResults::default();
+    for trial in [TrialKind::Default, TrialKind::Clean].into_iter().chain(
+        layers.iter().map(|layer| TrialKind::Exclude(layer.clone()))
+    ).chain(layers.iter().map(|layer| TrialKind::Isolation(layer.clone()))) {
+        match (&trial, find(&trial)) {
+            (TrialKind::Default, Some(trial_results)) => results.default_result = trial_results,
+            (TrialKind::Clean, Some(trial_results)) => results.clean_result = trial_results,
+            (TrialKind::Exclude(layer), Some(trial_results)) => results.exclude_results.push((layer.clone(), trial_results)),
+            (TrialKind::Isolation(layer), Some(trial_results)) => results.isolation_results.push((layer.clone(), trial_results)),
+            _ => results.skipped_trials.push(trial)
+        }
+    }
+    results
+}
+
+/// The isolation trials of the meta-layers that pull in other layers. Such a trial doesn't test
+/// the meta-layer alone, since the meta-layer would enable its components as well, so a failure
+/// couldn't be blamed on the meta-layer. These trials are never run, and stay in `skipped_trials`.
+fn group_isolation_trials(layers: &[ImplicitLayer]) -> Vec<TrialKind> {
+    layers.iter().filter(|layer| !component_layers(layer, layers).is_empty()).map(
+        |layer| TrialKind::Isolation(layer.id())
+    ).collect()
+}
+
+/// The default and clean trials that are still missing from `results`
+fn baseline_trials(results: &TestResults) -> Vec<TrialKind> {
+    results.skipped_trials.iter().filter(
+        |trial| matches!(trial, TrialKind::Default | TrialKind::Clean)
+    ).cloned().collect()
+}
+
+/// Decides which trials to run next, based on the results of the `completed` trials. Returns an
+/// empty list when the remaining trials can't change the conclusion. Like `draw_conclusion`, the
+/// trials are compared against the clean trial per Vulkan version, so the versions for which the
+/// clean trial fails are ignored (unless it fails on every version):
+/// - when the default trial succeeds on every other Vulkan version, the conclusion is `Healthy`
+///   (or `Partial` or `WeirdHealthy`), so only the exclude trials may be needed to find a layer
+///   that hides extensions
+/// - when the clean trial succeeds on any version, the isolation trials run first. When they find
+///   2 or more broken layers, the exclude trials are not needed. When they find 1 broken layer,
+///   only its exclude trial is needed, to check whether the other layers conflict.
+/// - otherwise, all trials are needed
+///
+/// The `never_run` trials are never planned.
+pub fn plan_next_trials(
+    results: &TestResults, completed: &[TrialKind], layers: &[LayerId], never_run: &[TrialKind]
+) -> Vec<TrialKind> {
+    if !completed.contains(&TrialKind::Default) {
+        return vec![TrialKind::Default, TrialKind::Clean];
+    }
+    let remaining = |trials: Vec<TrialKind>| trials.into_iter().filter(
+        |trial| !completed.contains(trial) && !never_run.contains(trial)
+    ).collect::<Vec<_>>();
+    let exclude_trials = || remaining(layers.iter().map(|layer| TrialKind::Exclude(layer.clone())).collect());
+    let isolation_trials = || remaining(layers.iter().map(|layer| TrialKind::Isolation(layer.clone())).collect());
+    let ran_layer_trials = completed.len() > 2;
+    let unsupported_versions: Vec<u32> = if results.clean_result.all_failed() { vec![] } else {
+        ALL_API_VERSIONS.into_iter().filter(|api_version| !results.clean_result.succeeded(*api_version)).collect()
+    };
+
+    if results.default_result.succeeded_except(&unsupported_versions) {
+        return if hides_extensions(results) && !ran_layer_trials { exclude_trials() } else { vec![] };
+    }
+    if results.clean_result.all_failed() {
+        return [exclude_trials(), isolation_trials()].concat();
+    }
+    if !ran_layer_trials && !isolation_trials().is_empty() {
+        return isolation_trials();
+    }
+
+    let broken_layers: Vec<&LayerId> = results.isolation_results.iter().filter(
+        |(_, layer_results)| !layer_results.succeeded_except(&unsupported_versions)
+    ).map(|(layer, _)| layer).collect();
+    if hides_extensions(results) || broken_layers.is_empty() {
+        exclude_trials()
+    } else if broken_layers.len() == 1 {
+        remaining(vec![TrialKind::Exclude(broken_layers[0].clone())])
+    } else { vec![] }
+}
+
+/// Runs 1 extra trial for each set of disabled layers, with the same options as `run_all_trials`
+pub fn run_extra_trials(
+    layers: &[ImplicitLayer], disabled_sets: &[Vec<LayerId>], options: &TrialOptions
+) -> Vec<ExtraTrial> {
+    let existing_filter = var(LOADER_LAYERS_DISABLE).ok();
+    let removed_envs: &[&str] = if options.clear_layer_variables { &LAYER_VARIABLES } else { &[] };
+    let trials: Vec<_> = disabled_sets.iter().map(|disabled_layers| {
+        let disabled: Vec<&ImplicitLayer> = layers.iter().filter(
+            |layer| disabled_layers.contains(&layer.id())
+        ).collect();
+        (disabled_layers.clone(), spawn_test_apps(&disable_variables(
+            &include_meta_layers(disabled, layers), options.method, existing_filter.as_deref()
+        ), removed_envs, &options.requirements))
+    }).collect();
+    trials.into_iter().map(|(disabled_layers, trial)| ExtraTrial {
+        disabled_layers, results: await_test_apps(trial)
+    }).collect()
+}
+
+pub fn draw_conclusion(results: &TestResults) -> Conclusion {
+    diagnose(results).swap_remove(0).conclusion
+}
+
+/// The Vulkan versions for which the trials succeeded and failed, respectively
+pub fn trial_step(trial: TrialKind, results: &VersionedTrialResults) -> TraceStep {
+    let (succeeded_versions, failed_versions) = ALL_API_VERSIONS.into_iter().partition(
+        |api_version| results.succeeded(*api_version)
     );
+    TraceStep::Trial { trial, succeeded_versions, failed_versions }
+}
+
+/// Gathers the trials that failed for Vulkan versions that are not in `unsupported_versions`
+fn inconclusive(results: &TestResults, unsupported_versions: &[u32]) -> Conclusion {
+    let trials = [
+        (TrialKind::Default, &results.default_result), (TrialKind::Clean, &results.clean_result)
+    ].into_iter().chain(results.exclude_results.iter().map(
+        |(layer, layer_results)| (TrialKind::Exclude(layer.clone()), layer_results)
+    )).chain(results.isolation_results.iter().map(
+        |(layer, layer_results)| (TrialKind::Isolation(layer.clone()), layer_results)
+    ));
+    Conclusion::Inconclusive { failed_trials: trials.filter_map(|(trial, trial_results)| {
+        let failed_versions: Vec<u32> = ALL_API_VERSIONS.into_iter().filter(
+            |api_version| !unsupported_versions.contains(api_version) && !trial_results.succeeded(*api_version)
+        ).collect();
+        if failed_versions.is_empty() { None } else { Some((trial, failed_versions)) }
+    }).collect() }
+}
+
+/// The ways to resolve the conclusion, where `layers` are the layers that it is about
+fn solutions_for(conclusion: &Conclusion, layers: &[LayerId]) -> Vec<Solution> {
+    let keep_only = |kept_layer: &LayerId| Solution {
+        disabled_layers: layers.iter().filter(|layer| layer != &kept_layer).cloned().collect(),
+        kept_layer: Some(kept_layer.clone())
+    };
+    match conclusion {
+        Conclusion::WeirdBroken { important_layer, exclude: true } => vec![Solution::disable(important_layer)],
+        Conclusion::WeirdBroken { important_layer, exclude: false } => vec![keep_only(important_layer)],
+        Conclusion::BrokenLayer { layer } | Conclusion::PartiallyBrokenLayer { layer, .. } |
+        Conclusion::HiddenExtensions { layer, .. } => vec![Solution::disable(layer)],
+        Conclusion::AsymmetricConflict { main_offender } => vec![Solution::disable(main_offender)],
+        Conclusion::SymmetricConflict { layers: conflicting_layers } => conflicting_layers.iter().map(Solution::disable).collect(),
+        Conclusion::ComplexConflict => layers.iter().map(keep_only).collect(),
+        _ => vec![]
+    }
+}
+
+/// The Vulkan versions for which all trials failed
+pub fn unsupported_versions(results: &TestResults) -> Vec<u32> {
+    let all_results = [&results.default_result, &results.clean_result].into_iter().chain(
+        results.exclude_results.iter().chain(&results.isolation_results).map(|(_, layer_results)| layer_results)
+    );
+    ALL_API_VERSIONS.into_iter().filter(
+        |api_version| all_results.clone().all(|trial| !trial.succeeded(*api_version))
+    ).collect()
+}
+
+fn broken_layer_conclusion(layer: &LayerId, layer_results: &VersionedTrialResults, unsupported_versions: &[u32]) -> Conclusion {
+    if layer_results.all_failed() {
+        Conclusion::BrokenLayer { layer: layer.clone() }
+    } else {
+        Conclusion::PartiallyBrokenLayer {
+            layer: layer.clone(),
+            broken_versions: ALL_API_VERSIONS.into_iter().filter(
+                |api_version| !unsupported_versions.contains(api_version) && !layer_results.succeeded(*api_version)
+            ).collect()
+        }
+    }
+}
+
+/// The reasoning behind a `HiddenExtensions` conclusion about `layer`
+fn hidden_extensions_trace(results: &TestResults, layer: &LayerId) -> Vec<TraceStep> {
+    let mut trace = vec![TraceStep::Note("the default trial misses extensions that the clean trial has".to_string())];
+    if let Some((_, layer_results)) = results.exclude_results.iter().find(|(candidate, _)| candidate == layer) {
+        trace.push(trial_step(TrialKind::Exclude(layer.clone()), layer_results));
+    }
+    trace
+}
+
+/// Draws conclusions from the trial results, and records the trials that were decisive for them.
+/// The first finding is the conclusion of `draw_conclusion`. When the trials reveal other
+/// problems as well, like a second broken layer or a conflict between the remaining layers, they
+/// become extra findings, so that the user can fix all of them at once.
+pub fn diagnose(results: &TestResults) -> Vec<Finding> {
+    let tested_layers: Vec<LayerId> = results.isolation_results.iter().map(|(layer, _)| layer.clone()).collect();
+    let finding = |conclusion: Conclusion, trace: Vec<TraceStep>, layers: &[LayerId]| Finding {
+        solutions: solutions_for(&conclusion, layers), conclusion, trace
+    };
+
+    let mut trace = Vec::new();
+    let conclusion = draw_traced_conclusion(results, &mut trace);
+    if !results.skipped_trials.is_empty() {
+        trace.push(TraceStep::Note(format!(
+            "{} trials were skipped, since they couldn't change the conclusion", results.skipped_trials.len()
+        )));
+    }
+    let mut findings = vec![finding(conclusion, trace, &tested_layers)];
+    let unsupported_versions = unsupported_versions(results);
+
+    if let Conclusion::BrokenLayer { layer } | Conclusion::PartiallyBrokenLayer { layer, .. } = &findings[0].conclusion {
+        let first_broken_layer = layer.clone();
+        let mut broken_layers = vec![first_broken_layer.clone()];
+        for (layer, layer_results) in &results.isolation_results {
+            if layer != &first_broken_layer && !layer_results.succeeded_except(&unsupported_versions) {
+                broken_layers.push(layer.clone());
+                findings.push(finding(
+                    broken_layer_conclusion(layer, layer_results, &unsupported_versions),
+                    vec![
+                        trial_step(TrialKind::Clean, &results.clean_result),
+                        trial_step(TrialKind::Isolation(layer.clone()), layer_results)
+                    ],
+                    &tested_layers
+                ));
+            }
+        }
+
+        // The exclude trial of the only broken layer tells whether the other layers work together
+        let remaining_layers: Vec<LayerId> = tested_layers.iter().filter(
+            |layer| !broken_layers.contains(layer)
+        ).cloned().collect();
+        let exclude_trial = results.exclude_results.iter().find(|(layer, _)| layer == &first_broken_layer);
+        if let (1, Some((_, exclude_results))) = (broken_layers.len(), exclude_trial) {
+            if remaining_layers.len() >= 2 && !exclude_results.succeeded_except(&unsupported_versions) {
+                let conclusion = if remaining_layers.len() == 2 {
+                    Conclusion::SymmetricConflict { layers: remaining_layers.clone() }
+                } else { Conclusion::ComplexConflict };
+                findings.push(finding(conclusion, vec![
+                    trial_step(TrialKind::Exclude(first_broken_layer), exclude_results),
+                    TraceStep::Note("all other layers work in isolation, so some of them conflict with each other".to_string())
+                ], &remaining_layers));
+            }
+        }
+    }
+
+    // Layers can hide extensions regardless of other problems
+    if let Some(hidden_extensions) = find_hidden_extensions(results) {
+        if let Conclusion::HiddenExtensions { layer, .. } = &hidden_extensions {
+            let already_disabled = findings.iter().any(|finding| finding.solutions.iter().any(
+                |solution| solution.kept_layer.is_none() && solution.disabled_layers.contains(layer)
+            ));
+            if !already_disabled && findings.iter().all(|finding| finding.conclusion != hidden_extensions) {
+                findings.push(finding(hidden_extensions.clone(), hidden_extensions_trace(results, layer), &tested_layers));
+            }
+        }
+    }
+    findings
+}
+
+/// Copies the trial results of `api_version`, and marks the trials of all other Vulkan versions as
+/// failed, so that `diagnose` ignores them
+fn project_version(results: &TestResults, api_version: u32) -> TestResults {
+    let project = |versioned: &VersionedTrialResults| {
+        let mut projected = versioned.clone();
+        for (candidate, trial) in ALL_API_VERSIONS.into_iter().zip([
+            &mut projected.vk10, &mut projected.vk11, &mut projected.vk12, &mut projected.vk13
+        ]) {
+            if candidate != api_version {
+                *trial = TrialResult { exit_code: -1, ..Default::default() };
+            }
+        }
+        projected
+    };
+    let project_layers = |layer_results: &[(LayerId, VersionedTrialResults)]| layer_results.iter().map(
+        |(layer, versioned)| (layer.clone(), project(versioned))
+    ).collect();
     TestResults {
-        default_result: await_test_apps(default_trial),
-        clean_result: await_test_apps(clean_trial),
-        exclude_results: exclude_trials.map(|trial| (trial.0, await_test_apps(trial.1))).collect(),
-        isolation_results: isolation_trials.map(|trial| (trial.0, await_test_apps(trial.1))).collect(),
+        default_result: project(&results.default_result),
+        clean_result: project(&results.clean_result),
+        exclude_results: project_layers(&results.exclude_results),
+        isolation_results: project_layers(&results.isolation_results),
+        skipped_trials: results.skipped_trials.clone()
     }
 }
 
-pub fn draw_conclusion(results: &TestResults) -> Conclusion {
+/// Diagnoses each Vulkan version independently, since a layer can break one version while a
+/// conflict breaks another version
+pub fn diagnose_per_version(results: &TestResults) -> Vec<(u32, Vec<Finding>)> {
+    ALL_API_VERSIONS.into_iter().map(|api_version| {
+        let projected = project_version(results, api_version);
+        let mut findings = diagnose(&projected);
+        for finding in &mut findings {
+            finding.trace.retain(|step| !matches!(step, TraceStep::UnsupportedVersions(_)));
+            match finding.conclusion {
+                // Since all other versions are marked as failed, Partial means that all trials of this version succeeded
+                Conclusion::Partial { .. } => {
+                    finding.conclusion = Conclusion::Healthy;
+                    finding.trace = vec![TraceStep::Note("all trials succeeded".to_string())];
+                },
+                Conclusion::Hopeless => finding.trace = vec![TraceStep::Note("all trials failed".to_string())],
+                _ => {}
+            }
+        }
+        if findings.len() > 1 && findings[0].conclusion == Conclusion::Healthy {
+            findings.remove(0);
+        }
+        (api_version, findings)
+    }).collect()
+}
+
+fn draw_traced_conclusion(results: &TestResults, trace: &mut Vec<TraceStep>) -> Conclusion {
     let mut all_results = Vec::with_capacity(2 + 2 * results.isolation_results.len());
     all_results.push(results.clean_result.clone());
     all_results.push(results.default_result.clone());
@@ -34,9 +390,15 @@ pub fn draw_conclusion(results: &TestResults) -> Conclusion {
     }
 
     if all_results.iter().all(|trial| trial.all_succeeded()) {
-        return Conclusion::Healthy;
+        trace.push(TraceStep::Note(format!("all {} trials succeeded on every Vulkan version", all_results.len())));
+        let hidden_extensions = find_hidden_extensions(results);
+        if let Some(Conclusion::HiddenExtensions { layer, .. }) = &hidden_extensions {
+            trace.extend(hidden_extensions_trace(results, layer));
+        }
+        return hidden_extensions.unwrap_or(Conclusion::Healthy);
     }
     if all_results.iter().all(|trial| trial.all_failed()) {
+        trace.push(TraceStep::Note(format!("all {} trials failed on every Vulkan version", all_results.len())));
         return Conclusion::Hopeless;
     }
 
@@ -45,7 +407,7 @@ pub fn draw_conclusion(results: &TestResults) -> Conclusion {
 
     let mut supported_versions = Vec::with_capacity(4);
     let mut unsupported_versions = Vec::with_capacity(4);
-    for version in [ash::vk::API_VERSION_1_0, ash::vk::API_VERSION_1_1, ash::vk::API_VERSION_1_2, ash::vk::API_VERSION_1_3] {
+    for version in ALL_API_VERSIONS {
         if all_results.iter().all(|trial| trial.succeeded(version)) {
             supported_versions.push(version);
         }
@@ -53,8 +415,12 @@ pub fn draw_conclusion(results: &TestResults) -> Conclusion {
             unsupported_versions.push(version);
         }
     }
+    if !unsupported_versions.is_empty() {
+        trace.push(TraceStep::UnsupportedVersions(unsupported_versions.clone()));
+    }
 
     if unsupported_versions.len() + supported_versions.len() == 4 {
+        trace.push(TraceStep::Note("every trial succeeded on all other Vulkan versions".to_string()));
         return Conclusion::Partial { supported_versions }
     }
 
@@ -62,94 +428,101 @@ pub fn draw_conclusion(results: &TestResults) -> Conclusion {
     // Maybe, some layer doesn't like a particular Vulkan version.
     // Maybe, some layer doesn't support any Vulkan version.
 
+    trace.push(trial_step(TrialKind::Default, &results.default_result));
     if results.default_result.succeeded_except(&unsupported_versions) {
         return Conclusion::WeirdHealthy;
     }
 
     // When this code is reached, the default trials didn't succeed
 
+    trace.push(trial_step(TrialKind::Clean, &results.clean_result));
     if !results.clean_result.succeeded_except(&unsupported_versions) {
         for (layer, layer_results) in &results.exclude_results {
             if layer_results.succeeded_except(&unsupported_versions) {
+                trace.push(trial_step(TrialKind::Exclude(layer.clone()), layer_results));
                 return Conclusion::WeirdBroken { important_layer: layer.clone(), exclude: true };
             }
         }
+        trace.push(TraceStep::Note("all exclude trials failed".to_string()));
         for (layer, layer_results) in &results.isolation_results {
             if layer_results.succeeded_except(&unsupported_versions) {
+                trace.push(trial_step(TrialKind::Isolation(layer.clone()), layer_results));
                 return Conclusion::WeirdBroken { important_layer: layer.clone(), exclude: false };
             }
         }
-        unreachable!();
+        trace.push(TraceStep::Note("all isolation trials failed".to_string()));
+        return inconclusive(results, &unsupported_versions);
     }
 
     // When this code is reached, the clean trials succeeded
 
     for (layer, layer_results) in &results.isolation_results {
         if !layer_results.succeeded_except(&unsupported_versions) {
-            return if layer_results.all_failed() {
-                Conclusion::BrokenLayer { layer: layer.clone() }
-            } else {
-                Conclusion::PartiallyBrokenLayer {
-                    layer: layer.clone(),
-                    broken_versions: [
-                        ash::vk::API_VERSION_1_0, ash::vk::API_VERSION_1_1, ash::vk::API_VERSION_1_2, ash::vk::API_VERSION_1_3
-                    ].into_iter().filter(
-                        |api_version| !unsupported_versions.contains(api_version)
-                            && !layer_results.succeeded(*api_version)
-                    ).collect()
-                }
-            }
+            trace.push(trial_step(TrialKind::Isolation(layer.clone()), layer_results));
+            return broken_layer_conclusion(layer, layer_results, &unsupported_versions);
         }
     }
 
     // When this code is reached, all layers work in isolation, so there must be some conflict
 
-    let num_succeeded_exclude_trials = results.exclude_results.iter().filter(|(_, layer_results)| {
+    trace.push(TraceStep::Note("all isolation trials succeeded".to_string()));
+    let succeeded_exclude_trials: Vec<_> = results.exclude_results.iter().filter(|(_, layer_results)| {
         layer_results.succeeded_except(&unsupported_versions)
-    }).count();
+    }).collect();
 
-    if num_succeeded_exclude_trials == 0 {
+    if succeeded_exclude_trials.is_empty() {
+        trace.push(TraceStep::Note("all exclude trials failed".to_string()));
         return Conclusion::ComplexConflict;
     }
 
-    if num_succeeded_exclude_trials == 1 {
-        for (layer, layer_results) in &results.exclude_results {
-            if layer_results.succeeded_except(&unsupported_versions) {
-                return Conclusion::AsymmetricConflict { main_offender: layer.clone() };
-            }
-        }
-        unreachable!()
+    for (layer, layer_results) in &succeeded_exclude_trials {
+        trace.push(trial_step(TrialKind::Exclude(layer.clone()), layer_results));
+    }
+
+    if succeeded_exclude_trials.len() == 1 {
+        trace.push(TraceStep::Note("all other exclude trials failed".to_string()));
+        return Conclusion::AsymmetricConflict { main_offender: succeeded_exclude_trials[0].0.clone() };
     }
 
     // When this code is reached, there are multiple possibilities to resolve the conflict
 
-    let conflicting_layers = results.exclude_results.iter().filter(|(_, layer_results)| {
-        layer_results.succeeded_except(&unsupported_versions)
-    }).map(|(layer, _)| layer.clone()).collect();
+    let conflicting_layers = succeeded_exclude_trials.into_iter().map(|(layer, _)| layer.clone()).collect();
     Conclusion::SymmetricConflict { layers: conflicting_layers }
 }
 
 #[cfg(test)]
 mod tests {
     use ash::vk;
-    use crate::definitions::{Conclusion, TestResults, TrialResult, VersionedTrialResults};
-    use crate::logic::draw_conclusion;
+    use crate::definitions::*;
+    use crate::logic::{
+        baseline_trials, collect_results, diagnose, diagnose_per_version, draw_conclusion, group_isolation_trials,
+        plan_next_trials
+    };
+    use crate::report::conclusion_layers;
+
+    fn id(name: &str) -> LayerId {
+        LayerId {
+            registry: ImplicitRegistry::CurrentUser,
+            settings_path: format!("{}.json", name),
+            manifest_index: 0
+        }
+    }
 
     fn failed_all() -> VersionedTrialResults {
         VersionedTrialResults {
-            vk10: TrialResult { exit_code: 1234, output: "failed1234".to_string() },
-            vk11: TrialResult { exit_code: 1234, output: "failed1234".to_string() },
-            vk12: TrialResult { exit_code: 1234, output: "failed1234".to_string() },
-            vk13: TrialResult { exit_code: 1234, output: "failed1234".to_string() },
+            vk10: TrialResult { exit_code: 1234, output: "failed1234".to_string(), ..Default::default() },
+            vk11: TrialResult { exit_code: 1234, output: "failed1234".to_string(), ..Default::default() },
+            vk12: TrialResult { exit_code: 1234, output: "failed1234".to_string(), ..Default::default() },
+            vk13: TrialResult { exit_code: 1234, output: "failed1234".to_string(), ..Default::default() },
         }
     }
 
     fn succeeded_all() -> VersionedTrialResults {
         VersionedTrialResults {
-            vk10: TrialResult { exit_code: 0, output: "".to_string() },
-            vk11: TrialResult { exit_code: 0, output: "".to_string() },
-            vk12: TrialResult { exit_code: 0, output: "".to_string() },
-            vk13: TrialResult { exit_code: 0, output: "".to_string() },
+            vk10: TrialResult { exit_code: 0, output: "".to_string(), ..Default::default() },
+            vk11: TrialResult { exit_code: 0, output: "".to_string(), ..Default::default() },
+            vk12: TrialResult { exit_code: 0, output: "".to_string(), ..Default::default() },
+            vk13: TrialResult { exit_code: 0, output: "".to_string(), ..Default::default() },
         }
     }
 
@@ -160,6 +533,7 @@ mod tests {
             clean_result: succeeded_all(),
             exclude_results: vec![],
             isolation_results: vec![],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::Healthy, draw_conclusion(&results));
     }
@@ -169,8 +543,9 @@ mod tests {
         let results = TestResults {
             default_result: succeeded_all(),
             clean_result: succeeded_all(),
-            exclude_results: vec![("dummy".to_string(), succeeded_all())],
-            isolation_results: vec![("dummy".to_string(), succeeded_all())],
+            exclude_results: vec![(id("dummy"), succeeded_all())],
+            isolation_results: vec![(id("dummy"), succeeded_all())],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::Healthy, draw_conclusion(&results));
     }
@@ -181,13 +556,14 @@ mod tests {
             default_result: succeeded_all(),
             clean_result: succeeded_all(),
             exclude_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::Healthy, draw_conclusion(&results));
     }
@@ -199,6 +575,7 @@ mod tests {
             clean_result: failed_all(),
             exclude_results: vec![],
             isolation_results: vec![],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::Hopeless, draw_conclusion(&results));
     }
@@ -208,8 +585,9 @@ mod tests {
         let results = TestResults {
             default_result: failed_all(),
             clean_result: failed_all(),
-            exclude_results: vec![("dummy".to_string(), failed_all())],
-            isolation_results: vec![("dummy".to_string(), failed_all())],
+            exclude_results: vec![(id("dummy"), failed_all())],
+            isolation_results: vec![(id("dummy"), failed_all())],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::Hopeless, draw_conclusion(&results));
     }
@@ -220,23 +598,24 @@ mod tests {
             default_result: failed_all(),
             clean_result: failed_all(),
             exclude_results: vec![
-                ("layer1".to_string(), failed_all()),
-                ("layer2".to_string(), failed_all())
+                (id("layer1"), failed_all()),
+                (id("layer2"), failed_all())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), failed_all()),
-                ("layer2".to_string(), failed_all())
+                (id("layer1"), failed_all()),
+                (id("layer2"), failed_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::Hopeless, draw_conclusion(&results));
     }
     
     fn without_vk12_support() -> VersionedTrialResults {
         VersionedTrialResults {
-            vk10: TrialResult { exit_code: 0, output: "".to_string() },
-            vk11: TrialResult { exit_code: 0, output: "".to_string() },
-            vk12: TrialResult { exit_code: 21000, output: "not happening".to_string() },
-            vk13: TrialResult { exit_code: 0, output: "".to_string() },
+            vk10: TrialResult { exit_code: 0, output: "".to_string(), ..Default::default() },
+            vk11: TrialResult { exit_code: 0, output: "".to_string(), ..Default::default() },
+            vk12: TrialResult { exit_code: 21000, output: "not happening".to_string(), ..Default::default() },
+            vk13: TrialResult { exit_code: 0, output: "".to_string(), ..Default::default() },
         }
     }
 
@@ -247,6 +626,7 @@ mod tests {
             clean_result: without_vk12_support(),
             exclude_results: vec![],
             isolation_results: vec![],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::Partial { supported_versions: vec![
             vk::API_VERSION_1_0, vk::API_VERSION_1_1, vk::API_VERSION_1_3
@@ -258,8 +638,9 @@ mod tests {
         let results = TestResults {
             default_result: without_vk12_support(),
             clean_result: without_vk12_support(),
-            exclude_results: vec![("dummy".to_string(), without_vk12_support())],
-            isolation_results: vec![("dummy".to_string(), without_vk12_support())],
+            exclude_results: vec![(id("dummy"), without_vk12_support())],
+            isolation_results: vec![(id("dummy"), without_vk12_support())],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::Partial { supported_versions: vec![
             vk::API_VERSION_1_0, vk::API_VERSION_1_1, vk::API_VERSION_1_3
@@ -272,13 +653,14 @@ mod tests {
             default_result: without_vk12_support(),
             clean_result: without_vk12_support(),
             exclude_results: vec![
-                ("layer1".to_string(), without_vk12_support()),
-                ("layer2".to_string(), without_vk12_support())
+                (id("layer1"), without_vk12_support()),
+                (id("layer2"), without_vk12_support())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), without_vk12_support()),
-                ("layer2".to_string(), without_vk12_support())
+                (id("layer1"), without_vk12_support()),
+                (id("layer2"), without_vk12_support())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::Partial { supported_versions: vec![
             vk::API_VERSION_1_0, vk::API_VERSION_1_1, vk::API_VERSION_1_3
@@ -290,8 +672,9 @@ mod tests {
         let results = TestResults {
             default_result: succeeded_all(),
             clean_result: without_vk12_support(),
-            exclude_results: vec![("dummy".to_string(), without_vk12_support())],
-            isolation_results: vec![("dummy".to_string(), succeeded_all())],
+            exclude_results: vec![(id("dummy"), without_vk12_support())],
+            isolation_results: vec![(id("dummy"), succeeded_all())],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::WeirdHealthy, draw_conclusion(&results));
     }
@@ -301,8 +684,9 @@ mod tests {
         let results = TestResults {
             default_result: succeeded_all(),
             clean_result: failed_all(),
-            exclude_results: vec![("dummy".to_string(), failed_all())],
-            isolation_results: vec![("dummy".to_string(), succeeded_all())],
+            exclude_results: vec![(id("dummy"), failed_all())],
+            isolation_results: vec![(id("dummy"), succeeded_all())],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::WeirdHealthy, draw_conclusion(&results));
     }
@@ -313,13 +697,14 @@ mod tests {
             default_result: succeeded_all(),
             clean_result: without_vk12_support(),
             exclude_results: vec![
-                ("layer1".to_string(), without_vk12_support()),
-                ("layer2".to_string(), succeeded_all())
+                (id("layer1"), without_vk12_support()),
+                (id("layer2"), succeeded_all())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), without_vk12_support())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), without_vk12_support())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::WeirdHealthy, draw_conclusion(&results));
     }
@@ -331,16 +716,17 @@ mod tests {
             default_result: without_vk12_support(),
             clean_result: failed_all(),
             exclude_results: vec![
-                ("layer1".to_string(), failed_all()),
-                ("layer2".to_string(), succeeded_all())
+                (id("layer1"), failed_all()),
+                (id("layer2"), succeeded_all())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), failed_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), failed_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::WeirdBroken {
-            important_layer: "layer2".to_string(), exclude: true
+            important_layer: id("layer2"), exclude: true
         }, draw_conclusion(&results));
     }
 
@@ -353,18 +739,19 @@ mod tests {
             default_result: without_vk12_support(),
             clean_result: failed_all(),
             exclude_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), without_vk12_support()),
-                ("layer3".to_string(), failed_all()),
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), without_vk12_support()),
+                (id("layer3"), failed_all()),
             ],
             isolation_results: vec![
-                ("layer1".to_string(), failed_all()),
-                ("layer2".to_string(), failed_all()),
-                ("layer3".to_string(), succeeded_all()),
+                (id("layer1"), failed_all()),
+                (id("layer2"), failed_all()),
+                (id("layer3"), succeeded_all()),
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::WeirdBroken {
-            important_layer: "layer1".to_string(), exclude: true
+            important_layer: id("layer1"), exclude: true
         }, draw_conclusion(&results));
     }
 
@@ -377,18 +764,19 @@ mod tests {
             default_result: failed_all(),
             clean_result: failed_all(),
             exclude_results: vec![
-                ("layer1".to_string(), failed_all()),
-                ("layer2".to_string(), without_vk12_support()),
-                ("layer3".to_string(), failed_all()),
+                (id("layer1"), failed_all()),
+                (id("layer2"), without_vk12_support()),
+                (id("layer3"), failed_all()),
             ],
             isolation_results: vec![
-                ("layer1".to_string(), failed_all()),
-                ("layer2".to_string(), failed_all()),
-                ("layer3".to_string(), succeeded_all()),
+                (id("layer1"), failed_all()),
+                (id("layer2"), failed_all()),
+                (id("layer3"), succeeded_all()),
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::WeirdBroken {
-            important_layer: "layer3".to_string(), exclude: false
+            important_layer: id("layer3"), exclude: false
         }, draw_conclusion(&results));
     }
 
@@ -397,10 +785,26 @@ mod tests {
         let results = TestResults {
             default_result: failed_all(),
             clean_result: succeeded_all(),
-            exclude_results: vec![("broken".to_string(), succeeded_all())],
-            isolation_results: vec![("broken".to_string(), failed_all())],
+            exclude_results: vec![(id("broken"), succeeded_all())],
+            isolation_results: vec![(id("broken"), failed_all())],
+            skipped_trials: vec![]
+        };
+        assert_eq!(Conclusion::BrokenLayer { layer: id("broken") }, draw_conclusion(&results));
+    }
+
+    #[test]
+    fn test_draw_conclusion_broken_layer_duplicate_names() {
+        // Both layers are called VK_LAYER_overlay, but only the second one is broken
+        let mut second = id("overlay");
+        second.manifest_index = 1;
+        let results = TestResults {
+            default_result: failed_all(),
+            clean_result: succeeded_all(),
+            exclude_results: vec![(id("overlay"), failed_all()), (second.clone(), succeeded_all())],
+            isolation_results: vec![(id("overlay"), succeeded_all()), (second.clone(), failed_all())],
+            skipped_trials: vec![]
         };
-        assert_eq!(Conclusion::BrokenLayer { layer: "broken".to_string() }, draw_conclusion(&results));
+        assert_eq!(Conclusion::BrokenLayer { layer: second }, draw_conclusion(&results));
     }
 
     #[test]
@@ -410,36 +814,359 @@ mod tests {
             default_result: without_vk12_support(),
             clean_result: succeeded_all(),
             exclude_results: vec![
-                ("layer1".to_string(), without_vk12_support()),
-                ("layer2".to_string(), succeeded_all())
+                (id("layer1"), without_vk12_support()),
+                (id("layer2"), succeeded_all())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), without_vk12_support())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), without_vk12_support())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::PartiallyBrokenLayer { 
-            layer: "layer2".to_string(),
+            layer: id("layer2"),
             broken_versions: vec![vk::API_VERSION_1_2],
         }, draw_conclusion(&results));
     }
 
+    /// Runs the trials that `plan_next_trials` asks for, where `outcome` gives the result of each
+    /// trial, and returns the results and the number of batches
+    fn run_planned_trials(
+        layers: &[LayerId], outcome: &dyn Fn(&TrialKind) -> VersionedTrialResults
+    ) -> (TestResults, usize) {
+        run_planned_trials_except(layers, &[], outcome)
+    }
+
+    /// Like `run_planned_trials`, but never runs the `never_run` trials
+    fn run_planned_trials_except(
+        layers: &[LayerId], never_run: &[TrialKind], outcome: &dyn Fn(&TrialKind) -> VersionedTrialResults
+    ) -> (TestResults, usize) {
+        let mut completed: Vec<(TrialKind, VersionedTrialResults)> = Vec::new();
+        let mut results = collect_results(layers, &completed);
+        let mut batches = 0;
+        loop {
+            let completed_trials: Vec<TrialKind> = completed.iter().map(|(trial, _)| trial.clone()).collect();
+            let next_trials = plan_next_trials(&results, &completed_trials, layers, never_run);
+            if next_trials.is_empty() {
+                return (results, batches);
+            }
+            for trial in next_trials {
+                assert!(!completed_trials.contains(&trial), "{:?} was planned twice", trial);
+                completed.push((trial.clone(), outcome(&trial)));
+            }
+            results = collect_results(layers, &completed);
+            batches += 1;
+        }
+    }
+
+    #[test]
+    fn test_plan_healthy() {
+        let layers = [id("a"), id("b")];
+        let (results, batches) = run_planned_trials(&layers, &|_| succeeded_all());
+        assert_eq!(1, batches);
+        assert_eq!(4, results.skipped_trials.len());
+        assert_eq!(Conclusion::Healthy, draw_conclusion(&results));
+        assert!(diagnose(&results)[0].trace.contains(&TraceStep::Note(
+            "4 trials were skipped, since they couldn't change the conclusion".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_plan_broken_layer() {
+        let layers = [id("a"), id("broken"), id("c")];
+        let outcome = |trial: &TrialKind| if trial == &TrialKind::Default || trial == &TrialKind::Isolation(id("broken")) {
+            failed_all()
+        } else { succeeded_all() };
+        let (results, batches) = run_planned_trials(&layers, &outcome);
+        assert_eq!(3, batches);
+        assert_eq!(vec![(id("broken"), succeeded_all())], results.exclude_results);
+        assert_eq!(vec![TrialKind::Exclude(id("a")), TrialKind::Exclude(id("c"))], results.skipped_trials);
+        assert_eq!(Conclusion::BrokenLayer { layer: id("broken") }, draw_conclusion(&results));
+    }
+
+    #[test]
+    fn test_meta_layer_with_broken_component() {
+        let component = ImplicitLayer::test("VK_LAYER_broken");
+        let meta_layer = ImplicitLayer {
+            meta_layer: Some(MetaLayer {
+                component_layers: vec!["VK_LAYER_broken".to_string()],
+                blacklisted_layers: vec![]
+            }),
+            ..ImplicitLayer::test("VK_LAYER_meta")
+        };
+        let layers = [component.clone(), meta_layer.clone(), ImplicitLayer::test("VK_LAYER_other")];
+        let never_run = group_isolation_trials(&layers);
+        assert_eq!(vec![TrialKind::Isolation(meta_layer.id())], never_run);
+
+        // The isolation trial of the meta-layer would pull in the broken component, so it must not
+        // run: its failure would be blamed on the meta-layer
+        let layer_ids: Vec<LayerId> = layers.iter().map(|layer| layer.id()).collect();
+        let outcome = |trial: &TrialKind| match trial {
+            TrialKind::Default | TrialKind::Exclude(_) => failed_all(),
+            TrialKind::Isolation(layer) if layer == &component.id() || layer == &meta_layer.id() => failed_all(),
+            _ => succeeded_all()
+        };
+        let (results, _) = run_planned_trials_except(&layer_ids, &never_run, &outcome);
+        assert!(results.skipped_trials.contains(&TrialKind::Isolation(meta_layer.id())));
+        assert!(results.isolation_results.iter().all(|(layer, _)| layer != &meta_layer.id()));
+
+        let findings = diagnose(&results);
+        assert_eq!(1, findings.len());
+        assert_eq!(Conclusion::BrokenLayer { layer: component.id() }, findings[0].conclusion);
+
+        // When the clean trial fails, all other trials are needed, but this one still doesn't run
+        let (results, _) = run_planned_trials_except(&layer_ids, &never_run, &|trial| match trial {
+            TrialKind::Clean => failed_all(),
+            _ => outcome(trial)
+        });
+        assert_eq!(vec![TrialKind::Isolation(meta_layer.id())], results.skipped_trials);
+    }
+
+    #[test]
+    fn test_plan_without_vk12_support() {
+        // Vulkan 1.2 never works, which must not make the planner run every trial
+        let layers = [id("a"), id("b")];
+        let (results, batches) = run_planned_trials(&layers, &|_| without_vk12_support());
+        assert_eq!(1, batches);
+        assert_eq!(4, results.skipped_trials.len());
+        assert_eq!(Conclusion::Partial {
+            supported_versions: vec![vk::API_VERSION_1_0, vk::API_VERSION_1_1, vk::API_VERSION_1_3]
+        }, draw_conclusion(&results));
+
+        let layers = [id("a"), id("broken"), id("c")];
+        let outcome = |trial: &TrialKind| if trial == &TrialKind::Default || trial == &TrialKind::Isolation(id("broken")) {
+            failed_all()
+        } else { without_vk12_support() };
+        let (results, batches) = run_planned_trials(&layers, &outcome);
+        assert_eq!(3, batches);
+        assert_eq!(vec![TrialKind::Exclude(id("a")), TrialKind::Exclude(id("c"))], results.skipped_trials);
+        assert_eq!(Conclusion::BrokenLayer { layer: id("broken") }, draw_conclusion(&results));
+    }
+
+    #[test]
+    fn test_baseline_trials() {
+        let layers = [id("a"), id("b")];
+        assert_eq!(vec![TrialKind::Default, TrialKind::Clean], baseline_trials(&collect_results(&layers, &[])));
+
+        let completed = [(TrialKind::Default, failed_all()), (TrialKind::Clean, failed_all())];
+        let results = collect_results(&layers, &completed);
+        assert!(baseline_trials(&results).is_empty());
+        assert_eq!(4, results.skipped_trials.len());
+        assert_eq!(Conclusion::Hopeless, draw_conclusion(&results));
+    }
+
+    #[test]
+    fn test_plan_everything() {
+        // When the clean trial fails, all trials are needed
+        let layers = [id("a"), id("b")];
+        let (results, batches) = run_planned_trials(&layers, &|trial| match trial {
+            TrialKind::Exclude(layer) if layer == &id("a") => succeeded_all(),
+            _ => failed_all()
+        });
+        assert_eq!(2, batches);
+        assert!(results.skipped_trials.is_empty());
+        assert_eq!(
+            Conclusion::WeirdBroken { important_layer: id("a"), exclude: true },
+            draw_conclusion(&results)
+        );
+    }
+
+    #[test]
+    fn test_draw_conclusion_inconclusive_no_layers() {
+        // The default trial fails for Vulkan 1.2, and the clean trial fails for Vulkan 1.3
+        let results = TestResults {
+            default_result: without_vk12_support(),
+            clean_result: from_mask(0b0111),
+            exclude_results: vec![],
+            isolation_results: vec![],
+            skipped_trials: vec![]
+        };
+        assert_eq!(Conclusion::Inconclusive { failed_trials: vec![
+            (TrialKind::Default, vec![vk::API_VERSION_1_2]),
+            (TrialKind::Clean, vec![vk::API_VERSION_1_3])
+        ] }, draw_conclusion(&results));
+    }
+
+    /// Builds trial results that succeed for the Vulkan versions whose bit is set in `mask`
+    fn from_mask(mask: u8) -> VersionedTrialResults {
+        let trial = |bit: u8| TrialResult {
+            exit_code: if mask & (1 << bit) != 0 { 0 } else { 1234 }, ..Default::default()
+        };
+        VersionedTrialResults { vk10: trial(0), vk11: trial(1), vk12: trial(2), vk13: trial(3) }
+    }
+
+    fn check_diagnosis(results: &TestResults) {
+        let findings = diagnose(results);
+        assert!(!findings.is_empty());
+        for finding in &findings {
+            let solution_layers = finding.solutions.iter().flat_map(
+                |solution| solution.disabled_layers.iter().chain(&solution.kept_layer)
+            );
+            for layer in conclusion_layers(&finding.conclusion).iter().chain(solution_layers) {
+                assert!(results.exclude_results.iter().chain(&results.isolation_results).any(
+                    |(candidate, _)| candidate == layer
+                ), "Unknown layer {:?} in {:?}", layer, finding);
+            }
+            if let Conclusion::Inconclusive { failed_trials } = &finding.conclusion {
+                assert!(!failed_trials.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagnose_never_panics() {
+        // All combinations without layers, and with 1 layer
+        for default_mask in 0 .. 16 {
+            for clean_mask in 0 .. 16 {
+                check_diagnosis(&TestResults {
+                    default_result: from_mask(default_mask),
+                    clean_result: from_mask(clean_mask),
+                    exclude_results: vec![],
+                    isolation_results: vec![],
+                    skipped_trials: vec![]
+                });
+                for exclude_mask in 0 .. 16 {
+                    for isolation_mask in 0 .. 16 {
+                        check_diagnosis(&TestResults {
+                            default_result: from_mask(default_mask),
+                            clean_result: from_mask(clean_mask),
+                            exclude_results: vec![(id("layer"), from_mask(exclude_mask))],
+                            isolation_results: vec![(id("layer"), from_mask(isolation_mask))],
+                            skipped_trials: vec![]
+                        });
+                    }
+                }
+            }
+        }
+
+        // Pseudo-random results with up to 4 layers, where the exclude and isolation trials don't
+        // always cover the same layers
+        let mut seed: u32 = 12345;
+        let mut next = |bound: u32| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) % bound
+        };
+        for _ in 0 .. 20_000 {
+            let mut random_trials = || {
+                let count = next(5);
+                (0 .. count).map(
+                    |index| (id(&format!("layer{}", index)), from_mask(next(16) as u8))
+                ).collect::<Vec<_>>()
+            };
+            let exclude_results = random_trials();
+            let isolation_results = random_trials();
+            check_diagnosis(&TestResults {
+                default_result: from_mask(next(16) as u8),
+                clean_result: from_mask(next(16) as u8),
+                exclude_results,
+                isolation_results,
+                skipped_trials: vec![]
+            });
+        }
+    }
+
+    #[test]
+    fn test_diagnose_broken_layer_and_conflict() {
+        // broken doesn't work at all, and first and second conflict with each other
+        let results = TestResults {
+            default_result: failed_all(),
+            clean_result: succeeded_all(),
+            exclude_results: vec![
+                (id("broken"), failed_all()),
+                (id("first"), failed_all()),
+                (id("second"), failed_all())
+            ],
+            isolation_results: vec![
+                (id("broken"), failed_all()),
+                (id("first"), succeeded_all()),
+                (id("second"), succeeded_all())
+            ],
+            skipped_trials: vec![]
+        };
+        let findings = diagnose(&results);
+        assert_eq!(2, findings.len());
+        assert_eq!(Conclusion::BrokenLayer { layer: id("broken") }, findings[0].conclusion);
+        assert_eq!(vec![Solution::disable(&id("broken"))], findings[0].solutions);
+        assert_eq!(Conclusion::SymmetricConflict { layers: vec![id("first"), id("second")] }, findings[1].conclusion);
+        assert_eq!(vec![Solution::disable(&id("first")), Solution::disable(&id("second"))], findings[1].solutions);
+    }
+
+    #[test]
+    fn test_diagnose_per_version() {
+        // a breaks Vulkan 1.3, whereas the combination of a and b breaks Vulkan 1.0
+        let results = TestResults {
+            default_result: from_mask(0b0110),
+            clean_result: succeeded_all(),
+            exclude_results: vec![(id("a"), succeeded_all()), (id("b"), from_mask(0b0111))],
+            isolation_results: vec![(id("a"), from_mask(0b0111)), (id("b"), succeeded_all())],
+            skipped_trials: vec![]
+        };
+        let per_version = diagnose_per_version(&results);
+        let conclusions: Vec<(u32, Vec<Conclusion>)> = per_version.iter().map(|(api_version, findings)| (
+            *api_version, findings.iter().map(|finding| finding.conclusion.clone()).collect()
+        )).collect();
+        assert_eq!(vec![
+            (vk::API_VERSION_1_0, vec![Conclusion::SymmetricConflict { layers: vec![id("a"), id("b")] }]),
+            (vk::API_VERSION_1_1, vec![Conclusion::Healthy]),
+            (vk::API_VERSION_1_2, vec![Conclusion::Healthy]),
+            (vk::API_VERSION_1_3, vec![Conclusion::BrokenLayer { layer: id("a") }])
+        ], conclusions);
+        assert_eq!(vec![TraceStep::Note("all trials succeeded".to_string())], per_version[1].1[0].trace);
+    }
+
+    #[test]
+    fn test_diagnose_2_broken_layers() {
+        let results = TestResults {
+            default_result: failed_all(),
+            clean_result: succeeded_all(),
+            exclude_results: vec![(id("first"), failed_all()), (id("second"), failed_all())],
+            isolation_results: vec![(id("first"), failed_all()), (id("second"), without_vk12_support())],
+            skipped_trials: vec![]
+        };
+        assert_eq!(vec![
+            Conclusion::BrokenLayer { layer: id("first") },
+            Conclusion::PartiallyBrokenLayer { layer: id("second"), broken_versions: vec![vk::API_VERSION_1_2] }
+        ], diagnose(&results).into_iter().map(|finding| finding.conclusion).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_diagnose_trace_partially_broken_layer() {
+        let results = TestResults {
+            default_result: without_vk12_support(),
+            clean_result: succeeded_all(),
+            exclude_results: vec![(id("layer1"), succeeded_all())],
+            isolation_results: vec![(id("layer1"), without_vk12_support())],
+            skipped_trials: vec![]
+        };
+        let without_vk12 = vec![vk::API_VERSION_1_0, vk::API_VERSION_1_1, vk::API_VERSION_1_3];
+        assert_eq!(vec![
+            TraceStep::Trial {
+                trial: TrialKind::Default, succeeded_versions: without_vk12.clone(), failed_versions: vec![vk::API_VERSION_1_2]
+            },
+            TraceStep::Trial { trial: TrialKind::Clean, succeeded_versions: ALL_API_VERSIONS.to_vec(), failed_versions: vec![] },
+            TraceStep::Trial {
+                trial: TrialKind::Isolation(id("layer1")), succeeded_versions: without_vk12, failed_versions: vec![vk::API_VERSION_1_2]
+            }
+        ], diagnose(&results)[0].trace);
+    }
+
     #[test]
     fn test_draw_conclusion_symmetric_conflict_2_layers() {
         let results = TestResults {
             default_result: failed_all(),
             clean_result: succeeded_all(),
             exclude_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::SymmetricConflict {
-            layers: vec!["layer1".to_string(), "layer2".to_string()]
+            layers: vec![id("layer1"), id("layer2")]
         }, draw_conclusion(&results));
     }
 
@@ -449,18 +1176,19 @@ mod tests {
             default_result: failed_all(),
             clean_result: succeeded_all(),
             exclude_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all()),
-                ("layer3".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all()),
+                (id("layer3"), succeeded_all())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all()),
-                ("layer3".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all()),
+                (id("layer3"), succeeded_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::SymmetricConflict {
-            layers: vec!["layer1".to_string(), "layer2".to_string(), "layer3".to_string()]
+            layers: vec![id("layer1"), id("layer2"), id("layer3")]
         }, draw_conclusion(&results));
     }
 
@@ -470,18 +1198,19 @@ mod tests {
             default_result: without_vk12_support(),
             clean_result: succeeded_all(),
             exclude_results: vec![
-                ("layer1".to_string(), without_vk12_support()),
-                ("layer2".to_string(), succeeded_all()),
-                ("layer3".to_string(), succeeded_all())
+                (id("layer1"), without_vk12_support()),
+                (id("layer2"), succeeded_all()),
+                (id("layer3"), succeeded_all())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all()),
-                ("layer3".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all()),
+                (id("layer3"), succeeded_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::SymmetricConflict {
-            layers: vec!["layer2".to_string(), "layer3".to_string()]
+            layers: vec![id("layer2"), id("layer3")]
         }, draw_conclusion(&results));
     }
 
@@ -492,18 +1221,19 @@ mod tests {
             default_result: without_vk12_support(),
             clean_result: succeeded_all(),
             exclude_results: vec![
-                ("layer1".to_string(), without_vk12_support()),
-                ("layer2".to_string(), succeeded_all()),
-                ("layer3".to_string(), without_vk12_support())
+                (id("layer1"), without_vk12_support()),
+                (id("layer2"), succeeded_all()),
+                (id("layer3"), without_vk12_support())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all()),
-                ("layer3".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all()),
+                (id("layer3"), succeeded_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::AsymmetricConflict {
-            main_offender: "layer2".to_string()
+            main_offender: id("layer2")
         }, draw_conclusion(&results));
     }
 
@@ -514,20 +1244,21 @@ mod tests {
             default_result: failed_all(),
             clean_result: succeeded_all(),
             exclude_results: vec![
-                ("layer1".to_string(), failed_all()),
-                ("layer3".to_string(), failed_all()),
-                ("layer4".to_string(), failed_all()),
-                ("layer2".to_string(), succeeded_all())
+                (id("layer1"), failed_all()),
+                (id("layer3"), failed_all()),
+                (id("layer4"), failed_all()),
+                (id("layer2"), succeeded_all())
             ],
             isolation_results: vec![
-                ("layer4".to_string(), succeeded_all()),
-                ("layer3".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all()),
-                ("layer1".to_string(), succeeded_all())
+                (id("layer4"), succeeded_all()),
+                (id("layer3"), succeeded_all()),
+                (id("layer2"), succeeded_all()),
+                (id("layer1"), succeeded_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::AsymmetricConflict {
-            main_offender: "layer2".to_string()
+            main_offender: id("layer2")
         }, draw_conclusion(&results));
     }
 
@@ -537,15 +1268,16 @@ mod tests {
             default_result: without_vk12_support(),
             clean_result: succeeded_all(),
             exclude_results: vec![
-                ("layer1".to_string(), without_vk12_support()),
-                ("layer2".to_string(), without_vk12_support()),
-                ("layer3".to_string(), without_vk12_support())
+                (id("layer1"), without_vk12_support()),
+                (id("layer2"), without_vk12_support()),
+                (id("layer3"), without_vk12_support())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all()),
-                ("layer3".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all()),
+                (id("layer3"), succeeded_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::ComplexConflict, draw_conclusion(&results));
     }
@@ -556,17 +1288,18 @@ mod tests {
             default_result: failed_all(),
             clean_result: succeeded_all(),
             exclude_results: vec![
-                ("layer1".to_string(), failed_all()),
-                ("layer3".to_string(), failed_all()),
-                ("layer4".to_string(), failed_all()),
-                ("layer2".to_string(), failed_all())
+                (id("layer1"), failed_all()),
+                (id("layer3"), failed_all()),
+                (id("layer4"), failed_all()),
+                (id("layer2"), failed_all())
             ],
             isolation_results: vec![
-                ("layer1".to_string(), succeeded_all()),
-                ("layer2".to_string(), succeeded_all()),
-                ("layer3".to_string(), succeeded_all()),
-                ("layer4".to_string(), succeeded_all())
+                (id("layer1"), succeeded_all()),
+                (id("layer2"), succeeded_all()),
+                (id("layer3"), succeeded_all()),
+                (id("layer4"), succeeded_all())
             ],
+            skipped_trials: vec![]
         };
         assert_eq!(Conclusion::ComplexConflict, draw_conclusion(&results));
     }
@@ -1,11 +1,39 @@
 #![windows_subsystem = "windows"]
 
+mod categories;
+mod config;
+mod console;
 mod definitions;
+mod drivers;
+mod duplicates;
+mod explicit_layers;
+mod extensions;
 mod gui;
+mod known_issues;
+mod launcher;
+mod layer_state;
+#[cfg(any(target_os = "linux", test))]
+mod linux_environment;
+mod loader_settings;
 mod logic;
+mod meta_layers;
+mod overhead;
+mod preflight;
+mod profiles;
 mod registry;
+mod report;
+mod sha256;
+mod shortcuts;
+mod solver;
 mod test_app;
 
+use console::attach_parent_console;
+use known_issues::maybe_run_known_issues_command;
+use launcher::maybe_run_launcher;
+use loader_settings::maybe_run_loader_settings_command;
+use profiles::maybe_run_profile_command;
+use report::maybe_run_diagnose_command;
+use shortcuts::maybe_print_shortcuts;
 use test_app::maybe_run_test_app;
 use nwg::NativeUi;
 use std::cell::RefCell;
@@ -14,6 +42,14 @@ use gui::*;
 
 fn main() {
     maybe_run_test_app();
+    // The test app reports to vk-fixer via its exit code and piped output, so it doesn't need this
+    attach_parent_console();
+    maybe_run_launcher();
+    maybe_run_profile_command();
+    maybe_print_shortcuts();
+    maybe_run_loader_settings_command();
+    maybe_run_diagnose_command();
+    maybe_run_known_issues_command();
 
     nwg::init().expect("Failed to init Native Windows GUI");
 
@@ -51,22 +87,27 @@ fn main() {
             nwg::dispatch_thread_events();
         }
 
-        if let GuiState::AutoResultsTable(results, layers) = &cloned_state {
+        if let GuiState::AutoResultsTable(results, layers, disable_method, fix, preflight_findings) = &cloned_state {
             let _ui = AutoResultsApp::build_ui(AutoResultsApp {
                 state: Rc::clone(&state),
                 layers: layers.clone(),
                 results: results.clone(),
+                disable_method: *disable_method,
+                fix: fix.clone(),
+                preflight_findings: preflight_findings.clone(),
                 ..Default::default()
             }).expect("Failed to build UI");
             nwg::dispatch_thread_events();
         }
 
-        if let GuiState::AutoConclusion(conclusion, layers, show_break_buttons) = &cloned_state {
+        if let GuiState::AutoConclusion(findings, layers, show_break_buttons, disable_method, fix) = &cloned_state {
             let _ui = AutoConclusionApp::build_ui(AutoConclusionApp {
                 state: Rc::clone(&state),
-                conclusion: conclusion.clone(),
+                findings: findings.clone(),
                 layers: layers.clone(),
                 show_break_buttons: *show_break_buttons,
+                disable_method: *disable_method,
+                fix: fix.clone(),
                 ..Default::default()
             }).expect("Failed to build UI");
             nwg::dispatch_thread_events();
@@ -81,6 +122,14 @@ fn main() {
             nwg::dispatch_thread_events();
         }
 
+        if cloned_state == GuiState::Profiles {
+            let _ui = ProfilesApp::build_ui(ProfilesApp {
+                state: Rc::clone(&state),
+                ..Default::default()
+            }).expect("Failed to build UI");
+            nwg::dispatch_thread_events();
+        }
+
         if let GuiState::AutoFailed(error, did_break) = &cloned_state {
             let _ui = AutoFailedApp::build_ui(AutoFailedApp {
                 state: Rc::clone(&state),
@@ -0,0 +1,121 @@
+use crate::definitions::*;
+
+fn contains_name(names: &[String], layer: &ImplicitLayer) -> bool {
+    names.iter().any(|name| name.eq_ignore_ascii_case(&layer.name))
+}
+
+/// Finds the implicit layers that are components of `meta_layer`
+pub fn component_layers<'a>(meta_layer: &ImplicitLayer, layers: &'a [ImplicitLayer]) -> Vec<&'a ImplicitLayer> {
+    match &meta_layer.meta_layer {
+        Some(meta) => layers.iter().filter(|layer| contains_name(&meta.component_layers, layer)).collect(),
+        None => vec![]
+    }
+}
+
+/// Finds the meta-layers that list `layer` as one of their components
+pub fn meta_layers_containing<'a>(layer: &ImplicitLayer, layers: &'a [ImplicitLayer]) -> Vec<&'a ImplicitLayer> {
+    layers.iter().filter(|candidate| candidate.meta_layer.as_ref().is_some_and(
+        |meta| contains_name(&meta.component_layers, layer)
+    )).collect()
+}
+
+/// Adds the meta-layers that would re-enable any of the `disabled` layers. Disabling the
+/// `disable_environment` of a component is useless while its meta-layer is still enabled, since
+/// the loader would pull it in anyway. Since meta-layers can contain other meta-layers, this is
+/// repeated until no more meta-layers need to be added.
+pub fn include_meta_layers<'a>(
+    mut disabled: Vec<&'a ImplicitLayer>, layers: &'a [ImplicitLayer]
+) -> Vec<&'a ImplicitLayer> {
+    let mut index = 0;
+    while index < disabled.len() {
+        for meta_layer in meta_layers_containing(disabled[index], layers) {
+            if !disabled.contains(&meta_layer) {
+                disabled.push(meta_layer);
+            }
+        }
+        index += 1;
+    }
+    disabled
+}
+
+/// Adds the component layers that any of the `enabled` meta-layers would pull in, including the
+/// components of nested meta-layers
+pub fn include_component_layers<'a>(
+    mut enabled: Vec<&'a ImplicitLayer>, layers: &'a [ImplicitLayer]
+) -> Vec<&'a ImplicitLayer> {
+    let mut index = 0;
+    while index < enabled.len() {
+        for component in component_layers(enabled[index], layers) {
+            if !enabled.contains(&component) {
+                enabled.push(component);
+            }
+        }
+        index += 1;
+    }
+    enabled
+}
+
+/// Describes the components and blacklisted layers of the given meta-layer, or returns `None` when
+/// the layer is not a meta-layer
+pub fn describe_meta_layer(layer: &ImplicitLayer) -> Option<String> {
+    let meta = layer.meta_layer.as_ref()?;
+    let mut description = format!("meta-layer that enables {}", if meta.component_layers.is_empty() {
+        "no other layers".to_string()
+    } else { meta.component_layers.join(", ") });
+    if !meta.blacklisted_layers.is_empty() {
+        description += &format!(" and disables {}", meta.blacklisted_layers.join(", "));
+    }
+    Some(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str, components: &[&str]) -> ImplicitLayer {
+        ImplicitLayer {
+            meta_layer: if components.is_empty() { None } else { Some(MetaLayer {
+                component_layers: components.iter().map(|name| name.to_string()).collect(),
+                blacklisted_layers: vec![]
+            }) },
+            ..ImplicitLayer::test(name)
+        }
+    }
+
+    #[test]
+    fn test_components() {
+        let layers = [
+            layer("VK_LAYER_a", &[]),
+            layer("VK_LAYER_b", &[]),
+            layer("VK_LAYER_meta", &["vk_layer_a", "VK_LAYER_missing"])
+        ];
+        assert_eq!(vec![&layers[0]], component_layers(&layers[2], &layers));
+        assert!(component_layers(&layers[0], &layers).is_empty());
+        assert_eq!(vec![&layers[2]], meta_layers_containing(&layers[0], &layers));
+        assert!(meta_layers_containing(&layers[1], &layers).is_empty());
+        assert_eq!(
+            Some("meta-layer that enables vk_layer_a, VK_LAYER_missing".to_string()),
+            describe_meta_layer(&layers[2])
+        );
+    }
+
+    #[test]
+    fn test_include_nested_meta_layers() {
+        let layers = [
+            layer("VK_LAYER_a", &[]),
+            layer("VK_LAYER_b", &[]),
+            layer("VK_LAYER_inner", &["VK_LAYER_a"]),
+            layer("VK_LAYER_outer", &["VK_LAYER_inner"])
+        ];
+        assert_eq!(
+            vec![&layers[0], &layers[2], &layers[3]],
+            include_meta_layers(vec![&layers[0]], &layers)
+        );
+        assert_eq!(vec![&layers[1]], include_meta_layers(vec![&layers[1]], &layers));
+        assert_eq!(
+            vec![&layers[3], &layers[2], &layers[0]],
+            include_component_layers(vec![&layers[3]], &layers)
+        );
+        assert_eq!(vec![&layers[1]], include_component_layers(vec![&layers[1]], &layers));
+    }
+}
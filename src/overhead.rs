@@ -0,0 +1,107 @@
+use crate::definitions::*;
+
+/// How much slower instance and device creation become when only one layer is enabled, compared to
+/// the trials without any layers
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LayerOverhead {
+    pub layer: LayerId,
+    /// The overhead in microseconds for Vulkan 1.0, 1.1, 1.2, and 1.3. It is `None` when the
+    /// isolation trial or the clean trial of that version failed. Due to measurement noise, it
+    /// can be slightly negative.
+    pub overhead_micros: [Option<i64>; 4]
+}
+
+impl LayerOverhead {
+    /// The largest overhead of all API versions
+    pub fn max_micros(&self) -> Option<i64> {
+        self.overhead_micros.iter().flatten().copied().max()
+    }
+
+    pub fn exceeds(&self, threshold_ms: u64) -> bool {
+        self.max_micros().is_some_and(|overhead| overhead > 1000 * threshold_ms as i64)
+    }
+}
+
+fn versions(results: &VersionedTrialResults) -> [&TrialResult; 4] {
+    [&results.vk10, &results.vk11, &results.vk12, &results.vk13]
+}
+
+/// Computes the overhead of each layer by comparing its isolation trial with the clean trial
+pub fn compute_overheads(results: &TestResults) -> Vec<LayerOverhead> {
+    let clean_results = versions(&results.clean_result);
+    results.isolation_results.iter().map(|(layer, isolation_results)| {
+        let mut overhead_micros = [None; 4];
+        for (index, isolation_result) in versions(isolation_results).into_iter().enumerate() {
+            let clean_result = clean_results[index];
+            if isolation_result.exit_code != 0 || clean_result.exit_code != 0 {
+                continue;
+            }
+            if let (Some(isolation_time), Some(clean_time)) = (
+                isolation_result.timings.creation_time(), clean_result.timings.creation_time()
+            ) {
+                overhead_micros[index] = Some(isolation_time.as_micros() as i64 - clean_time.as_micros() as i64);
+            }
+        }
+        LayerOverhead { layer: layer.clone(), overhead_micros }
+    }).collect()
+}
+
+/// Formats an overhead in microseconds as milliseconds, like "+12.3 ms"
+pub fn format_overhead(overhead_micros: Option<i64>) -> String {
+    match overhead_micros {
+        Some(micros) => format!("{:+.1} ms", micros as f64 / 1000.0),
+        None => "?".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    fn trial(exit_code: i32, instance_ms: u64, device_ms: u64) -> TrialResult {
+        TrialResult {
+            exit_code,
+            output: "".to_string(),
+            timings: TrialTimings {
+                load_loader: Some(Duration::from_millis(1)),
+                create_instance: Some(Duration::from_millis(instance_ms)),
+                create_device: Some(Duration::from_millis(device_ms))
+            },
+            capabilities: TrialCapabilities::default()
+        }
+    }
+
+    fn results(vk10: TrialResult, vk11: TrialResult, vk12: TrialResult, vk13: TrialResult) -> VersionedTrialResults {
+        VersionedTrialResults { vk10, vk11, vk12, vk13 }
+    }
+
+    #[test]
+    fn test_compute_overheads() {
+        let layer = LayerId {
+            registry: ImplicitRegistry::CurrentUser,
+            settings_path: "slow.json".to_string(),
+            manifest_index: 0
+        };
+        let test_results = TestResults {
+            default_result: results(trial(0, 50, 10), trial(0, 50, 10), trial(0, 50, 10), trial(0, 50, 10)),
+            clean_result: results(trial(0, 5, 10), trial(0, 5, 10), trial(1, 5, 10), trial(0, 6, 10)),
+            exclude_results: vec![],
+            isolation_results: vec![(layer.clone(), results(
+                trial(0, 45, 10), trial(1, 45, 10), trial(0, 45, 10), trial(0, 5, 10)
+            ))],
+            skipped_trials: vec![]
+        };
+
+        let overheads = compute_overheads(&test_results);
+        assert_eq!(vec![LayerOverhead {
+            layer, overhead_micros: [Some(40_000), None, None, Some(-1000)]
+        }], overheads);
+        assert_eq!(Some(40_000), overheads[0].max_micros());
+        assert!(overheads[0].exceeds(20));
+        assert!(!overheads[0].exceeds(40));
+        assert_eq!("+40.0 ms", format_overhead(overheads[0].overhead_micros[0]));
+        assert_eq!("-1.0 ms", format_overhead(overheads[0].overhead_micros[3]));
+        assert_eq!("?", format_overhead(None));
+    }
+}
@@ -0,0 +1,273 @@
+use std::fs::File;
+use std::io::Read;
+use crate::definitions::*;
+use crate::drivers::{resolve_library_path, DriverManifest};
+
+/// Parses an `api_version` like "1.3.250" into its major and minor version
+fn parse_api_version(api_version: &str) -> Option<(u32, u32)> {
+    let mut parts = api_version.trim().split('.');
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// Reads the architecture (32 or 64 bits) from the header of a PE (Windows) or ELF (Linux)
+/// library, or returns `None` when the header is not recognized
+fn library_bits(header: &[u8]) -> Option<u32> {
+    if header.starts_with(b"\x7fELF") {
+        return match header.get(4)? {
+            1 => Some(32),
+            2 => Some(64),
+            _ => None
+        };
+    }
+    if header.starts_with(b"MZ") {
+        let pe_offset = u32::from_le_bytes(header.get(0x3c .. 0x40)?.try_into().ok()?) as usize;
+        if header.get(pe_offset .. pe_offset + 4)? != b"PE\0\0" {
+            return None;
+        }
+        return match u16::from_le_bytes(header.get(pe_offset + 4 .. pe_offset + 6)?.try_into().ok()?) {
+            // x86 and ARM
+            0x014c | 0x01c4 => Some(32),
+            // x64 and ARM64
+            0x8664 | 0xaa64 => Some(64),
+            _ => None
+        };
+    }
+    None
+}
+
+/// Checks whether the library of the layer exists, and whether it has `expected_bits`. Bare file
+/// names are found by the library search path of the system, so they can't be checked.
+fn library_problem(layer: &ImplicitLayer, expected_bits: u32) -> Option<LayerProblem> {
+    let library = resolve_library_path(&layer.settings_path, layer.library_path.as_ref()?)?;
+    let mut header = Vec::new();
+    let read_result = File::open(&library).and_then(|file| file.take(4096).read_to_end(&mut header));
+    if read_result.is_err() {
+        return Some(LayerProblem::MissingLibrary { library: library.display().to_string() });
+    }
+    match library_bits(&header) {
+        Some(library_bits) if library_bits != expected_bits => Some(LayerProblem::WrongArchitecture {
+            library: library.display().to_string(), library_bits, expected_bits
+        }),
+        _ => None
+    }
+}
+
+/// Whether no environment variable can ever satisfy the `enable_environment` of the layer: when
+/// the key is empty or contains `=`, or when it is the `disable_environment` as well
+fn unsatisfiable_enable_environment(layer: &ImplicitLayer) -> Option<LayerProblem> {
+    let (key, value) = layer.enable_environment.as_ref()?;
+    if key.is_empty() || key.contains('=') || key.eq_ignore_ascii_case(&layer.disable_environment) {
+        Some(LayerProblem::UnsatisfiableEnableEnvironment { key: key.clone(), value: value.clone() })
+    } else { None }
+}
+
+fn finding(layer: &ImplicitLayer, problem: LayerProblem, note: String) -> Finding {
+    let solutions = match &problem {
+        LayerProblem::DuplicateName { .. } | LayerProblem::UnsatisfiableEnableEnvironment { .. } => vec![],
+        _ => vec![Solution::disable(&layer.id())]
+    };
+    Finding {
+        conclusion: Conclusion::MisconfiguredLayer { layer: layer.id(), problem },
+        trace: vec![TraceStep::Note(note)],
+        solutions
+    }
+}
+
+/// Checks the manifests of the layers without running any trial. `states` are the effective
+/// states of the `layers`: only the libraries and API versions of enabled layers are checked,
+/// since the loader ignores the other layers anyway. The newest `api_version` of the `drivers` is
+/// compared to the `api_version` of each layer.
+pub fn preflight_check(layers: &[ImplicitLayer], states: &[EffectiveState], drivers: &[DriverManifest]) -> Vec<Finding> {
+    let newest_driver = drivers.iter().filter(|driver| driver.problems.is_empty()).filter_map(
+        |driver| Some((parse_api_version(driver.api_version.as_ref()?)?, driver))
+    ).max_by_key(|(version, _)| *version);
+
+    let mut findings = Vec::new();
+    for (layer, state) in layers.iter().zip(states) {
+        if layer.registry_value == 0 {
+            if let Some(problem) = unsatisfiable_enable_environment(layer) {
+                let note = format!(
+                    "{} is only enabled when {} is set, but that can never happen", layer.name,
+                    layer.enable_environment.as_ref().map_or(String::new(), |(key, value)| format!("{}={}", key, value))
+                );
+                findings.push(finding(layer, problem, note));
+            }
+        }
+        if !state.enabled {
+            continue;
+        }
+
+        let ignored_manifests: Vec<String> = layers.iter().filter(
+            |other| other.shadowed_by.as_ref() == Some(&layer.settings_path) && other.name.eq_ignore_ascii_case(&layer.name)
+        ).map(|other| other.settings_path.clone()).collect();
+        if !ignored_manifests.is_empty() {
+            let note = format!(
+                "the loader uses {} for {}, and ignores {}", layer.settings_path, layer.name, ignored_manifests.join(", ")
+            );
+            findings.push(finding(layer, LayerProblem::DuplicateName { ignored_manifests }, note));
+        }
+
+        if layer.meta_layer.is_none() {
+            if let Some(problem) = library_problem(layer, usize::BITS) {
+                let note = match &problem {
+                    LayerProblem::WrongArchitecture { library, library_bits, expected_bits } => format!(
+                        "{} is a {}-bit library, but the {}-bit loader uses {}", library, library_bits, expected_bits, layer.settings_path
+                    ),
+                    _ => format!("{} points to a library that doesn't exist", layer.settings_path)
+                };
+                findings.push(finding(layer, problem, note));
+            }
+        }
+
+        if let (Some(api_version), Some((driver_version, driver))) = (&layer.api_version, &newest_driver) {
+            if parse_api_version(api_version).is_some_and(|layer_version| layer_version < *driver_version) {
+                let driver_api_version = driver.api_version.clone().unwrap_or_default();
+                let note = format!(
+                    "the api_version of {} is {}, but the driver {} supports Vulkan {}",
+                    layer.name, api_version, driver.manifest_path, driver_api_version
+                );
+                findings.push(finding(layer, LayerProblem::OutdatedApiVersion {
+                    api_version: api_version.clone(), driver_version: driver_api_version
+                }, note));
+            }
+        }
+    }
+    findings
+}
+
+/// Whether the pre-flight findings already answer the question, so the trials can be skipped:
+/// this is the case when every tested layer has a problem that certainly stops the loader from
+/// using it
+pub fn preflight_is_conclusive(findings: &[Finding], tested_layers: &[ImplicitLayer]) -> bool {
+    !tested_layers.is_empty() && tested_layers.iter().all(|layer| findings.iter().any(|finding| matches!(
+        &finding.conclusion, Conclusion::MisconfiguredLayer { layer: problem_layer, problem }
+        if problem_layer == &layer.id() && problem.is_certain()
+    )))
+}
+
+/// Puts the pre-flight findings before the findings of the trials. When the pre-flight check
+/// found anything, a `Healthy` conclusion of the trials is left out, since the computer is not
+/// entirely healthy after all.
+pub fn combine_findings(preflight_findings: Vec<Finding>, trial_findings: Vec<Finding>) -> Vec<Finding> {
+    let has_preflight_findings = !preflight_findings.is_empty();
+    preflight_findings.into_iter().chain(trial_findings.into_iter().filter(
+        |finding| !has_preflight_findings || finding.conclusion != Conclusion::Healthy
+    )).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::{validate_driver_manifest, DriverSource};
+    use std::fs;
+
+    fn layer(name: &str, settings_path: &str) -> ImplicitLayer {
+        ImplicitLayer {
+            settings_path: settings_path.to_string(),
+            registry: ImplicitRegistry::LocalMachine,
+            ..ImplicitLayer::test(name)
+        }
+    }
+
+    fn enabled() -> EffectiveState {
+        EffectiveState { enabled: true, reason: StateReason::EnabledByDefault }
+    }
+
+    fn problems(findings: &[Finding]) -> Vec<(String, LayerProblem)> {
+        findings.iter().map(|finding| match &finding.conclusion {
+            Conclusion::MisconfiguredLayer { layer, problem } => (layer.settings_path.clone(), problem.clone()),
+            other => panic!("Unexpected conclusion {:?}", other)
+        }).collect()
+    }
+
+    #[test]
+    fn test_library_bits() {
+        let mut pe = vec![0u8; 0x90];
+        pe[0 .. 2].copy_from_slice(b"MZ");
+        pe[0x3c] = 0x80;
+        pe[0x80 .. 0x84].copy_from_slice(b"PE\0\0");
+        pe[0x84 .. 0x86].copy_from_slice(&0x014cu16.to_le_bytes());
+        assert_eq!(Some(32), library_bits(&pe));
+        pe[0x84 .. 0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        assert_eq!(Some(64), library_bits(&pe));
+        assert_eq!(None, library_bits(&pe[.. 0x82]));
+        assert_eq!(Some(64), library_bits(b"\x7fELF\x02\x01\x01"));
+        assert_eq!(Some(32), library_bits(b"\x7fELF\x01\x01\x01"));
+        assert_eq!(None, library_bits(b"not a library"));
+    }
+
+    #[test]
+    fn test_preflight_check() {
+        let directory = std::env::temp_dir().join(format!("vk-fixer-preflight-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = |file: &str| directory.join(file).to_str().unwrap().to_string();
+        let other_bits: u8 = if usize::BITS == 64 { 1 } else { 2 };
+        fs::write(directory.join("wrong.so"), [0x7f, b'E', b'L', b'F', other_bits]).unwrap();
+        fs::write(directory.join("fine.dll"), "whatever").unwrap();
+
+        let mut missing = layer("VK_LAYER_missing", &path("missing.json"));
+        missing.library_path = Some("./missing.dll".to_string());
+        let mut wrong = layer("VK_LAYER_wrong", &path("wrong.json"));
+        wrong.library_path = Some("./wrong.so".to_string());
+        let mut outdated = layer("VK_LAYER_outdated", &path("outdated.json"));
+        outdated.library_path = Some("./fine.dll".to_string());
+        outdated.api_version = Some("1.1.70".to_string());
+        let mut duplicate = layer("VK_LAYER_OUTDATED", &path("old/outdated.json"));
+        duplicate.shadowed_by = Some(outdated.settings_path.clone());
+        let mut unsatisfiable = layer("VK_LAYER_never", &path("never.json"));
+        unsatisfiable.enable_environment = Some(("DISABLE_VK_LAYER_never".to_string(), "1".to_string()));
+        let mut inactive = layer("VK_LAYER_inactive", &path("inactive.json"));
+        inactive.library_path = Some("./missing.dll".to_string());
+        let healthy = layer("VK_LAYER_healthy", &path("healthy.json"));
+
+        let layers = [missing, wrong, outdated, duplicate, unsatisfiable, inactive, healthy];
+        let disabled = EffectiveState { enabled: false, reason: StateReason::RegistryValue(1) };
+        let states = [enabled(), enabled(), enabled(), disabled.clone(), disabled.clone(), disabled, enabled()];
+        let drivers = [validate_driver_manifest("driver.json", DriverSource::Registry, Some(
+            r#"{ "ICD": { "library_path": "driver.dll", "api_version": "1.3.277" } }"#
+        ))];
+        let findings = preflight_check(&layers, &states, &drivers);
+        assert_eq!(vec![
+            (path("missing.json"), LayerProblem::MissingLibrary {
+                library: directory.join("./missing.dll").display().to_string()
+            }),
+            (path("wrong.json"), LayerProblem::WrongArchitecture {
+                library: directory.join("./wrong.so").display().to_string(),
+                library_bits: if usize::BITS == 64 { 32 } else { 64 }, expected_bits: usize::BITS
+            }),
+            (path("outdated.json"), LayerProblem::DuplicateName { ignored_manifests: vec![path("old/outdated.json")] }),
+            (path("outdated.json"), LayerProblem::OutdatedApiVersion {
+                api_version: "1.1.70".to_string(), driver_version: "1.3.277".to_string()
+            }),
+            (path("never.json"), LayerProblem::UnsatisfiableEnableEnvironment {
+                key: "DISABLE_VK_LAYER_never".to_string(), value: "1".to_string()
+            })
+        ], problems(&findings));
+        assert_eq!(vec![Solution::disable(&layers[0].id())], findings[0].solutions);
+        assert!(findings[2].solutions.is_empty());
+        assert_eq!(
+            vec![TraceStep::Note(format!("{} points to a library that doesn't exist", path("missing.json")))],
+            findings[0].trace
+        );
+
+        assert!(!preflight_is_conclusive(&findings, &layers[0 .. 3]));
+        assert!(preflight_is_conclusive(&findings, &layers[0 .. 2]));
+        assert!(!preflight_is_conclusive(&findings, &[]));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_combine_findings() {
+        let healthy = Finding::default();
+        let broken = Finding { conclusion: Conclusion::ComplexConflict, ..Default::default() };
+        let misconfigured = finding(&layer("VK_LAYER_a", "a.json"), LayerProblem::MissingLibrary {
+            library: "a.dll".to_string()
+        }, "a.json points to a library that doesn't exist".to_string());
+        assert_eq!(vec![healthy.clone()], combine_findings(vec![], vec![healthy.clone()]));
+        assert_eq!(vec![misconfigured.clone()], combine_findings(vec![misconfigured.clone()], vec![healthy]));
+        assert_eq!(
+            vec![misconfigured.clone(), broken.clone()],
+            combine_findings(vec![misconfigured], vec![broken])
+        );
+    }
+}
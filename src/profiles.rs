@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+use std::env::args;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+use std::process::exit;
+use crate::config::config_directory;
+use crate::definitions::DisableMethod;
+
+const PROFILE_USAGE: &str = "Usage:
+vk-fixer profile list
+vk-fixer profile save <name> --game <executable or id> [--loader-filter] --disable <layer>...
+vk-fixer profile remove <name>";
+
+/// A set of implicit layers that should be disabled whenever a specific game is launched
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// The executable (path or file name) or (Steam) ID of the game
+    pub game: String,
+    pub disabled_layers: Vec<String>,
+    #[serde(default)]
+    pub method: DisableMethod
+}
+
+impl Profile {
+    /// Checks whether this profile belongs to the given game executable or ID. Executables match
+    /// when their file names are equal, so profiles keep working when the game is moved.
+    pub fn matches_game(&self, game: &str) -> bool {
+        let file_name = |path: &str| path.rsplit(['/', '\\']).next().unwrap_or(path).to_ascii_lowercase();
+        self.game.eq_ignore_ascii_case(game) || file_name(&self.game) == file_name(game)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProfileFile {
+    profiles: Vec<Profile>
+}
+
+/// Stores the profiles in a JSON config file
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ProfileStore {
+    pub path: PathBuf
+}
+
+impl ProfileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Uses `profiles.json` in the config directory of vk-fixer
+    pub fn default_location() -> Option<Self> {
+        Some(Self::new(config_directory()?.join("profiles.json")))
+    }
+
+    pub fn list(&self) -> Result<Vec<Profile>> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => serde_json::from_str::<ProfileFile>(&content).map(|file| file.profiles).map_err(
+                |parse_error| Error::new(ErrorKind::InvalidData, format!(
+                    "Failed to parse {}: {}", self.path.display(), parse_error
+                ))
+            ),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(vec![]),
+            Err(error) => Err(error)
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Profile>> {
+        Ok(self.list()?.into_iter().find(|profile| profile.name == name))
+    }
+
+    pub fn find_for_game(&self, game: &str) -> Result<Option<Profile>> {
+        Ok(self.list()?.into_iter().find(|profile| profile.matches_game(game)))
+    }
+
+    /// Adds the given profile, or replaces the existing profile with the same name
+    pub fn save(&self, profile: Profile) -> Result<()> {
+        let mut profiles = self.list()?;
+        if let Some(existing) = profiles.iter_mut().find(|existing| existing.name == profile.name) {
+            *existing = profile;
+        } else {
+            profiles.push(profile);
+        }
+        self.write(profiles)
+    }
+
+    /// Removes the profile with the given name, and returns whether it existed
+    pub fn remove(&self, name: &str) -> Result<bool> {
+        let mut profiles = self.list()?;
+        let old_length = profiles.len();
+        profiles.retain(|profile| profile.name != name);
+        let removed = profiles.len() != old_length;
+        if removed {
+            self.write(profiles)?;
+        }
+        Ok(removed)
+    }
+
+    fn write(&self, profiles: Vec<Profile>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(&ProfileFile { profiles }).map_err(
+            |error| Error::new(ErrorKind::InvalidData, error)
+        )?;
+        fs::write(&self.path, content)
+    }
+}
+
+/// Handles the `vk-fixer profile ...` commands
+pub fn maybe_run_profile_command() {
+    let args = args().collect::<Vec<_>>();
+    if args.len() >= 2 && args[1] == "profile" {
+        let result = ProfileStore::default_location().ok_or(
+            "Can't find the config directory".to_string()
+        ).and_then(|store| run_profile_command(&store, &args[2 ..]));
+        match result {
+            Ok(output) => {
+                print!("{}", output);
+                exit(0);
+            },
+            Err(error) => {
+                eprintln!("{}\n{}", error, PROFILE_USAGE);
+                exit(2);
+            }
+        }
+    }
+}
+
+pub fn run_profile_command(store: &ProfileStore, args: &[String]) -> std::result::Result<String, String> {
+    let command = args.first().map(|command| command.as_str());
+    match command {
+        Some("list") => {
+            let mut output = String::new();
+            for profile in store.list().map_err(|error| error.to_string())? {
+                output += &format!("{}: {} without {}\n", profile.name, profile.game, profile.disabled_layers.join(", "));
+            }
+            Ok(output)
+        },
+        Some("save") => {
+            let profile = parse_profile(&args[1 ..])?;
+            let name = profile.name.clone();
+            store.save(profile).map_err(|error| error.to_string())?;
+            Ok(format!("Saved profile {}\n", name))
+        },
+        Some("remove") => {
+            let name = args.get(1).ok_or("Missing profile name")?;
+            if store.remove(name).map_err(|error| error.to_string())? {
+                Ok(format!("Removed profile {}\n", name))
+            } else {
+                Err(format!("There is no profile named {}", name))
+            }
+        },
+        _ => Err("Unknown profile command".to_string())
+    }
+}
+
+fn parse_profile(args: &[String]) -> std::result::Result<Profile, String> {
+    let name = args.first().filter(|name| !name.starts_with("--")).ok_or("Missing profile name")?;
+    let mut game = None;
+    let mut disabled_layers = Vec::new();
+    let mut method = DisableMethod::DisableEnvironment;
+    let mut parsing_layers = false;
+
+    let mut remaining = args[1 ..].iter();
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--game" => {
+                game = Some(remaining.next().ok_or("Missing game after --game")?.clone());
+                parsing_layers = false;
+            },
+            "--disable" => parsing_layers = true,
+            "--loader-filter" => {
+                method = DisableMethod::LoaderFilter;
+                parsing_layers = false;
+            },
+            layer if parsing_layers && !layer.starts_with("--") => disabled_layers.push(layer.to_string()),
+            unknown => return Err(format!("Unexpected argument {}", unknown))
+        }
+    }
+
+    Ok(Profile {
+        name: name.clone(),
+        game: game.ok_or("Missing --game")?,
+        disabled_layers,
+        method
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_store(name: &str) -> ProfileStore {
+        let directory = std::env::temp_dir().join(format!("vk-fixer-profiles-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        ProfileStore::new(directory.join("profiles.json"))
+    }
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn profile(name: &str, game: &str) -> Profile {
+        Profile {
+            name: name.to_string(),
+            game: game.to_string(),
+            disabled_layers: vec!["VK_LAYER_bad_overlay".to_string()],
+            method: DisableMethod::DisableEnvironment
+        }
+    }
+
+    #[test]
+    fn test_crud() {
+        let store = temporary_store("crud");
+        assert_eq!(Vec::<Profile>::new(), store.list().unwrap());
+
+        store.save(profile("first", "C:\\Games\\First\\first.exe")).unwrap();
+        store.save(profile("second", "1234")).unwrap();
+        assert_eq!(vec![profile("first", "C:\\Games\\First\\first.exe"), profile("second", "1234")], store.list().unwrap());
+
+        let mut updated = profile("first", "first.exe");
+        updated.method = DisableMethod::LoaderFilter;
+        store.save(updated.clone()).unwrap();
+        assert_eq!(Some(updated.clone()), store.get("first").unwrap());
+        assert_eq!(2, store.list().unwrap().len());
+
+        assert!(store.remove("second").unwrap());
+        assert!(!store.remove("second").unwrap());
+        assert_eq!(vec![updated], store.list().unwrap());
+        fs::remove_dir_all(store.path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_find_for_game() {
+        let store = temporary_store("find");
+        store.save(profile("first", "C:\\Games\\First\\First.exe")).unwrap();
+        store.save(profile("second", "1234")).unwrap();
+
+        assert_eq!("first", store.find_for_game("D:/Other/first.exe").unwrap().unwrap().name);
+        assert_eq!("second", store.find_for_game("1234").unwrap().unwrap().name);
+        assert_eq!(None, store.find_for_game("third.exe").unwrap());
+        fs::remove_dir_all(store.path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_profile_commands() {
+        let store = temporary_store("commands");
+        assert_eq!(Ok("Saved profile first\n".to_string()), run_profile_command(&store, &to_args(&[
+            "save", "first", "--game", "first.exe", "--disable", "VK_LAYER_a", "VK_LAYER_b"
+        ])));
+        assert_eq!(
+            Ok("first: first.exe without VK_LAYER_a, VK_LAYER_b\n".to_string()),
+            run_profile_command(&store, &to_args(&["list"]))
+        );
+        assert!(run_profile_command(&store, &to_args(&["save", "second", "--disable", "VK_LAYER_a"])).is_err());
+        assert!(run_profile_command(&store, &to_args(&["remove", "second"])).is_err());
+        assert_eq!(Ok("Removed profile first\n".to_string()), run_profile_command(&store, &to_args(&["remove", "first"])));
+        fs::remove_dir_all(store.path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,10 +1,15 @@
 use crate::definitions::*;
+use crate::drivers::DriverSource;
+use crate::duplicates::mark_shadowed_layers;
+use crate::layer_state::{disable_variables, LOADER_LAYERS_DISABLE};
+use crate::loader_settings::find_loader_settings;
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::env::var;
+use std::env::vars;
 use std::fs::File;
 use std::io::BufReader;
 use std::process::Command;
+use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
 use windows::Win32::System::Registry::*;
 use windows::core::*;
 
@@ -18,8 +23,15 @@ struct RootLayerSettings {
 struct LayerSettings {
     name: String,
     description: String,
+    /// Explicit layers don't have a `disable_environment`
+    #[serde(default)]
     disable_environment: HashMap<String, String>,
-    enable_environment: Option<HashMap<String, String>>
+    enable_environment: Option<HashMap<String, String>>,
+    component_layers: Option<Vec<String>>,
+    blacklisted_layers: Option<Vec<String>>,
+    library_path: Option<String>,
+    implementation_version: Option<String>,
+    api_version: Option<String>
 }
 
 pub fn get_implicit_layers() -> (Vec<ImplicitLayer>, Vec<String>) {
@@ -38,13 +50,16 @@ pub fn get_implicit_layers() -> (Vec<ImplicitLayer>, Vec<String>) {
         errors.push(machine_error.message().to_string());
     }
 
-    ([user_layers.unwrap_or(vec![]), machine_layers.unwrap_or(vec![])].concat(), errors)
+    let mut layers = [user_layers.unwrap_or(vec![]), machine_layers.unwrap_or(vec![])].concat();
+    mark_shadowed_layers(&mut layers);
+    (layers, errors)
 }
 
-fn enumerate_keys_of_hkey(root_hkey: HKEY, path: PCSTR) -> Result<Vec<String>> {
+fn enumerate_values_of_hkey(root_hkey: HKEY, path: PCSTR) -> Result<Vec<(String, Vec<u8>)>> {
     let mut hkey = HKEY::default();
     let mut num_keys = 0;
     let mut longest_key_length = 0;
+    let mut longest_data_length = 0;
 
     unsafe {
         RegOpenKeyExA(
@@ -54,42 +69,105 @@ fn enumerate_keys_of_hkey(root_hkey: HKEY, path: PCSTR) -> Result<Vec<String>> {
             hkey, PSTR::null(), None, None, None,
             None, None, Some(&mut num_keys),
             Some(&mut longest_key_length),
-            None, None, None
+            Some(&mut longest_data_length), None, None
         )?;
 
         let mut result = Vec::with_capacity(num_keys as usize);
 
-        let mut key_holder = String::with_capacity(longest_key_length as usize);
-        let p_key = PSTR(key_holder.as_mut_ptr());
+        let mut key_holder = vec![0u8; longest_key_length as usize + 1];
+        let mut data_holder = vec![0u8; longest_data_length as usize];
 
         for index in 0 ..num_keys {
-            let mut current_key_length = longest_key_length + 1;
+            let mut current_key_length = key_holder.len() as u32;
+            let mut current_data_length = data_holder.len() as u32;
             RegEnumValueA(
-                hkey, index, p_key,
+                hkey, index, PSTR(key_holder.as_mut_ptr()),
                 &mut current_key_length, None,
-                None, None, None
+                None, Some(data_holder.as_mut_ptr()), Some(&mut current_data_length)
             )?;
 
-            result.push(p_key.to_string()?);
+            result.push((
+                String::from_utf8_lossy(&key_holder[.. current_key_length as usize]).to_string(),
+                data_holder[.. current_data_length as usize].to_vec()
+            ));
         }
+        let _ = RegCloseKey(hkey);
         Ok(result)
     }
 }
 
+/// Enumerates the names of the subkeys of the given key
+fn enumerate_subkeys_of_hkey(root_hkey: HKEY, path: PCSTR) -> Result<Vec<String>> {
+    let mut hkey = HKEY::default();
+    let mut num_subkeys = 0;
+    let mut longest_subkey_length = 0;
+
+    unsafe {
+        RegOpenKeyExA(
+            root_hkey, path, 0, KEY_READ, &mut hkey
+        )?;
+        RegQueryInfoKeyA(
+            hkey, PSTR::null(), None, None, Some(&mut num_subkeys),
+            Some(&mut longest_subkey_length), None, None,
+            None, None, None, None
+        )?;
+
+        let mut result = Vec::with_capacity(num_subkeys as usize);
+        let mut name_holder = vec![0u8; longest_subkey_length as usize + 1];
+
+        for index in 0 .. num_subkeys {
+            let mut current_name_length = name_holder.len() as u32;
+            RegEnumKeyExA(
+                hkey, index, PSTR(name_holder.as_mut_ptr()),
+                &mut current_name_length, None, PSTR::null(), None, None
+            )?;
+            result.push(String::from_utf8_lossy(&name_holder[.. current_name_length as usize]).to_string());
+        }
+        let _ = RegCloseKey(hkey);
+        Ok(result)
+    }
+}
+
+fn decode_string_value(data: &[u8]) -> String {
+    let end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[.. end]).to_string()
+}
+
+/// Decodes a REG_MULTI_SZ value, which is a list of null-terminated strings
+fn decode_multi_string_value(data: &[u8]) -> Vec<String> {
+    data.split(|byte| *byte == 0).filter(|part| !part.is_empty()).map(
+        |part| String::from_utf8_lossy(part).to_string()
+    ).collect()
+}
+
+fn decode_dword_value(data: &[u8]) -> u32 {
+    match data.try_into() {
+        Ok(dword) => u32::from_le_bytes(dword),
+        // The loader only loads layers with a DWORD value of 0, so treat anything else like a 1
+        Err(_) => 1
+    }
+}
+
 fn enumerate_layers_of_hkey(root_hkey: HKEY, registry: ImplicitRegistry, errors: &mut Vec<String>) -> Result<Vec<ImplicitLayer>> {
-    let layer_paths = enumerate_keys_of_hkey(
+    let layer_paths = enumerate_values_of_hkey(
         root_hkey, s!("SOFTWARE\\Khronos\\Vulkan\\ImplicitLayers")
     )?;
 
     let mut result = Vec::with_capacity(layer_paths.len());
-    for settings_path in layer_paths {
+    for (settings_path, data) in layer_paths {
+        let first_new_layer = result.len();
         extract_layer_settings(&settings_path, registry, &mut result, errors);
+        for layer in &mut result[first_new_layer ..] {
+            layer.registry_value = decode_dword_value(&data);
+        }
     }
 
     Ok(result)
 }
 
-fn extract_layer_settings(path: &str, registry: ImplicitRegistry, dest: &mut Vec<ImplicitLayer>, errors: &mut Vec<String>) {
+/// Reads all layers of the given manifest, together with their index in the manifest
+fn read_layer_manifest(path: &str, errors: &mut Vec<String>) -> Vec<(usize, LayerSettings)> {
+    let mut result = Vec::new();
     if let Ok(file) = File::open(path) {
         let reader = BufReader::new(file);
         let layer_settings: serde_json::error::Result<RootLayerSettings> = serde_json::from_reader(reader);
@@ -98,94 +176,274 @@ fn extract_layer_settings(path: &str, registry: ImplicitRegistry, dest: &mut Vec
             Err(parse_error) => errors.push(format!("Failed to parse {}: {}", path, parse_error)),
             Ok(settings) => {
                 if let Some(layer) = settings.layer {
-                    extract_single_layer_settings(layer, path, registry, dest, errors);
+                    result.push((0, layer));
                 }
                 if let Some(layers) = settings.layers {
-                    for layer in layers {
-                        extract_single_layer_settings(layer, path, registry, dest, errors);
-                    }
+                    result.extend(layers.into_iter().enumerate());
                 }
             }
         };
     } else {
         errors.push(format!("Failed to open file {}", path));
     }
+    result
+}
+
+fn extract_layer_settings(path: &str, registry: ImplicitRegistry, dest: &mut Vec<ImplicitLayer>, errors: &mut Vec<String>) {
+    for (manifest_index, layer) in read_layer_manifest(path, errors) {
+        extract_single_layer_settings(layer, path, registry, manifest_index, dest, errors);
+    }
 }
 
-fn extract_single_layer_settings(layer: LayerSettings, settings_path: &str, registry: ImplicitRegistry, dest: &mut Vec<ImplicitLayer>, errors: &mut Vec<String>) {
+/// Finds the explicit layers in the registry. Unlike implicit layers, these are only loaded when
+/// an application (or `VK_INSTANCE_LAYERS`) asks for them.
+pub fn get_explicit_layers() -> (Vec<ExplicitLayer>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut layers = Vec::new();
+    for (root_hkey, registry) in [
+        (HKEY_CURRENT_USER, ImplicitRegistry::CurrentUser), (HKEY_LOCAL_MACHINE, ImplicitRegistry::LocalMachine)
+    ] {
+        // Most systems don't have any explicit layers, so a missing registry key is not an error
+        let layer_paths = enumerate_values_of_hkey(
+            root_hkey, s!("SOFTWARE\\Khronos\\Vulkan\\ExplicitLayers")
+        ).unwrap_or(vec![]);
+        for (settings_path, data) in layer_paths {
+            if decode_dword_value(&data) != 0 {
+                continue;
+            }
+            for (_, layer) in read_layer_manifest(&settings_path, &mut errors) {
+                layers.push(ExplicitLayer {
+                    settings_path: settings_path.clone(),
+                    registry,
+                    name: layer.name,
+                    description: layer.description
+                });
+            }
+        }
+    }
+    (layers, errors)
+}
+
+fn extract_single_layer_settings(
+    layer: LayerSettings, settings_path: &str, registry: ImplicitRegistry, manifest_index: usize,
+    dest: &mut Vec<ImplicitLayer>, errors: &mut Vec<String>
+) {
     let get_disable_environment = layer.disable_environment.keys().into_iter().next();
     if let Some(disable_environment) = get_disable_environment {
         let enable_environment = match layer.enable_environment {
-            Some(environment_map) => environment_map.keys().into_iter().next().map(|key| key.clone()),
+            Some(environment_map) => environment_map.into_iter().next(),
             None => None
         };
         dest.push(ImplicitLayer {
             settings_path: settings_path.to_string(),
             registry,
+            manifest_index,
+            registry_value: 0,
             name: layer.name,
             description: layer.description,
             disable_environment: disable_environment.clone(),
-            enable_environment
+            enable_environment,
+            shadowed_by: None,
+            library_path: layer.library_path,
+            implementation_version: layer.implementation_version,
+            api_version: layer.api_version,
+            meta_layer: if layer.component_layers.is_some() || layer.blacklisted_layers.is_some() {
+                Some(MetaLayer {
+                    component_layers: layer.component_layers.unwrap_or(vec![]),
+                    blacklisted_layers: layer.blacklisted_layers.unwrap_or(vec![])
+                })
+            } else { None }
         });
     } else {
         errors.push(format!("Layer {} has empty disable_environment", settings_path));
     }
 }
 
-fn spawn_remove_command(key: &str) -> bool {
-    Command::new("reg").args(["delete", "HKCU\\Environment", "/v", key, "/f"]).spawn().is_ok()
+const USER_ENVIRONMENT: &str = "HKCU\\Environment";
+const SYSTEM_ENVIRONMENT: &str = "HKLM\\SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment";
+
+/// Runs the command, waits until it finishes, and returns whether it succeeded
+fn run_command(command: &mut Command) -> bool {
+    command.status().is_ok_and(|status| status.success())
 }
-pub fn remove_user_environment(key: &str) -> bool {
-    if spawn_remove_command(key) {
+
+fn run_remove_command(scope: EnvironmentScope, key: &str) -> bool {
+    let environment = match scope {
+        EnvironmentScope::User => USER_ENVIRONMENT,
+        EnvironmentScope::System => SYSTEM_ENVIRONMENT
+    };
+    run_command(Command::new("reg").args(["delete", environment, "/v", key, "/f"]))
+}
+
+pub fn remove_environment(scope: EnvironmentScope, key: &str) -> bool {
+    if scope == EnvironmentScope::System && !has_admin_privileges() {
+        return false;
+    }
+    if run_remove_command(scope, key) {
 
         // Unlike the setx command, the reg delete command will NOT refresh the environment
         // variables. By setting and unsetting a dummy variable, I abuse setx the refresh the
         // environment variable I care about.
         let dummy = "force-refresh-dummy";
-        set_user_environment(dummy);
-        let _ = spawn_remove_command(dummy);
+        set_environment(scope, dummy);
+        let _ = run_remove_command(scope, dummy);
         true
     } else { false }
 }
 
+pub fn set_environment(scope: EnvironmentScope, key: &str) -> bool {
+    set_environment_value(scope, key, "1")
+}
+
+pub fn set_environment_value(scope: EnvironmentScope, key: &str, value: &str) -> bool {
+    match scope {
+        EnvironmentScope::User => run_command(Command::new("setx").args([key, value])),
+        EnvironmentScope::System => has_admin_privileges() &&
+            run_command(Command::new("setx").args([key, value, "/M"]))
+    }
+}
+
+/// Persistently disables all given layers, using the given method
+pub fn disable_layers(scope: EnvironmentScope, layers: &[&ImplicitLayer], method: DisableMethod) -> bool {
+    let env = get_global_environment_keys();
+    let persisted = match scope {
+        EnvironmentScope::User => &env.user,
+        EnvironmentScope::System => &env.system
+    };
+    let existing_filter = persisted.iter().find(
+        |(key, _)| key.eq_ignore_ascii_case(LOADER_LAYERS_DISABLE)
+    ).map(|(_, value)| value.as_str());
+
+    disable_variables(layers, method, existing_filter).iter().all(
+        |(key, value)| set_environment_value(scope, key, value)
+    )
+}
+
+pub fn remove_user_environment(key: &str) -> bool {
+    remove_environment(EnvironmentScope::User, key)
+}
+
 pub fn set_user_environment(key: &str) -> bool {
-    Command::new("setx").args([key, "1"]).spawn().is_ok()
+    set_environment(EnvironmentScope::User, key)
+}
+
+const USER_LOADER_SETTINGS: &str = "HKCU\\SOFTWARE\\Khronos\\Vulkan\\LoaderSettings";
+
+/// Finds the paths of the loader settings files that are registered in the registry, in the order
+/// that the loader checks them. Like implicit layers, the value of the path must be 0.
+pub fn get_loader_settings_paths() -> Vec<String> {
+    let mut paths = Vec::new();
+    for root_hkey in [HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE] {
+        if let Ok(values) = enumerate_values_of_hkey(root_hkey, s!("SOFTWARE\\Khronos\\Vulkan\\LoaderSettings")) {
+            for (path, data) in values {
+                if decode_dword_value(&data) == 0 {
+                    paths.push(path);
+                }
+            }
+        }
+    }
+    paths
+}
+
+const DISPLAY_ADAPTER_CLASS: &str = "SYSTEM\\CurrentControlSet\\Control\\Class\\{4d36e968-e325-11ce-bfc1-08002be10318}";
+
+/// Finds the driver manifests that are registered in the `Drivers` key (whose value must be 0,
+/// like implicit layers), and in the `VulkanDriverName` values of the display adapters
+pub fn get_driver_manifest_paths(errors: &mut Vec<String>) -> Vec<(String, DriverSource)> {
+    let mut manifests = Vec::new();
+    match enumerate_values_of_hkey(HKEY_LOCAL_MACHINE, s!("SOFTWARE\\Khronos\\Vulkan\\Drivers")) {
+        Ok(values) => for (path, data) in values {
+            if decode_dword_value(&data) == 0 {
+                manifests.push((path, DriverSource::Registry));
+            }
+        },
+        // Most modern drivers only register themselves in their display adapter key
+        Err(error) if error.code() == HRESULT::from_win32(ERROR_FILE_NOT_FOUND.0) => {},
+        Err(error) => errors.push(format!("Failed to read the Vulkan Drivers key: {}", error.message()))
+    }
+
+    let class_key = format!("{}\0", DISPLAY_ADAPTER_CLASS);
+    let adapters = match enumerate_subkeys_of_hkey(HKEY_LOCAL_MACHINE, PCSTR(class_key.as_ptr())) {
+        Ok(adapters) => adapters,
+        Err(error) => {
+            errors.push(format!("Failed to enumerate the display adapters: {}", error.message()));
+            vec![]
+        }
+    };
+    // The adapters are numbered like 0000 and 0001, and there is also a Properties key
+    for adapter in adapters.iter().filter(|adapter| adapter.chars().all(|character| character.is_ascii_digit())) {
+        let key = format!("{}\\{}\0", DISPLAY_ADAPTER_CLASS, adapter);
+        match enumerate_values_of_hkey(HKEY_LOCAL_MACHINE, PCSTR(key.as_ptr())) {
+            Ok(values) => for (_, data) in values.iter().filter(|(name, _)| name.eq_ignore_ascii_case("VulkanDriverName")) {
+                for path in decode_multi_string_value(data) {
+                    manifests.push((path, DriverSource::DisplayAdapter));
+                }
+            },
+            Err(error) => errors.push(format!("Failed to read display adapter {}: {}", adapter, error.message()))
+        }
+    }
+    manifests
+}
+
+pub fn register_loader_settings(path: &str) -> bool {
+    run_command(Command::new("reg").args([
+        "add", USER_LOADER_SETTINGS, "/v", path, "/t", "REG_DWORD", "/d", "0", "/f"
+    ]))
+}
+
+pub fn unregister_loader_settings(path: &str) -> bool {
+    run_command(Command::new("reg").args(["delete", USER_LOADER_SETTINGS, "/v", path, "/f"]))
+}
+
+/// Checks whether this process is allowed to modify the system environment variables, which is
+/// only possible when it runs with administrator privileges. Note that `setx /M` and `reg delete`
+/// would silently fail in the background without them.
+pub fn has_admin_privileges() -> bool {
+    open_key_for_writing(
+        HKEY_LOCAL_MACHINE,
+        s!("SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment")
+    ).is_ok()
+}
+
+fn open_key_for_writing(root_hkey: HKEY, path: PCSTR) -> Result<()> {
+    let mut hkey = HKEY::default();
+    unsafe {
+        RegOpenKeyExA(root_hkey, path, 0, KEY_SET_VALUE, &mut hkey)?;
+        RegCloseKey(hkey)?;
+    }
+    Ok(())
 }
 
 pub fn get_global_environment_keys() -> EnvironmentVariables {
     let mut errors = Vec::new();
-    let user_keys = enumerate_keys_of_hkey(
+    let user_values = enumerate_values_of_hkey(
         HKEY_CURRENT_USER, s!("Environment")
     );
-    let system_keys = enumerate_keys_of_hkey(
+    let system_values = enumerate_values_of_hkey(
         HKEY_LOCAL_MACHINE,
         s!("SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment")
     );
 
-    if let Err(user_error) = &user_keys {
+    if let Err(user_error) = &user_values {
         errors.push(user_error.message().to_string());
     }
-    if let Err(system_error) = &system_keys {
+    if let Err(system_error) = &system_values {
         errors.push(system_error.message().to_string());
     }
 
+    let decode = |values: Result<Vec<(String, Vec<u8>)>>| values.unwrap_or(vec![]).into_iter().map(
+        |(key, data)| (key, decode_string_value(&data))
+    ).collect();
+    let loader_settings = find_loader_settings(&mut errors);
     EnvironmentVariables {
-        user: user_keys.unwrap_or(vec![]),
-        system: system_keys.unwrap_or(vec![]),
+        process: vars().collect(),
+        user: decode(user_values),
+        system: decode(system_values),
+        loader_settings,
         errors,
     }
 }
 
-pub fn is_enabled(layer: &ImplicitLayer) -> bool {
-    if var(&layer.disable_environment).is_ok() {
-        return false;
-    }
-    if let Some(enable_env) = &layer.enable_environment {
-        return var(enable_env).is_ok();
-    }
-    true
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -203,14 +461,39 @@ mod tests {
             ImplicitLayer {
                 settings_path: "./single-layer-manifest.json".to_string(),
                 registry: ImplicitRegistry::LocalMachine,
+                manifest_index: 0,
+                registry_value: 0,
                 name: "VK_LAYER_LUNARG_overlay".to_string(),
                 description: "LunarG HUD layer".to_string(),
                 disable_environment: "DISABLE_LAYER_OVERLAY_1".to_string(),
-                enable_environment: Some("ENABLE_LAYER_OVERLAY_1".to_string())
+                enable_environment: Some(("ENABLE_LAYER_OVERLAY_1".to_string(), "1".to_string())),
+                shadowed_by: None,
+                library_path: Some("vkOverlayLayer.dll".to_string()),
+                implementation_version: Some("2".to_string()),
+                api_version: Some("1.0.5".to_string()),
+                meta_layer: None
             }
         ], layers);
     }
 
+    #[test]
+    fn test_parsing_meta_layer() {
+        let mut layers = Vec::new();
+        let mut errors = Vec::new();
+        extract_layer_settings(
+            "./meta-layer-manifest.json",
+            ImplicitRegistry::CurrentUser,
+            &mut layers, &mut errors
+        );
+        assert_eq!(Vec::<String>::new(), errors);
+        assert_eq!(1, layers.len());
+        assert_eq!("VK_LAYER_LUNARG_override", layers[0].name);
+        assert_eq!(Some(MetaLayer {
+            component_layers: vec!["VK_LAYER_KHRONOS_validation".to_string(), "VK_LAYER_LUNARG_overlay".to_string()],
+            blacklisted_layers: vec!["VK_LAYER_bad_overlay".to_string()]
+        }), layers[0].meta_layer);
+    }
+
     #[test]
     fn test_parsing_multiple_layers() {
         let mut layers = Vec::new();
@@ -225,11 +508,27 @@ mod tests {
             ImplicitLayer {
                 settings_path: "./multiple-layers-manifest.json".to_string(),
                 registry: ImplicitRegistry::CurrentUser,
+                manifest_index: 0,
+                registry_value: 0,
                 name: "VK_LAYER_LUNARG_overlay - multiple".to_string(),
                 description: "LunarG HUD layer".to_string(),
                 disable_environment: "DISABLE_LAYER_OVERLAY_1".to_string(),
-                enable_environment: None
+                enable_environment: None,
+                shadowed_by: None,
+                library_path: Some("vkOverlayLayer.dll".to_string()),
+                implementation_version: Some("2".to_string()),
+                api_version: Some("1.0.5".to_string()),
+                meta_layer: None
             }
         ], layers);
     }
+
+    #[test]
+    fn test_decode_multi_string_value() {
+        assert_eq!(
+            vec!["C:\\Windows\\nv-vk64.json".to_string(), "C:\\Windows\\other.json".to_string()],
+            decode_multi_string_value(b"C:\\Windows\\nv-vk64.json\0C:\\Windows\\other.json\0\0")
+        );
+        assert!(decode_multi_string_value(b"\0").is_empty());
+    }
 }
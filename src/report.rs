@@ -0,0 +1,585 @@
+use serde_json::{json, Value};
+use std::env::args;
+use std::process::exit;
+use crate::config::Config;
+use crate::definitions::*;
+use crate::drivers::enumerate_drivers;
+use crate::layer_state::effective_states;
+use crate::logic::{diagnose, diagnose_per_version, run_all_trials, run_baseline_trials, trial_step};
+use crate::meta_layers::include_meta_layers;
+use crate::preflight::{combine_findings, preflight_check, preflight_is_conclusive};
+use crate::registry::{get_global_environment_keys, get_implicit_layers};
+use crate::solver::find_fix;
+
+const DIAGNOSE_USAGE: &str = "Usage: vk-fixer diagnose [--json] [--loader-filter] [--clear-layer-variables] [--all-trials] [--keep <layer>]
+    [--require-instance-extension <name>] [--require-device-extension <name>] [--require-feature <name>]";
+
+/// Displays the name of the layer with the given ID, and includes its manifest path when
+/// multiple layers have the same name
+pub fn display_layer(layers: &[ImplicitLayer], id: &LayerId) -> String {
+    match layers.iter().find(|layer| &layer.id() == id) {
+        Some(layer) => if layers.iter().filter(|other| other.name == layer.name).count() > 1 {
+            format!("{} ({})", layer.name, layer.settings_path)
+        } else { layer.name.clone() },
+        None => id.settings_path.clone()
+    }
+}
+
+pub fn display_api_version(api_version: u32) -> String {
+    format!("{}.{}", ash::vk::api_version_major(api_version), ash::vk::api_version_minor(api_version))
+}
+
+/// Displays a list of Vulkan versions, like "1.0–1.2" or "1.0, 1.3"
+pub fn display_api_versions(api_versions: &[u32]) -> String {
+    if api_versions.len() > 1 && api_versions.windows(2).all(
+        |pair| ash::vk::api_version_minor(pair[1]) == ash::vk::api_version_minor(pair[0]) + 1
+    ) {
+        return format!(
+            "{}–{}", display_api_version(api_versions[0]), display_api_version(api_versions[api_versions.len() - 1])
+        );
+    }
+    api_versions.iter().map(|api_version| display_api_version(*api_version)).collect::<Vec<_>>().join(", ")
+}
+
+/// The name of the kind of conclusion, like "PartiallyBrokenLayer"
+pub fn conclusion_name(conclusion: &Conclusion) -> &'static str {
+    match conclusion {
+        Conclusion::Healthy => "Healthy",
+        Conclusion::WeirdHealthy => "WeirdHealthy",
+        Conclusion::Hopeless => "Hopeless",
+        Conclusion::WeirdBroken { .. } => "WeirdBroken",
+        Conclusion::Partial { .. } => "Partial",
+        Conclusion::BrokenLayer { .. } => "BrokenLayer",
+        Conclusion::PartiallyBrokenLayer { .. } => "PartiallyBrokenLayer",
+        Conclusion::SymmetricConflict { .. } => "SymmetricConflict",
+        Conclusion::AsymmetricConflict { .. } => "AsymmetricConflict",
+        Conclusion::ComplexConflict => "ComplexConflict",
+        Conclusion::HiddenExtensions { .. } => "HiddenExtensions",
+        Conclusion::Inconclusive { .. } => "Inconclusive",
+        Conclusion::MisconfiguredLayer { .. } => "MisconfiguredLayer"
+    }
+}
+
+pub fn describe_trial(trial: &TrialKind, layers: &[ImplicitLayer]) -> String {
+    match trial {
+        TrialKind::Default => "default trial (all layers)".to_string(),
+        TrialKind::Clean => "clean trial (no layers)".to_string(),
+        TrialKind::Exclude(layer) => format!("exclude trial of {}", display_layer(layers, layer)),
+        TrialKind::Isolation(layer) => format!("isolation trial of {}", display_layer(layers, layer)),
+        TrialKind::Extra(disabled_layers) if disabled_layers.is_empty() => "extra trial (all layers)".to_string(),
+        TrialKind::Extra(disabled_layers) => format!("extra trial without {}", disabled_layers.iter().map(
+            |layer| display_layer(layers, layer)
+        ).collect::<Vec<_>>().join(", "))
+    }
+}
+
+pub fn describe_trace_step(step: &TraceStep, layers: &[ImplicitLayer]) -> String {
+    match step {
+        TraceStep::Trial { trial, succeeded_versions, failed_versions } => {
+            let trial = describe_trial(trial, layers);
+            if failed_versions.is_empty() {
+                format!("{} succeeded on {}", trial, display_api_versions(succeeded_versions))
+            } else if succeeded_versions.is_empty() {
+                format!("{} failed on {}", trial, display_api_versions(failed_versions))
+            } else {
+                format!(
+                    "{} succeeded on {}, but failed on {}", trial,
+                    display_api_versions(succeeded_versions), display_api_versions(failed_versions)
+                )
+            }
+        },
+        TraceStep::UnsupportedVersions(api_versions) => format!(
+            "no trial succeeded on {}, so these versions are ignored", display_api_versions(api_versions)
+        ),
+        TraceStep::Note(note) => note.clone()
+    }
+}
+
+/// Describes each step of the reasoning, followed by the conclusion, like
+/// "clean trial (no layers) succeeded on 1.0–1.3", "isolation trial of X failed on 1.2", and
+/// "→ PartiallyBrokenLayer"
+pub fn describe_trace(finding: &Finding, layers: &[ImplicitLayer]) -> Vec<String> {
+    let mut lines: Vec<String> = finding.trace.iter().map(|step| describe_trace_step(step, layers)).collect();
+    lines.push(format!("→ {}", conclusion_name(&finding.conclusion)));
+    lines
+}
+
+/// The layers that need to be disabled to apply the solution. When a disabled layer is a
+/// component of a meta-layer, the meta-layer needs to be disabled as well.
+pub fn solution_layers<'a>(solution: &Solution, layers: &'a [ImplicitLayer]) -> Vec<&'a ImplicitLayer> {
+    let disabled_layers = layers.iter().filter(|layer| solution.disabled_layers.contains(&layer.id())).collect();
+    if solution.kept_layer.is_some() { disabled_layers } else { include_meta_layers(disabled_layers, layers) }
+}
+
+/// Whether applying the solution would disable any of the `kept_layers`
+pub fn disables_kept_layer(solution: &Solution, kept_layers: &[LayerId], layers: &[ImplicitLayer]) -> bool {
+    solution_layers(solution, layers).iter().any(|layer| kept_layers.contains(&layer.id()))
+}
+
+pub fn describe_solution(solution: &Solution, layers: &[ImplicitLayer]) -> String {
+    let names = |ids: &[LayerId]| ids.iter().map(|layer| display_layer(layers, layer)).collect::<Vec<_>>().join(", ");
+    if solution.disabled_layers.is_empty() {
+        return "Nothing needs to be disabled".to_string();
+    }
+    if let Some(kept_layer) = &solution.kept_layer {
+        return if solution.disabled_layers.len() + 1 == layers.len() {
+            format!("Disable all layers except {}", display_layer(layers, kept_layer))
+        } else {
+            format!("Disable {}, but keep {}", names(&solution.disabled_layers), display_layer(layers, kept_layer))
+        };
+    }
+    let meta_layers: Vec<LayerId> = solution_layers(solution, layers).into_iter().map(
+        |layer| layer.id()
+    ).filter(|layer| !solution.disabled_layers.contains(layer)).collect();
+    if meta_layers.is_empty() {
+        format!("Disable {}", names(&solution.disabled_layers))
+    } else {
+        format!("Disable {} and the meta-layers that pull it in ({})", names(&solution.disabled_layers), names(&meta_layers))
+    }
+}
+
+/// The layers that the conclusion is about
+pub fn conclusion_layers(conclusion: &Conclusion) -> Vec<LayerId> {
+    match conclusion {
+        Conclusion::WeirdBroken { important_layer, .. } => vec![important_layer.clone()],
+        Conclusion::BrokenLayer { layer } | Conclusion::PartiallyBrokenLayer { layer, .. } |
+        Conclusion::HiddenExtensions { layer, .. } | Conclusion::MisconfiguredLayer { layer, .. } => vec![layer.clone()],
+        Conclusion::SymmetricConflict { layers } => layers.clone(),
+        Conclusion::AsymmetricConflict { main_offender } => vec![main_offender.clone()],
+        _ => vec![]
+    }
+}
+
+/// Describes a problem that the pre-flight check found, like "its library X doesn't exist, so
+/// the loader can't load it"
+pub fn describe_layer_problem(problem: &LayerProblem) -> String {
+    match problem {
+        LayerProblem::MissingLibrary { library } => format!("its library {} doesn't exist, so the loader can't load it", library),
+        LayerProblem::WrongArchitecture { library, library_bits, expected_bits } => format!(
+            "its library {} is {}-bit, but the loader needs a {}-bit library, so it can't load it",
+            library, library_bits, expected_bits
+        ),
+        LayerProblem::OutdatedApiVersion { api_version, driver_version } => format!(
+            "it was written for Vulkan {}, but your driver supports Vulkan {}, so it may not handle newer features",
+            api_version, driver_version
+        ),
+        LayerProblem::DuplicateName { ignored_manifests } => format!(
+            "the loader ignores the other manifests with the same name ({}), which may belong to another version",
+            ignored_manifests.join(", ")
+        ),
+        LayerProblem::UnsatisfiableEnableEnvironment { key, value } => format!(
+            "it is only enabled when {} is {}, which can never happen, so it is never used", key, value
+        )
+    }
+}
+
+/// Builds the JSON report of a finding, which contains the conclusion, the layers it is about,
+/// the reasoning trace, and the solutions
+pub fn finding_report(finding: &Finding, layers: &[ImplicitLayer]) -> Value {
+    let api_versions = |api_versions: &[u32]| api_versions.iter().map(
+        |api_version| display_api_version(*api_version)
+    ).collect::<Vec<_>>();
+    let mut details = match &finding.conclusion {
+        Conclusion::WeirdBroken { exclude, .. } => json!({ "exclude": exclude }),
+        Conclusion::Partial { supported_versions } => json!({ "supported_versions": api_versions(supported_versions) }),
+        Conclusion::PartiallyBrokenLayer { broken_versions, .. } => json!({ "broken_versions": api_versions(broken_versions) }),
+        Conclusion::HiddenExtensions { instance_extensions, device_extensions, .. } => json!({
+            "instance_extensions": instance_extensions, "device_extensions": device_extensions
+        }),
+        Conclusion::Inconclusive { failed_trials } => json!({
+            "failed_trials": failed_trials.iter().map(|(trial, failed_versions)| json!({
+                "trial": describe_trial(trial, layers), "failed_versions": api_versions(failed_versions)
+            })).collect::<Vec<_>>()
+        }),
+        Conclusion::MisconfiguredLayer { problem, .. } => json!({
+            "problem": describe_layer_problem(problem), "certain": problem.is_certain()
+        }),
+        _ => json!({})
+    };
+    details["conclusion"] = json!(conclusion_name(&finding.conclusion));
+    details["layers"] = json!(conclusion_layers(&finding.conclusion).iter().map(
+        |layer| display_layer(layers, layer)
+    ).collect::<Vec<_>>());
+    details["trace"] = json!(describe_trace(finding, layers));
+    details["solutions"] = json!(finding.solutions.iter().map(
+        |solution| describe_solution(solution, layers)
+    ).collect::<Vec<_>>());
+    details
+}
+
+/// Describes what the fix solver did for the kept layers: the extra trials that it ran, followed
+/// by its outcome, like "→ Solved". The solutions themselves are in `fix.plan`.
+pub fn describe_fix(fix: &KeptLayersFix, layers: &[ImplicitLayer]) -> Vec<String> {
+    let mut lines: Vec<String> = fix.extra_trials.iter().map(|extra_trial| describe_trace_step(&trial_step(
+        TrialKind::Extra(extra_trial.disabled_layers.clone()), &extra_trial.results
+    ), layers)).collect();
+    lines.push(match &fix.plan {
+        FixPlan::Solved(_) => "→ Solved".to_string(),
+        FixPlan::NeedsTrials(planned_trials) if planned_trials.is_empty() => {
+            "→ Undecided: there are too many combinations of layers to check".to_string()
+        },
+        FixPlan::NeedsTrials(planned_trials) => format!(
+            "→ Undecided: {} more trials are needed, but I stopped after {} extra trials",
+            planned_trials.len(), fix.extra_trials.len()
+        ),
+        FixPlan::Impossible => "→ Impossible: the trials fail even when all other layers are disabled".to_string()
+    });
+    lines
+}
+
+/// Builds the JSON report of the fix solver
+pub fn fix_report(fix: &KeptLayersFix, layers: &[ImplicitLayer]) -> Value {
+    let names = |ids: &[LayerId]| ids.iter().map(|layer| display_layer(layers, layer)).collect::<Vec<_>>();
+    let (outcome, solutions, planned_trials) = match &fix.plan {
+        FixPlan::Solved(solutions) => ("Solved", solutions.iter().map(
+            |solution| describe_solution(solution, layers)
+        ).collect(), vec![]),
+        FixPlan::NeedsTrials(planned_trials) => ("NeedsTrials", vec![], planned_trials.iter().map(
+            |disabled_layers| names(disabled_layers)
+        ).collect()),
+        FixPlan::Impossible => ("Impossible", vec![], vec![])
+    };
+    json!({
+        "kept_layers": names(&fix.kept_layers),
+        "outcome": outcome,
+        "trace": describe_fix(fix, layers),
+        "solutions": solutions,
+        "planned_trials": planned_trials
+    })
+}
+
+/// Summarizes the conclusion of the finding and the layers that it is about, like "BrokenLayer: X"
+pub fn summarize_finding(finding: &Finding, layers: &[ImplicitLayer]) -> String {
+    let involved_layers = conclusion_layers(&finding.conclusion);
+    if involved_layers.is_empty() {
+        return conclusion_name(&finding.conclusion).to_string();
+    }
+    format!("{}: {}", conclusion_name(&finding.conclusion), involved_layers.iter().map(
+        |layer| display_layer(layers, layer)
+    ).collect::<Vec<_>>().join(", "))
+}
+
+/// Merges the findings of each Vulkan version (see `diagnose_per_version`) into a table with 1 row
+/// per distinct problem, which tells for which of Vulkan 1.0, 1.1, 1.2, and 1.3 it occurs.
+/// Healthy findings are left out.
+pub fn merge_per_version(per_version: &[(u32, Vec<Finding>)]) -> Vec<(Finding, [bool; 4])> {
+    let mut rows: Vec<(Finding, [bool; 4])> = Vec::new();
+    for (api_version, findings) in per_version {
+        let column = ALL_API_VERSIONS.iter().position(|candidate| candidate == api_version).unwrap_or(0);
+        for finding in findings.iter().filter(|finding| finding.conclusion != Conclusion::Healthy) {
+            match rows.iter_mut().find(|(row, _)| row.conclusion == finding.conclusion) {
+                Some((_, affected_versions)) => affected_versions[column] = true,
+                None => {
+                    let mut affected_versions = [false; 4];
+                    affected_versions[column] = true;
+                    rows.push((finding.clone(), affected_versions));
+                }
+            }
+        }
+    }
+    rows
+}
+
+/// Builds the JSON report of all findings, the findings of each Vulkan version, the skipped
+/// trials, and the fix for the kept layers (if the user wants to keep any)
+pub fn diagnosis_report(
+    findings: &[Finding], per_version: &[(u32, Vec<Finding>)], skipped_trials: &[TrialKind],
+    fix: Option<&KeptLayersFix>, layers: &[ImplicitLayer]
+) -> Value {
+    let mut report = json!({
+        "findings": findings.iter().map(|finding| finding_report(finding, layers)).collect::<Vec<_>>(),
+        "skipped_trials": skipped_trials.iter().map(|trial| describe_trial(trial, layers)).collect::<Vec<_>>(),
+        "per_version": per_version.iter().map(|(api_version, version_findings)| json!({
+            "api_version": display_api_version(*api_version),
+            "findings": version_findings.iter().map(|finding| finding_report(finding, layers)).collect::<Vec<_>>()
+        })).collect::<Vec<_>>()
+    });
+    if let Some(fix) = fix {
+        report["kept_layers_fix"] = fix_report(fix, layers);
+    }
+    report
+}
+
+/// Handles `vk-fixer diagnose`, which runs all trials on the enabled implicit layers, and prints
+/// the conclusion and the reasoning behind it (as JSON when `--json` is given)
+pub fn maybe_run_diagnose_command() {
+    let args = args().collect::<Vec<_>>();
+    if args.len() >= 2 && args[1] == "diagnose" {
+        let mut json_output = false;
+        let mut options = TrialOptions::default();
+        let mut requirement_args = Vec::new();
+        let (mut config, config_error) = Config::load();
+        let mut remaining = args[2 ..].iter();
+        while let Some(arg) = remaining.next() {
+            match arg.as_str() {
+                "--json" => json_output = true,
+                "--loader-filter" => options.method = DisableMethod::LoaderFilter,
+                "--clear-layer-variables" => options.clear_layer_variables = true,
+                "--all-trials" => options.exhaustive = true,
+                "--keep" => match remaining.next() {
+                    Some(name) => config.kept_layers.push(name.clone()),
+                    None => {
+                        eprintln!("Missing layer name after --keep\n{}", DIAGNOSE_USAGE);
+                        exit(2);
+                    }
+                },
+                _ => requirement_args.push(arg.clone())
+            }
+        }
+
+        options.requirements = config.requirements.clone();
+        match Requirements::parse_args(&requirement_args) {
+            Ok(requirements) => options.requirements.extend(requirements),
+            Err(error) => {
+                eprintln!("{}\n{}", error, DIAGNOSE_USAGE);
+                exit(2);
+            }
+        }
+        let requirement_errors = options.requirements.remove_invalid_names();
+        if !requirement_errors.is_empty() {
+            eprintln!("{}", requirement_errors.join("\n"));
+            exit(2);
+        }
+
+        let (all_layers, errors) = get_implicit_layers();
+        for error in config_error.iter().chain(&errors) {
+            eprintln!("{}", error);
+        }
+        let states = effective_states(&all_layers, &get_global_environment_keys());
+        let (drivers, _) = enumerate_drivers();
+        let preflight_findings = preflight_check(&all_layers, &states, &drivers);
+        let layers: Vec<ImplicitLayer> = all_layers.iter().zip(states).filter(
+            |(_, state)| state.enabled
+        ).map(|(layer, _)| layer.clone()).collect();
+
+        // When the pre-flight check already found the answer, the trials of the layers can't tell
+        // anything new
+        let layer_trials_skipped = preflight_is_conclusive(&preflight_findings, &layers);
+        let results = if layer_trials_skipped {
+            run_baseline_trials(&layers, &options)
+        } else { run_all_trials(&layers, &options) };
+        let findings = combine_findings(preflight_findings, diagnose(&results));
+        let per_version = diagnose_per_version(&results);
+        let kept_layers: Vec<LayerId> = layers.iter().filter(|layer| config.is_kept(layer)).map(|layer| layer.id()).collect();
+        let fix = if kept_layers.is_empty() || layer_trials_skipped {
+            None
+        } else { Some(find_fix(&layers, &results, &kept_layers, &options)) };
+        if json_output {
+            let mut report = diagnosis_report(&findings, &per_version, &results.skipped_trials, fix.as_ref(), &layers);
+            if layer_trials_skipped {
+                report["layer_trials_skipped"] = json!(true);
+            }
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        } else {
+            if layer_trials_skipped {
+                println!("The pre-flight check found that none of the layers can be loaded, so only the default and clean trials were run.");
+                println!();
+            }
+            for (index, finding) in findings.iter().enumerate() {
+                if index > 0 {
+                    println!();
+                }
+                println!("Conclusion: {}", conclusion_name(&finding.conclusion));
+                if let Conclusion::MisconfiguredLayer { problem, .. } = &finding.conclusion {
+                    println!("Problem: {}", describe_layer_problem(problem));
+                }
+                let involved_layers = conclusion_layers(&finding.conclusion);
+                if !involved_layers.is_empty() {
+                    println!("Layers: {}", involved_layers.iter().map(
+                        |layer| display_layer(&layers, layer)
+                    ).collect::<Vec<_>>().join(", "));
+                }
+                println!("Why:");
+                for line in describe_trace(finding, &layers) {
+                    println!("  {}", line);
+                }
+                if !finding.solutions.is_empty() {
+                    println!("Solutions (any of them):");
+                    for solution in &finding.solutions {
+                        if disables_kept_layer(solution, &kept_layers, &layers) {
+                            println!("  {} (disables a layer that you want to keep)", describe_solution(solution, &layers));
+                        } else {
+                            println!("  {}", describe_solution(solution, &layers));
+                        }
+                    }
+                }
+            }
+
+            if !results.skipped_trials.is_empty() {
+                println!();
+                println!("Skipped trials (use --all-trials to run them anyway):");
+                for trial in &results.skipped_trials {
+                    println!("  {}", describe_trial(trial, &layers));
+                }
+            }
+
+            if !per_version.is_empty() {
+                println!();
+                println!("Per Vulkan version:");
+            }
+            for (api_version, version_findings) in &per_version {
+                println!("  {}: {}", display_api_version(*api_version), version_findings.iter().map(
+                    |finding| summarize_finding(finding, &layers)
+                ).collect::<Vec<_>>().join("; "));
+            }
+
+            if let Some(fix) = &fix {
+                println!();
+                println!("Fix that keeps {}:", kept_layers.iter().map(
+                    |layer| display_layer(&layers, layer)
+                ).collect::<Vec<_>>().join(", "));
+                for line in describe_fix(fix, &layers) {
+                    println!("  {}", line);
+                }
+                if let FixPlan::Solved(solutions) = &fix.plan {
+                    println!("Solutions (any of them):");
+                    for solution in solutions {
+                        println!("  {}", describe_solution(solution, &layers));
+                    }
+                }
+            }
+        }
+        exit(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use super::*;
+
+    fn layer(name: &str) -> ImplicitLayer {
+        ImplicitLayer::test(name)
+    }
+
+    #[test]
+    fn test_display_api_versions() {
+        assert_eq!("1.0–1.3", display_api_versions(&ALL_API_VERSIONS));
+        assert_eq!("1.0, 1.2", display_api_versions(&[vk::API_VERSION_1_0, vk::API_VERSION_1_2]));
+        assert_eq!("1.2", display_api_versions(&[vk::API_VERSION_1_2]));
+        assert_eq!("", display_api_versions(&[]));
+    }
+
+    #[test]
+    fn test_describe_trace_and_report() {
+        let layers = [layer("VK_LAYER_old")];
+        let finding = Finding {
+            conclusion: Conclusion::PartiallyBrokenLayer { layer: layers[0].id(), broken_versions: vec![vk::API_VERSION_1_2] },
+            trace: vec![
+                TraceStep::Trial { trial: TrialKind::Clean, succeeded_versions: ALL_API_VERSIONS.to_vec(), failed_versions: vec![] },
+                TraceStep::Trial {
+                    trial: TrialKind::Isolation(layers[0].id()),
+                    succeeded_versions: vec![vk::API_VERSION_1_0, vk::API_VERSION_1_1, vk::API_VERSION_1_3],
+                    failed_versions: vec![vk::API_VERSION_1_2]
+                }
+            ],
+            solutions: vec![Solution::disable(&layers[0].id())]
+        };
+        let trace = vec![
+            "clean trial (no layers) succeeded on 1.0–1.3".to_string(),
+            "isolation trial of VK_LAYER_old succeeded on 1.0, 1.1, 1.3, but failed on 1.2".to_string(),
+            "→ PartiallyBrokenLayer".to_string()
+        ];
+        assert_eq!(trace, describe_trace(&finding, &layers));
+        assert_eq!("PartiallyBrokenLayer: VK_LAYER_old", summarize_finding(&finding, &layers));
+        let finding_json = json!({
+            "conclusion": "PartiallyBrokenLayer",
+            "layers": ["VK_LAYER_old"],
+            "broken_versions": ["1.2"],
+            "trace": trace,
+            "solutions": ["Disable VK_LAYER_old"]
+        });
+        assert_eq!(json!({
+            "findings": [finding_json],
+            "skipped_trials": ["exclude trial of VK_LAYER_old"],
+            "per_version": [{ "api_version": "1.2", "findings": [finding_json] }]
+        }), diagnosis_report(
+            std::slice::from_ref(&finding), &[(vk::API_VERSION_1_2, vec![finding.clone()])],
+            &[TrialKind::Exclude(layers[0].id())], None, &layers
+        ));
+    }
+
+    #[test]
+    fn test_misconfigured_layer_report() {
+        let layers = [layer("VK_LAYER_gone")];
+        let finding = Finding {
+            conclusion: Conclusion::MisconfiguredLayer {
+                layer: layers[0].id(), problem: LayerProblem::MissingLibrary { library: "C:\\gone.dll".to_string() }
+            },
+            trace: vec![TraceStep::Note("VK_LAYER_gone.json points to a library that doesn't exist".to_string())],
+            solutions: vec![Solution::disable(&layers[0].id())]
+        };
+        assert_eq!("MisconfiguredLayer: VK_LAYER_gone", summarize_finding(&finding, &layers));
+        assert_eq!(json!({
+            "conclusion": "MisconfiguredLayer",
+            "layers": ["VK_LAYER_gone"],
+            "problem": "its library C:\\gone.dll doesn't exist, so the loader can't load it",
+            "certain": true,
+            "trace": ["VK_LAYER_gone.json points to a library that doesn't exist", "→ MisconfiguredLayer"],
+            "solutions": ["Disable VK_LAYER_gone"]
+        }), finding_report(&finding, &layers));
+    }
+
+    #[test]
+    fn test_merge_per_version() {
+        let healthy = Finding::default();
+        let broken = Finding { conclusion: Conclusion::BrokenLayer { layer: layer("a").id() }, ..Default::default() };
+        let conflict = Finding { conclusion: Conclusion::ComplexConflict, ..Default::default() };
+        assert_eq!(vec![
+            (conflict.clone(), [true, false, false, true]),
+            (broken.clone(), [false, false, true, true])
+        ], merge_per_version(&[
+            (vk::API_VERSION_1_0, vec![conflict.clone()]),
+            (vk::API_VERSION_1_1, vec![healthy]),
+            (vk::API_VERSION_1_2, vec![broken.clone()]),
+            (vk::API_VERSION_1_3, vec![conflict, broken])
+        ]));
+    }
+
+    #[test]
+    fn test_describe_solution() {
+        let mut meta_layer = layer("VK_LAYER_meta");
+        meta_layer.meta_layer = Some(MetaLayer { component_layers: vec!["VK_LAYER_a".to_string()], blacklisted_layers: vec![] });
+        let layers = [layer("VK_LAYER_a"), layer("VK_LAYER_b"), layer("VK_LAYER_c"), meta_layer];
+
+        let disable_a = Solution::disable(&layers[0].id());
+        assert_eq!(
+            "Disable VK_LAYER_a and the meta-layers that pull it in (VK_LAYER_meta)",
+            describe_solution(&disable_a, &layers)
+        );
+        assert_eq!(2, solution_layers(&disable_a, &layers).len());
+        assert_eq!("Disable VK_LAYER_b", describe_solution(&Solution::disable(&layers[1].id()), &layers));
+
+        let keep_b = Solution { disabled_layers: vec![layers[0].id(), layers[2].id(), layers[3].id()], kept_layer: Some(layers[1].id()) };
+        assert_eq!("Disable all layers except VK_LAYER_b", describe_solution(&keep_b, &layers));
+        let keep_c = Solution { disabled_layers: vec![layers[1].id()], kept_layer: Some(layers[2].id()) };
+        assert_eq!("Disable VK_LAYER_b, but keep VK_LAYER_c", describe_solution(&keep_c, &layers));
+
+        assert!(disables_kept_layer(&disable_a, &[layers[3].id()], &layers));
+        assert!(!disables_kept_layer(&keep_c, &[layers[2].id()], &layers));
+        assert_eq!("Nothing needs to be disabled", describe_solution(
+            &Solution { disabled_layers: vec![], kept_layer: None }, &layers
+        ));
+    }
+
+    #[test]
+    fn test_describe_fix() {
+        let layers = [layer("VK_LAYER_a"), layer("VK_LAYER_b"), layer("VK_LAYER_obs")];
+        let fix = KeptLayersFix {
+            kept_layers: vec![layers[2].id()],
+            plan: FixPlan::Solved(vec![Solution::disable(&layers[0].id())]),
+            extra_trials: vec![ExtraTrial { disabled_layers: vec![layers[0].id()], results: Default::default() }]
+        };
+        assert_eq!(vec![
+            "extra trial without VK_LAYER_a succeeded on 1.0–1.3".to_string(),
+            "→ Solved".to_string()
+        ], describe_fix(&fix, &layers));
+        assert_eq!(json!({
+            "kept_layers": ["VK_LAYER_obs"],
+            "outcome": "Solved",
+            "trace": describe_fix(&fix, &layers),
+            "solutions": ["Disable VK_LAYER_a"],
+            "planned_trials": []
+        }), fix_report(&fix, &layers));
+    }
+}
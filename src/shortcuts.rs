@@ -0,0 +1,214 @@
+use std::env::{args, current_exe};
+use std::process::exit;
+use crate::definitions::{DisableMethod, ImplicitLayer};
+use crate::launcher::resolve_layers;
+use crate::layer_state::disable_variables;
+use crate::meta_layers::include_meta_layers;
+use crate::registry::get_implicit_layers;
+
+const SHORTCUTS_USAGE: &str = "Usage: vk-fixer shortcuts [--linux | --windows] [--loader-filter] [--game <executable>] --disable <layer>...";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TargetPlatform {
+    Windows,
+    Linux
+}
+
+impl TargetPlatform {
+    pub fn current() -> Self {
+        if cfg!(windows) { TargetPlatform::Windows } else { TargetPlatform::Linux }
+    }
+}
+
+/// The different ways to launch a game with some layers disabled, without disabling them globally
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Shortcuts {
+    /// Can be used as launch option in the Steam properties of a game
+    pub steam_launch_option: String,
+    /// A .bat script (Windows) or .sh script (Linux) that launches the game
+    pub launcher_script: String,
+    /// A .desktop entry that launches the game (Linux only)
+    pub desktop_entry: Option<String>
+}
+
+/// The path to the vk-fixer executable, for the shortcuts that launch games via `vk-fixer run`
+pub fn own_executable_path() -> String {
+    current_exe().map(|path| path.to_string_lossy().into_owned()).unwrap_or_else(|_| "vk-fixer".to_string())
+}
+
+/// Generates the shortcuts to launch `game` with all `layers` disabled using `method`. On Linux, the
+/// Steam launch option sets the environment variables directly. Since Steam on Windows can't set
+/// environment variables, the launch option uses `vk-fixer run` at `vk_fixer_path` instead.
+pub fn generate_shortcuts(
+    layers: &[&ImplicitLayer], game: &str, platform: TargetPlatform, method: DisableMethod, vk_fixer_path: &str
+) -> Shortcuts {
+    let variables = disable_variables(layers, method, None);
+    match platform {
+        TargetPlatform::Windows => {
+            let mut launch_option = format!("\"{}\" run", vk_fixer_path);
+            if method == DisableMethod::LoaderFilter {
+                launch_option += " --loader-filter";
+            }
+            launch_option += " --disable";
+            for layer in layers {
+                launch_option += &format!(" {}", layer.name);
+            }
+            launch_option += " -- %command%";
+
+            let mut script = "@echo off\r\n".to_string();
+            for (key, value) in &variables {
+                script += &format!("set {}={}\r\n", key, value);
+            }
+            script += &format!("\"{}\" %*\r\n", game);
+
+            Shortcuts { steam_launch_option: launch_option, launcher_script: script, desktop_entry: None }
+        },
+        TargetPlatform::Linux => {
+            let assignments: Vec<String> = variables.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+
+            let mut script = "#!/bin/sh\n".to_string();
+            for assignment in &assignments {
+                script += &format!("export {}\n", assignment);
+            }
+            script += &format!("exec {} \"$@\"\n", shell_quote(game));
+
+            let game_name = game.rsplit('/').next().unwrap_or(game);
+            let desktop_entry = format!(
+                "[Desktop Entry]\nType=Application\nName={} (vk-fixer)\nExec=env {} {}\nTerminal=false\n",
+                game_name, assignments.join(" "), desktop_quote(game)
+            );
+
+            Shortcuts {
+                steam_launch_option: format!("{} %command%", assignments.join(" ")),
+                launcher_script: script,
+                desktop_entry: Some(desktop_entry)
+            }
+        }
+    }
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn desktop_quote(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    for character in value.chars() {
+        if matches!(character, '"' | '`' | '$' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    format!("\"{}\"", escaped)
+}
+
+/// Handles `vk-fixer shortcuts ...`, which prints the shortcuts for the given layers
+pub fn maybe_print_shortcuts() {
+    let args = args().collect::<Vec<_>>();
+    if args.len() >= 2 && args[1] == "shortcuts" {
+        let mut platform = TargetPlatform::current();
+        let mut method = DisableMethod::DisableEnvironment;
+        let mut game = "<game>".to_string();
+        let mut layer_names = Vec::new();
+        let mut remaining = args[2 ..].iter();
+        while let Some(arg) = remaining.next() {
+            match arg.as_str() {
+                "--linux" => platform = TargetPlatform::Linux,
+                "--windows" => platform = TargetPlatform::Windows,
+                "--loader-filter" => method = DisableMethod::LoaderFilter,
+                "--game" => if let Some(next) = remaining.next() {
+                    game = next.clone();
+                },
+                "--disable" => {},
+                layer if !layer.starts_with("--") => layer_names.push(layer.to_string()),
+                unknown => {
+                    eprintln!("Unexpected argument {}\n{}", unknown, SHORTCUTS_USAGE);
+                    exit(2);
+                }
+            }
+        }
+
+        let (layers, _) = get_implicit_layers();
+        let disabled_layers = resolve_layers(&layer_names, &layers).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            exit(2);
+        });
+        let disabled_layers = include_meta_layers(disabled_layers, &layers);
+        let shortcuts = generate_shortcuts(&disabled_layers, &game, platform, method, &own_executable_path());
+        println!("Steam launch option:\n{}\n", shortcuts.steam_launch_option);
+        println!("Launcher script:\n{}", shortcuts.launcher_script);
+        if let Some(desktop_entry) = shortcuts.desktop_entry {
+            println!("Desktop entry:\n{}", desktop_entry);
+        }
+        exit(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str, key: &str) -> ImplicitLayer {
+        ImplicitLayer { disable_environment: key.to_string(), ..ImplicitLayer::test(name) }
+    }
+
+    #[test]
+    fn test_windows_shortcuts() {
+        let overlay = layer("VK_LAYER_overlay", "DISABLE_OVERLAY");
+        let capture = layer("VK_LAYER_capture", "DISABLE_CAPTURE");
+        let shortcuts = generate_shortcuts(
+            &[&overlay, &capture], "C:\\Games\\game.exe", TargetPlatform::Windows,
+            DisableMethod::DisableEnvironment, "C:\\Tools\\vk-fixer.exe"
+        );
+        assert_eq!(
+            "\"C:\\Tools\\vk-fixer.exe\" run --disable VK_LAYER_overlay VK_LAYER_capture -- %command%",
+            shortcuts.steam_launch_option
+        );
+        assert_eq!(
+            "@echo off\r\nset DISABLE_OVERLAY=1\r\nset DISABLE_CAPTURE=1\r\n\"C:\\Games\\game.exe\" %*\r\n",
+            shortcuts.launcher_script
+        );
+        assert_eq!(None, shortcuts.desktop_entry);
+    }
+
+    #[test]
+    fn test_linux_shortcuts() {
+        let overlay = layer("VK_LAYER_overlay", "DISABLE_OVERLAY");
+        let capture = layer("VK_LAYER_capture", "DISABLE_CAPTURE");
+        let shortcuts = generate_shortcuts(
+            &[&overlay, &capture], "/home/me/My Games/it's a game", TargetPlatform::Linux,
+            DisableMethod::DisableEnvironment, "vk-fixer"
+        );
+        assert_eq!("DISABLE_OVERLAY=1 DISABLE_CAPTURE=1 %command%", shortcuts.steam_launch_option);
+        assert_eq!(
+            "#!/bin/sh\nexport DISABLE_OVERLAY=1\nexport DISABLE_CAPTURE=1\nexec '/home/me/My Games/it'\\''s a game' \"$@\"\n",
+            shortcuts.launcher_script
+        );
+        assert_eq!(Some(
+            "[Desktop Entry]\nType=Application\nName=it's a game (vk-fixer)\n\
+            Exec=env DISABLE_OVERLAY=1 DISABLE_CAPTURE=1 \"/home/me/My Games/it's a game\"\nTerminal=false\n".to_string()
+        ), shortcuts.desktop_entry);
+    }
+
+    #[test]
+    fn test_loader_filter_shortcuts() {
+        let overlay = layer("VK_LAYER_overlay", "DISABLE_OVERLAY");
+        let capture = layer("VK_LAYER_capture", "DISABLE_CAPTURE");
+        let layers = [&overlay, &capture];
+
+        let shortcuts = generate_shortcuts(&layers, "game.exe", TargetPlatform::Windows, DisableMethod::LoaderFilter, "vk-fixer.exe");
+        assert_eq!(
+            "\"vk-fixer.exe\" run --loader-filter --disable VK_LAYER_overlay VK_LAYER_capture -- %command%",
+            shortcuts.steam_launch_option
+        );
+        assert_eq!(
+            "@echo off\r\nset VK_LOADER_LAYERS_DISABLE=VK_LAYER_overlay,VK_LAYER_capture\r\n\"game.exe\" %*\r\n",
+            shortcuts.launcher_script
+        );
+
+        let shortcuts = generate_shortcuts(&layers, "/usr/bin/game", TargetPlatform::Linux, DisableMethod::LoaderFilter, "vk-fixer");
+        assert_eq!("VK_LOADER_LAYERS_DISABLE=VK_LAYER_overlay,VK_LAYER_capture %command%", shortcuts.steam_launch_option);
+        assert!(shortcuts.launcher_script.contains("\nexport VK_LOADER_LAYERS_DISABLE=VK_LAYER_overlay,VK_LAYER_capture\n"));
+        assert!(shortcuts.desktop_entry.unwrap().contains("Exec=env VK_LOADER_LAYERS_DISABLE=VK_LAYER_overlay,VK_LAYER_capture "));
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+use crate::definitions::*;
+use crate::logic::{run_extra_trials, unsupported_versions};
+use crate::meta_layers::{include_component_layers, include_meta_layers};
+
+/// The maximum number of extra trials that `find_fix` runs at once
+const MAX_TRIALS_PER_ROUND: usize = 8;
+/// The maximum number of times that `find_fix` runs extra trials before giving up
+const MAX_ROUNDS: usize = 3;
+/// The maximum number of sets of disabled layers that `solve_fix` considers, which keeps it fast
+/// on computers with many implicit layers
+const MAX_CANDIDATES: usize = 10_000;
+
+/// What the trials tell about a set of enabled layers
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Evidence {
+    Good,
+    Bad,
+    Unknown
+}
+
+/// The indices of the layers that are disabled when `disabled` (and the meta-layers containing
+/// them) are disabled, in ascending order
+fn disabled_indices(layers: &[ImplicitLayer], disabled: Vec<&ImplicitLayer>) -> Vec<usize> {
+    let disabled = include_meta_layers(disabled, layers);
+    (0 .. layers.len()).filter(|index| disabled.contains(&&layers[*index])).collect()
+}
+
+/// The flags of the layers that are enabled in the isolation trial of `layers[index]`. A
+/// meta-layer pulls in its components, so its isolation trial tests them as well.
+fn isolated_layers(layers: &[ImplicitLayer], index: usize) -> Vec<bool> {
+    let enabled = include_component_layers(vec![&layers[index]], layers);
+    layers.iter().map(|layer| enabled.contains(&layer)).collect()
+}
+
+/// Converts the trials to (enabled layers, succeeded) pairs, where the enabled layers are given by
+/// a flag per layer. Later observations take precedence over earlier ones.
+fn observations(
+    layers: &[ImplicitLayer], results: &TestResults, extra_trials: &[ExtraTrial]
+) -> Vec<(Vec<bool>, bool)> {
+    let unsupported_versions = unsupported_versions(results);
+    let index_of = |id: &LayerId| layers.iter().position(|layer| &layer.id() == id);
+    let without = |disabled: &[usize]| (0 .. layers.len()).map(|index| !disabled.contains(&index)).collect::<Vec<_>>();
+
+    let mut observations = vec![
+        (vec![true; layers.len()], &results.default_result),
+        (vec![false; layers.len()], &results.clean_result)
+    ];
+    for (id, layer_results) in &results.exclude_results {
+        if let Some(index) = index_of(id) {
+            observations.push((without(&disabled_indices(layers, vec![&layers[index]])), layer_results));
+        }
+    }
+    for (id, layer_results) in &results.isolation_results {
+        if let Some(index) = index_of(id) {
+            observations.push((isolated_layers(layers, index), layer_results));
+        }
+    }
+    for trial in extra_trials {
+        let disabled = layers.iter().filter(|layer| trial.disabled_layers.contains(&layer.id())).collect();
+        observations.push((without(&disabled_indices(layers, disabled)), &trial.results));
+    }
+    observations.into_iter().map(
+        |(enabled, trial_results)| (enabled, trial_results.succeeded_except(&unsupported_versions))
+    ).collect()
+}
+
+/// Assumes that disabling more layers never causes new failures: a set of enabled layers is good
+/// when a trial with at least these layers succeeded, and bad when a trial with at most these
+/// layers failed. When the trials contradict each other, only a trial with exactly these layers
+/// is trusted.
+fn evaluate(enabled: &[bool], observations: &[(Vec<bool>, bool)]) -> Evidence {
+    let is_subset = |small: &[bool], large: &[bool]| small.iter().zip(large).all(|(a, b)| !a || *b);
+    if let Some((_, succeeded)) = observations.iter().rev().find(|(observed, _)| observed == enabled) {
+        return if *succeeded { Evidence::Good } else { Evidence::Bad };
+    }
+    let good = observations.iter().any(|(observed, succeeded)| *succeeded && is_subset(enabled, observed));
+    let bad = observations.iter().any(|(observed, succeeded)| !*succeeded && is_subset(observed, enabled));
+    match (good, bad) {
+        (true, false) => Evidence::Good,
+        (false, true) => Evidence::Bad,
+        _ => Evidence::Unknown
+    }
+}
+
+/// Advances `combination` (ascending positions in a list of `count` items) to the next
+/// combination of the same size in lexicographic order, or returns false when it was the last one
+fn next_combination(combination: &mut [usize], count: usize) -> bool {
+    let size = combination.len();
+    for position in (0 .. size).rev() {
+        if combination[position] < count - size + position {
+            combination[position] += 1;
+            for next in position + 1 .. size {
+                combination[next] = combination[next - 1] + 1;
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// Looks for the fewest layers to disable such that the trials succeed, without disabling any of
+/// the `kept_layers`. Disabling a layer also disables the meta-layers that contain it. When the
+/// trials don't tell whether the smallest candidates would work, the trials that would tell are
+/// planned instead. At most `MAX_CANDIDATES` candidates are considered: when the search needs
+/// more, the candidates that need trials so far are returned (which may be none).
+pub fn solve_fix(
+    layers: &[ImplicitLayer], results: &TestResults, extra_trials: &[ExtraTrial], kept_layers: &[LayerId]
+) -> FixPlan {
+    let observations = observations(layers, results, extra_trials);
+    let is_kept = |index: &usize| kept_layers.contains(&layers[*index].id());
+    let without = |disabled: &[usize]| (0 .. layers.len()).map(|index| !disabled.contains(&index)).collect::<Vec<_>>();
+    // The layers that can be disabled without also disabling a kept (meta-)layer
+    let free_layers: Vec<usize> = (0 .. layers.len()).filter(
+        |index| !disabled_indices(layers, vec![&layers[*index]]).iter().any(is_kept)
+    ).collect();
+
+    // Disabling more layers never hurts, so nothing helps when disabling all free layers doesn't
+    let all_free = disabled_indices(layers, free_layers.iter().map(|index| &layers[*index]).collect());
+    if evaluate(&without(&all_free), &observations) == Evidence::Bad {
+        return FixPlan::Impossible;
+    }
+
+    let mut seen: HashSet<Vec<usize>> = HashSet::new();
+    let mut candidates: Vec<(Vec<usize>, Evidence)> = Vec::new();
+    let mut smallest: Option<usize> = None;
+    'search: for size in 0 ..= free_layers.len() {
+        if smallest.is_some_and(|smallest| size > smallest) {
+            break;
+        }
+        let mut combination: Vec<usize> = (0 .. size).collect();
+        loop {
+            if seen.len() >= MAX_CANDIDATES {
+                break 'search;
+            }
+            let disabled = disabled_indices(layers, combination.iter().map(|position| &layers[free_layers[*position]]).collect());
+            if seen.insert(disabled.clone()) {
+                let evidence = evaluate(&without(&disabled), &observations);
+                if evidence != Evidence::Bad {
+                    smallest = Some(smallest.map_or(disabled.len(), |smallest| smallest.min(disabled.len())));
+                }
+                candidates.push((disabled, evidence));
+            }
+            if !next_combination(&mut combination, free_layers.len()) {
+                break;
+            }
+        }
+    }
+
+    let to_ids = |disabled: &Vec<usize>| disabled.iter().map(|index| layers[*index].id()).collect::<Vec<_>>();
+    let Some(smallest) = smallest else {
+        // The search was cut off before it found any candidate that may work
+        return FixPlan::NeedsTrials(vec![]);
+    };
+    let smallest_with = |evidence: Evidence| candidates.iter().filter(
+        |(disabled, candidate_evidence)| disabled.len() == smallest && *candidate_evidence == evidence
+    ).map(|(disabled, _)| to_ids(disabled)).collect::<Vec<_>>();
+
+    let solutions = smallest_with(Evidence::Good);
+    if solutions.is_empty() {
+        FixPlan::NeedsTrials(smallest_with(Evidence::Unknown))
+    } else {
+        FixPlan::Solved(solutions.into_iter().map(
+            |disabled_layers| Solution { disabled_layers, kept_layer: None }
+        ).collect())
+    }
+}
+
+/// Runs `solve_fix`, and runs the trials that it plans until it finds a solution, or until it has
+/// run `MAX_ROUNDS` rounds of extra trials
+pub fn find_fix(
+    layers: &[ImplicitLayer], results: &TestResults, kept_layers: &[LayerId], options: &TrialOptions
+) -> KeptLayersFix {
+    let mut extra_trials = Vec::new();
+    let mut plan = solve_fix(layers, results, &extra_trials, kept_layers);
+    for _ in 0 .. MAX_ROUNDS {
+        let FixPlan::NeedsTrials(planned_trials) = &plan else { break };
+        if planned_trials.is_empty() {
+            break;
+        }
+        let planned_trials = &planned_trials[.. planned_trials.len().min(MAX_TRIALS_PER_ROUND)];
+        extra_trials.extend(run_extra_trials(layers, planned_trials, options));
+        plan = solve_fix(layers, results, &extra_trials, kept_layers);
+    }
+    KeptLayersFix { kept_layers: kept_layers.to_vec(), plan, extra_trials }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str, components: &[&str]) -> ImplicitLayer {
+        ImplicitLayer {
+            meta_layer: if components.is_empty() { None } else { Some(MetaLayer {
+                component_layers: components.iter().map(|name| name.to_string()).collect(),
+                blacklisted_layers: vec![]
+            }) },
+            ..ImplicitLayer::test(name)
+        }
+    }
+
+    fn outcome(succeeded: bool) -> VersionedTrialResults {
+        let result = TrialResult { exit_code: if succeeded { 0 } else { 1 }, ..Default::default() };
+        VersionedTrialResults { vk10: result.clone(), vk11: result.clone(), vk12: result.clone(), vk13: result }
+    }
+
+    /// Simulates the trials of `run_all_trials`, where `works` tells whether a trial succeeds
+    /// with the given enabled layers
+    fn simulate(layers: &[ImplicitLayer], works: &dyn Fn(&[bool]) -> bool) -> TestResults {
+        let without = |layer: &ImplicitLayer| {
+            let disabled = disabled_indices(layers, vec![layer]);
+            (0 .. layers.len()).map(|index| !disabled.contains(&index)).collect::<Vec<_>>()
+        };
+        TestResults {
+            default_result: outcome(works(&vec![true; layers.len()])),
+            clean_result: outcome(works(&vec![false; layers.len()])),
+            exclude_results: layers.iter().map(|layer| (layer.id(), outcome(works(&without(layer))))).collect(),
+            isolation_results: layers.iter().enumerate().map(
+                |(index, layer)| (layer.id(), outcome(works(&isolated_layers(layers, index))))
+            ).collect(),
+            skipped_trials: vec![]
+        }
+    }
+
+    #[test]
+    fn test_solve_broken_layer() {
+        let layers = [layer("a", &[]), layer("b", &[]), layer("obs", &[])];
+        let results = simulate(&layers, &|enabled| !enabled[0]);
+        assert_eq!(
+            FixPlan::Solved(vec![Solution::disable(&layers[0].id())]),
+            solve_fix(&layers, &results, &[], &[layers[2].id()])
+        );
+        assert_eq!(FixPlan::Impossible, solve_fix(&layers, &results, &[], &[layers[0].id()]));
+
+        let healthy = simulate(&layers, &|_| true);
+        assert_eq!(
+            FixPlan::Solved(vec![Solution { disabled_layers: vec![], kept_layer: None }]),
+            solve_fix(&layers, &healthy, &[], &[])
+        );
+    }
+
+    #[test]
+    fn test_solve_complex_conflict_with_kept_layer() {
+        // Any 2 enabled layers conflict, so everything except the kept layer must be disabled
+        let layers = [layer("a", &[]), layer("b", &[]), layer("obs", &[])];
+        let results = simulate(&layers, &|enabled| enabled.iter().filter(|enabled| **enabled).count() <= 1);
+        assert_eq!(FixPlan::Solved(vec![Solution {
+            disabled_layers: vec![layers[0].id(), layers[1].id()], kept_layer: None
+        }]), solve_fix(&layers, &results, &[], &[layers[2].id()]));
+    }
+
+    #[test]
+    fn test_plan_extra_trials() {
+        // Only a and b conflict, but the standard trials can't tell whether disabling a alone is enough
+        let layers = [layer("a", &[]), layer("b", &[]), layer("c", &[]), layer("d", &[])];
+        let works = |enabled: &[bool]| !(enabled[0] && (enabled[1] || enabled[2]));
+        let results = simulate(&layers, &works);
+        let kept = [layers[1].id(), layers[2].id()];
+        assert_eq!(
+            FixPlan::Solved(vec![Solution::disable(&layers[0].id())]),
+            solve_fix(&layers, &results, &[], &kept)
+        );
+
+        let works = |enabled: &[bool]| enabled.iter().filter(|enabled| **enabled).count() <= 2;
+        let results = simulate(&layers, &works);
+        let plan = solve_fix(&layers, &results, &[], &[layers[0].id()]);
+        let expected_trials = vec![
+            vec![layers[1].id(), layers[2].id()],
+            vec![layers[1].id(), layers[3].id()],
+            vec![layers[2].id(), layers[3].id()]
+        ];
+        assert_eq!(FixPlan::NeedsTrials(expected_trials.clone()), plan);
+
+        let extra_trials: Vec<ExtraTrial> = expected_trials.iter().map(|disabled_layers| ExtraTrial {
+            disabled_layers: disabled_layers.clone(), results: outcome(true)
+        }).collect();
+        let FixPlan::Solved(solutions) = solve_fix(&layers, &results, &extra_trials, &[layers[0].id()]) else {
+            panic!("Expected a solution")
+        };
+        assert_eq!(3, solutions.len());
+    }
+
+    #[test]
+    fn test_solve_with_meta_layer() {
+        // Disabling component a requires disabling the meta-layer too, so the meta-layer can't be kept
+        let layers = [layer("a", &[]), layer("b", &[]), layer("meta", &["a", "b"])];
+        let results = simulate(&layers, &|enabled| !enabled[0]);
+        assert_eq!(FixPlan::Solved(vec![Solution {
+            disabled_layers: vec![layers[0].id(), layers[2].id()], kept_layer: None
+        }]), solve_fix(&layers, &results, &[], &[]));
+        assert_eq!(FixPlan::Impossible, solve_fix(&layers, &results, &[], &[layers[2].id()]));
+    }
+
+    #[test]
+    fn test_meta_layer_isolation_includes_components() {
+        // The isolation trial of the meta-layer fails because of its broken component, so keeping
+        // the component alone must not look like a solution
+        let layers = [layer("broken", &[]), layer("b", &[]), layer("meta", &["broken"])];
+        let results = simulate(&layers, &|enabled| !enabled[0]);
+        assert_eq!(
+            vec![true, false, true],
+            isolated_layers(&layers, 2)
+        );
+        assert_eq!(FixPlan::Solved(vec![Solution {
+            disabled_layers: vec![layers[0].id(), layers[2].id()], kept_layer: None
+        }]), solve_fix(&layers, &results, &[], &[layers[1].id()]));
+        assert_eq!(FixPlan::Impossible, solve_fix(&layers, &results, &[], &[layers[0].id()]));
+    }
+
+    #[test]
+    fn test_solve_many_layers() {
+        let layers: Vec<ImplicitLayer> = (0 .. 20).map(|index| layer(&format!("layer{}", index), &[])).collect();
+        // The kept layer is broken, so nothing helps
+        let results = simulate(&layers, &|enabled| !enabled[0]);
+        assert_eq!(FixPlan::Impossible, solve_fix(&layers, &results, &[], &[layers[0].id()]));
+
+        // Any 2 enabled layers conflict, but the standard trials can't tell, so every pair needs a trial
+        let results = simulate(&layers, &|enabled| enabled.iter().filter(|enabled| **enabled).count() <= 1);
+        let FixPlan::NeedsTrials(planned_trials) = solve_fix(&layers, &results, &[], &[layers[0].id()]) else {
+            panic!("Expected planned trials")
+        };
+        assert_eq!(19 * 18 / 2, planned_trials.len());
+    }
+}
@@ -1,16 +1,35 @@
+use ash::prelude::VkResult;
 use ash::vk;
+use std::ffi::{c_void, CStr, CString};
 use std::collections::HashMap;
 use std::default::Default;
 use std::env::args;
 use std::process::{exit, Command, Child, Stdio};
+use std::ptr::null_mut;
 use std::str::FromStr;
-use crate::definitions::{TrialResult, VersionedTrialResults};
+use std::time::{Duration, Instant};
+use crate::definitions::{Requirements, TrialCapabilities, TrialResult, TrialTimings, VersionedTrialResults};
+
+/// The test app reports the duration of each stage on its own line that starts with this prefix,
+/// followed by the stage name and the duration in microseconds
+const TIMING_PREFIX: &str = "vk-fixer-timing ";
+/// The test app reports each layer and extension that it sees on its own line that starts with
+/// one of these prefixes, followed by the name
+const INSTANCE_LAYER_PREFIX: &str = "vk-fixer-instance-layer ";
+const INSTANCE_EXTENSION_PREFIX: &str = "vk-fixer-instance-extension ";
+const DEVICE_EXTENSION_PREFIX: &str = "vk-fixer-device-extension ";
 
 pub fn maybe_run_test_app() {
     let args = args().collect::<Vec<_>>();
-    if args.len() == 3 && args[1] == "test-app" {
+    if args.len() >= 3 && args[1] == "test-app" {
         if let Ok(api_version) = u32::from_str(&args[2]) {
-            run_test_app(api_version);
+            match Requirements::parse_args(&args[3..]) {
+                Ok(requirements) => run_test_app(api_version, &requirements),
+                Err(message) => {
+                    print!("Invalid requirements: {}", message);
+                    exit(-21030);
+                }
+            }
             exit(0);
         }
     }
@@ -30,30 +49,38 @@ fn await_test_app(child: std::io::Result<Child>) -> TrialResult {
     match child.map(|t| t.wait_with_output()) {
         Err(weird) => TrialResult {
             exit_code: -21021,
-            output: format!("Failed to launch: {:?}", weird)
+            output: format!("Failed to launch: {:?}", weird),
+            timings: TrialTimings::default(),
+            capabilities: TrialCapabilities::default()
         },
         Ok(child_result) => {
             match child_result {
                 Ok(result) => {
+                    let raw_stdout = String::from_utf8(result.stdout.clone()).unwrap_or("Invalid stdout".to_string());
+                    let (stdout, timings, capabilities) = extract_reports(&raw_stdout);
                     let mut output = String::new();
-                    if result.stderr.is_empty() && !result.stdout.is_empty() {
-                        output = String::from_utf8(result.stdout.clone()).unwrap_or("Invalid stdout".to_string());
+                    if result.stderr.is_empty() && !stdout.is_empty() {
+                        output = stdout.clone();
                     }
-                    if !result.stderr.is_empty() && result.stdout.is_empty() {
+                    if !result.stderr.is_empty() && stdout.is_empty() {
                         output = String::from_utf8(result.stderr.clone()).unwrap_or("Invalid stderr".to_string());
                     }
-                    if !result.stderr.is_empty() && !result.stdout.is_empty() {
-                        output = String::from_utf8(result.stdout.clone()).unwrap_or("Invalid stdout".to_string()) +
+                    if !result.stderr.is_empty() && !stdout.is_empty() {
+                        output = stdout.clone() +
                             "stderr: " + &String::from_utf8(result.stderr.clone()).unwrap_or("Invalid stderr".to_string());
                     }
                     TrialResult {
                         exit_code: result.status.code().unwrap_or(-21022),
-                        output
+                        output,
+                        timings,
+                        capabilities
                     }
                 },
                 Err(no_result) => TrialResult {
                     exit_code: -21020,
-                    output: format!("Failed to get result: {:?}", no_result)
+                    output: format!("Failed to get result: {:?}", no_result),
+                    timings: TrialTimings::default(),
+                    capabilities: TrialCapabilities::default()
                 }
             }
         }
@@ -61,42 +88,251 @@ fn await_test_app(child: std::io::Result<Child>) -> TrialResult {
 
 }
 
-pub fn spawn_test_apps(envs: &[&str]) -> [std::io::Result<Child>; 4] {
+/// Splits the stdout of the test app into the timings and capabilities that it reported, and the
+/// remaining output
+fn extract_reports(stdout: &str) -> (String, TrialTimings, TrialCapabilities) {
+    let mut timings = TrialTimings::default();
+    let mut capabilities = TrialCapabilities::default();
+    let mut output = String::new();
+    for line in stdout.split_inclusive('\n') {
+        let capability_lists = [
+            (INSTANCE_LAYER_PREFIX, &mut capabilities.instance_layers),
+            (INSTANCE_EXTENSION_PREFIX, &mut capabilities.instance_extensions),
+            (DEVICE_EXTENSION_PREFIX, &mut capabilities.device_extensions)
+        ];
+        if let Some((name, list)) = capability_lists.into_iter().find_map(
+            |(prefix, list)| line.strip_prefix(prefix).map(|name| (name, list))
+        ) {
+            list.push(name.trim().to_string());
+            continue;
+        }
+
+        let timing = line.strip_prefix(TIMING_PREFIX).and_then(|timing| timing.trim().split_once(' ')).and_then(
+            |(stage, micros)| u64::from_str(micros).ok().map(|micros| (stage, Duration::from_micros(micros)))
+        );
+        match timing {
+            Some(("load_loader", duration)) => timings.load_loader = Some(duration),
+            Some(("create_instance", duration)) => timings.create_instance = Some(duration),
+            Some(("create_device", duration)) => timings.create_device = Some(duration),
+            _ => output += line
+        }
+    }
+    (output, timings, capabilities)
+}
+
+fn report_timing(stage: &str, start_time: Instant) {
+    println!("{}{} {}", TIMING_PREFIX, stage, start_time.elapsed().as_micros());
+}
+
+fn report_names<T>(prefix: &str, properties: VkResult<Vec<T>>, name: impl Fn(&T) -> Option<&CStr>) {
+    for property in properties.unwrap_or_default() {
+        if let Some(name) = name(&property) {
+            println!("{}{}", prefix, name.to_string_lossy());
+        }
+    }
+}
+
+/// Spawns the test app for each Vulkan API version, with the given extra environment variables,
+/// and without the `removed_envs`. The test apps fail when they can't enable the `requirements`.
+pub fn spawn_test_apps(
+    envs: &[(String, String)], removed_envs: &[&str], requirements: &Requirements
+) -> [std::io::Result<Child>; 4] {
     [
-        spawn_test_app(envs, vk::API_VERSION_1_0),
-        spawn_test_app(envs, vk::API_VERSION_1_1),
-        spawn_test_app(envs, vk::API_VERSION_1_2),
-        spawn_test_app(envs, vk::API_VERSION_1_3),
+        spawn_test_app(envs, removed_envs, requirements, vk::API_VERSION_1_0),
+        spawn_test_app(envs, removed_envs, requirements, vk::API_VERSION_1_1),
+        spawn_test_app(envs, removed_envs, requirements, vk::API_VERSION_1_2),
+        spawn_test_app(envs, removed_envs, requirements, vk::API_VERSION_1_3),
     ]
 }
 
-fn spawn_test_app(envs: &[&str], api_version: u32) -> std::io::Result<Child> {
+fn spawn_test_app(
+    envs: &[(String, String)], removed_envs: &[&str], requirements: &Requirements, api_version: u32
+) -> std::io::Result<Child> {
     let mut env_map: HashMap<&str, &str> = HashMap::new();
-    for key in envs {
-        env_map.insert(key, "1");
+    for (key, value) in envs {
+        env_map.insert(key, value);
     }
 
-    Command::new(
+    let mut command = Command::new(
         args().next().expect("First arg should be path to own exe file")
-    ).args(["test-app", &api_version.to_string()]).stdout(Stdio::piped()).stderr(Stdio::piped())
-        .envs(env_map).spawn()
+    );
+    for key in removed_envs {
+        command.env_remove(key);
+    }
+    command.args(["test-app", &api_version.to_string()]).args(requirements.to_args())
+        .stdout(Stdio::piped()).stderr(Stdio::piped()).envs(env_map).spawn()
+}
+
+/// The Vulkan feature names that don't follow from the camelCase of their Rust field names
+const IRREGULAR_FEATURE_NAMES: [(&str, &str); 4] = [
+    ("texture_compression_etc2", "textureCompressionETC2"),
+    ("texture_compression_astc_ldr", "textureCompressionASTC_LDR"),
+    ("texture_compression_bc", "textureCompressionBC"),
+    ("texture_compression_astc_hdr", "textureCompressionASTC_HDR")
+];
+
+/// Converts a Rust field name like `sparse_residency_image2_d` to the Vulkan feature name
+/// `sparseResidencyImage2D`
+fn feature_name(field: &str) -> String {
+    if let Some((_, name)) = IRREGULAR_FEATURE_NAMES.iter().find(|(irregular_field, _)| *irregular_field == field) {
+        return name.to_string();
+    }
+    let mut parts = field.split('_');
+    let mut name = parts.next().unwrap_or("").to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            name.push(first.to_ascii_uppercase());
+            name.extend(chars);
+        }
+    }
+    name
+}
+
+macro_rules! find_feature {
+    ($name:expr, $($features:expr, $minor:literal, [$($field:ident)*]);*) => {{
+        $($(if $name == feature_name(stringify!($field)) {
+            return Some((&mut $features.$field, $minor));
+        })*)*
+        None
+    }}
+}
+
+/// Whether the test app knows the device feature with the given Vulkan name
+pub fn is_known_feature(name: &str) -> bool {
+    DeviceFeatures::default().flag(name).is_some()
+}
+
+/// The device features of Vulkan 1.0 up to 1.3
+#[derive(Default)]
+struct DeviceFeatures {
+    core: vk::PhysicalDeviceFeatures,
+    vk11: vk::PhysicalDeviceVulkan11Features<'static>,
+    vk12: vk::PhysicalDeviceVulkan12Features<'static>,
+    vk13: vk::PhysicalDeviceVulkan13Features<'static>
+}
+
+impl DeviceFeatures {
+    /// Finds the flag of the feature with the given name (like `samplerAnisotropy`), and the minor
+    /// Vulkan version that is needed to query and enable it. Since `VkPhysicalDeviceVulkan11Features`
+    /// was added in Vulkan 1.2, the Vulkan 1.1 features also need Vulkan 1.2.
+    fn flag(&mut self, name: &str) -> Option<(&mut vk::Bool32, u32)> {
+        find_feature!(name,
+            self.core, 0, [
+                robust_buffer_access full_draw_index_uint32 image_cube_array independent_blend geometry_shader
+                tessellation_shader sample_rate_shading dual_src_blend logic_op multi_draw_indirect
+                draw_indirect_first_instance depth_clamp depth_bias_clamp fill_mode_non_solid depth_bounds
+                wide_lines large_points alpha_to_one multi_viewport sampler_anisotropy texture_compression_etc2
+                texture_compression_astc_ldr texture_compression_bc occlusion_query_precise
+                pipeline_statistics_query vertex_pipeline_stores_and_atomics fragment_stores_and_atomics
+                shader_tessellation_and_geometry_point_size shader_image_gather_extended
+                shader_storage_image_extended_formats shader_storage_image_multisample
+                shader_storage_image_read_without_format shader_storage_image_write_without_format
+                shader_uniform_buffer_array_dynamic_indexing shader_sampled_image_array_dynamic_indexing
+                shader_storage_buffer_array_dynamic_indexing shader_storage_image_array_dynamic_indexing
+                shader_clip_distance shader_cull_distance shader_float64 shader_int64 shader_int16
+                shader_resource_residency shader_resource_min_lod sparse_binding sparse_residency_buffer
+                sparse_residency_image2_d sparse_residency_image3_d sparse_residency2_samples
+                sparse_residency4_samples sparse_residency8_samples sparse_residency16_samples
+                sparse_residency_aliased variable_multisample_rate inherited_queries
+            ];
+            self.vk11, 2, [
+                storage_buffer16_bit_access uniform_and_storage_buffer16_bit_access storage_push_constant16
+                storage_input_output16 multiview multiview_geometry_shader multiview_tessellation_shader
+                variable_pointers_storage_buffer variable_pointers protected_memory sampler_ycbcr_conversion
+                shader_draw_parameters
+            ];
+            self.vk12, 2, [
+                sampler_mirror_clamp_to_edge draw_indirect_count storage_buffer8_bit_access
+                uniform_and_storage_buffer8_bit_access storage_push_constant8 shader_buffer_int64_atomics
+                shader_shared_int64_atomics shader_float16 shader_int8 descriptor_indexing
+                shader_input_attachment_array_dynamic_indexing shader_uniform_texel_buffer_array_dynamic_indexing
+                shader_storage_texel_buffer_array_dynamic_indexing shader_uniform_buffer_array_non_uniform_indexing
+                shader_sampled_image_array_non_uniform_indexing shader_storage_buffer_array_non_uniform_indexing
+                shader_storage_image_array_non_uniform_indexing shader_input_attachment_array_non_uniform_indexing
+                shader_uniform_texel_buffer_array_non_uniform_indexing
+                shader_storage_texel_buffer_array_non_uniform_indexing
+                descriptor_binding_uniform_buffer_update_after_bind descriptor_binding_sampled_image_update_after_bind
+                descriptor_binding_storage_image_update_after_bind descriptor_binding_storage_buffer_update_after_bind
+                descriptor_binding_uniform_texel_buffer_update_after_bind
+                descriptor_binding_storage_texel_buffer_update_after_bind
+                descriptor_binding_update_unused_while_pending descriptor_binding_partially_bound
+                descriptor_binding_variable_descriptor_count runtime_descriptor_array sampler_filter_minmax
+                scalar_block_layout imageless_framebuffer uniform_buffer_standard_layout
+                shader_subgroup_extended_types separate_depth_stencil_layouts host_query_reset timeline_semaphore
+                buffer_device_address buffer_device_address_capture_replay buffer_device_address_multi_device
+                vulkan_memory_model vulkan_memory_model_device_scope vulkan_memory_model_availability_visibility_chains
+                shader_output_viewport_index shader_output_layer subgroup_broadcast_dynamic_id
+            ];
+            self.vk13, 3, [
+                robust_image_access inline_uniform_block descriptor_binding_inline_uniform_block_update_after_bind
+                pipeline_creation_cache_control private_data shader_demote_to_helper_invocation
+                shader_terminate_invocation subgroup_size_control compute_full_subgroups synchronization2
+                texture_compression_astc_hdr shader_zero_initialize_workgroup_memory dynamic_rendering
+                shader_integer_dot_product maintenance4
+            ]
+        )
+    }
+
+    /// Chains the structures of the Vulkan 1.1 up to `minor_version` features behind `features2`.
+    /// The features must not be moved while `features2` is in use.
+    fn chain(&mut self, features2: &mut vk::PhysicalDeviceFeatures2, minor_version: u32) {
+        features2.features = self.core;
+        features2.p_next = &mut self.vk11 as *mut _ as *mut c_void;
+        self.vk11.p_next = &mut self.vk12 as *mut _ as *mut c_void;
+        self.vk12.p_next = if minor_version >= 3 { &mut self.vk13 as *mut _ as *mut c_void } else { null_mut() };
+        self.vk13.p_next = null_mut();
+    }
+}
+
+/// Checks that all `required` names are in `available`, and converts them to C strings
+fn check_extensions<T>(
+    required: &[String], available: VkResult<Vec<T>>, name: impl Fn(&T) -> Option<&CStr>, description: &str, exit_code: i32
+) -> Vec<CString> {
+    let available = available.unwrap_or_default();
+    required.iter().map(|required_name| {
+        if !available.iter().any(|property| name(property).is_some_and(|name| name.to_bytes() == required_name.as_bytes())) {
+            print!("Missing required {} {}", description, required_name);
+            exit(exit_code);
+        }
+        CString::new(required_name.as_str()).unwrap_or_default()
+    }).collect()
 }
 
-fn run_test_app(api_version: u32) {
+fn run_test_app(api_version: u32, requirements: &Requirements) {
     unsafe {
+        let start_time = Instant::now();
         let raw_entry = ash::Entry::load();
         if let Err(entry_error) = raw_entry {
             print!("Failed to load Entry: {:?}", entry_error);
             exit(-21023);
         }
         let entry = raw_entry.unwrap();
+        report_timing("load_loader", start_time);
+        report_names(
+            INSTANCE_LAYER_PREFIX, entry.enumerate_instance_layer_properties(),
+            |layer| layer.layer_name_as_c_str().ok()
+        );
+        report_names(
+            INSTANCE_EXTENSION_PREFIX, entry.enumerate_instance_extension_properties(None),
+            |extension| extension.extension_name_as_c_str().ok()
+        );
+
+        let instance_extensions = check_extensions(
+            &requirements.instance_extensions, entry.enumerate_instance_extension_properties(None),
+            |extension| extension.extension_name_as_c_str().ok(), "instance extension", -21027
+        );
+        let instance_extension_pointers = instance_extensions.iter().map(|name| name.as_ptr()).collect::<Vec<_>>();
 
         let mut app_info = vk::ApplicationInfo::default();
         app_info.api_version = api_version;
 
         let mut ci_instance = vk::InstanceCreateInfo::default();
         ci_instance.p_application_info = &app_info;
+        ci_instance.enabled_extension_count = instance_extension_pointers.len() as u32;
+        ci_instance.pp_enabled_extension_names = instance_extension_pointers.as_ptr();
 
+        let start_time = Instant::now();
         let raw_instance = entry
             .create_instance(&ci_instance, None);
         if let Err(instance_error) = raw_instance {
@@ -104,6 +340,7 @@ fn run_test_app(api_version: u32) {
             exit(-21024);
         }
         let instance = raw_instance.unwrap();
+        report_timing("create_instance", start_time);
 
         let raw_physical_device = instance
             .enumerate_physical_devices();
@@ -112,6 +349,47 @@ fn run_test_app(api_version: u32) {
             exit(-21025);
         }
         let physical_device = raw_physical_device.unwrap()[0];
+        report_names(
+            DEVICE_EXTENSION_PREFIX, instance.enumerate_device_extension_properties(physical_device),
+            |extension| extension.extension_name_as_c_str().ok()
+        );
+        let device_extensions = check_extensions(
+            &requirements.device_extensions, instance.enumerate_device_extension_properties(physical_device),
+            |extension| extension.extension_name_as_c_str().ok(), "device extension", -21028
+        );
+        let device_extension_pointers = device_extensions.iter().map(|name| name.as_ptr()).collect::<Vec<_>>();
+
+        let device_version = instance.get_physical_device_properties(physical_device).api_version;
+        let minor_version = vk::api_version_minor(api_version.min(device_version));
+        let mut supported_features = DeviceFeatures::default();
+        if minor_version >= 2 {
+            let mut features2 = vk::PhysicalDeviceFeatures2::default();
+            supported_features.chain(&mut features2, minor_version);
+            instance.get_physical_device_features2(physical_device, &mut features2);
+            supported_features.core = features2.features;
+        } else {
+            supported_features.core = instance.get_physical_device_features(physical_device);
+        }
+
+        let mut required_features = DeviceFeatures::default();
+        for feature in &requirements.features {
+            let supported = match supported_features.flag(feature) {
+                None => {
+                    print!("Unknown required feature {}", feature);
+                    exit(-21029);
+                },
+                Some((_, required_minor)) if required_minor > minor_version => {
+                    print!("Required feature {} needs Vulkan 1.{}", feature, required_minor);
+                    exit(-21029);
+                },
+                Some((supported, _)) => *supported
+            };
+            if supported != vk::TRUE {
+                print!("Missing required feature {}", feature);
+                exit(-21029);
+            }
+            *required_features.flag(feature).unwrap().0 = vk::TRUE;
+        }
 
         let queue_priorities = 1.0;
 
@@ -123,7 +401,17 @@ fn run_test_app(api_version: u32) {
         let mut ci_device = vk::DeviceCreateInfo::default();
         ci_device.p_queue_create_infos = &queue_info;
         ci_device.queue_create_info_count = 1;
+        ci_device.enabled_extension_count = device_extension_pointers.len() as u32;
+        ci_device.pp_enabled_extension_names = device_extension_pointers.as_ptr();
+        let mut features2 = vk::PhysicalDeviceFeatures2::default();
+        if minor_version >= 2 {
+            required_features.chain(&mut features2, minor_version);
+            ci_device.p_next = &features2 as *const _ as *const c_void;
+        } else {
+            ci_device.p_enabled_features = &required_features.core;
+        }
 
+        let start_time = Instant::now();
         let raw_device = instance
             .create_device(physical_device, &ci_device, None);
         if let Err(device_error) = raw_device {
@@ -131,8 +419,56 @@ fn run_test_app(api_version: u32) {
             exit(-21026);
         }
         let device = raw_device.unwrap();
+        report_timing("create_device", start_time);
 
         device.destroy_device(None);
         instance.destroy_instance(None);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_reports() {
+        let (output, timings, capabilities) = extract_reports(
+            "vk-fixer-timing load_loader 1500\nvk-fixer-instance-layer VK_LAYER_overlay\n\
+            vk-fixer-instance-extension VK_KHR_surface\r\nvk-fixer-instance-extension VK_KHR_win32_surface\n\
+            vk-fixer-timing create_instance 20000\nFailed to enumerate physical devices"
+        );
+        assert_eq!("Failed to enumerate physical devices", output);
+        assert_eq!(TrialTimings {
+            load_loader: Some(Duration::from_micros(1500)),
+            create_instance: Some(Duration::from_micros(20000)),
+            create_device: None
+        }, timings);
+        assert_eq!(None, timings.creation_time());
+        assert_eq!(TrialCapabilities {
+            instance_layers: vec!["VK_LAYER_overlay".to_string()],
+            instance_extensions: vec!["VK_KHR_surface".to_string(), "VK_KHR_win32_surface".to_string()],
+            device_extensions: vec![]
+        }, capabilities);
+    }
+
+    #[test]
+    fn test_feature_flags() {
+        assert_eq!("sparseResidencyImage2D", feature_name("sparse_residency_image2_d"));
+        assert_eq!("storageBuffer16BitAccess", feature_name("storage_buffer16_bit_access"));
+
+        let mut features = DeviceFeatures::default();
+        *features.flag("samplerAnisotropy").unwrap().0 = vk::TRUE;
+        assert_eq!(vk::TRUE, features.core.sampler_anisotropy);
+        assert_eq!(2, features.flag("multiview").unwrap().1);
+        assert_eq!(3, features.flag("dynamicRendering").unwrap().1);
+        assert!(features.flag("sampler_anisotropy").is_none());
+
+        for (_, name) in IRREGULAR_FEATURE_NAMES {
+            assert!(is_known_feature(name), "{}", name);
+        }
+        *features.flag("textureCompressionASTC_LDR").unwrap().0 = vk::TRUE;
+        assert_eq!(vk::TRUE, features.core.texture_compression_astc_ldr);
+        assert_eq!(3, features.flag("textureCompressionASTC_HDR").unwrap().1);
+        assert!(!is_known_feature("textureCompressionEtc2"));
+    }
+}